@@ -32,12 +32,9 @@ async fn main() -> anyhow::Result<()> {
     for (i, m) in result.example_matches.iter().take(3).enumerate() {
         println!("\n--- Example Match {} ---", i + 1);
         println!("File: {}", m.file_path.display());
-        println!("Line {}: {}", m.line_number, m.line_content);
-        if !m.context_before.is_empty() {
-            println!("Context before: {:?}", m.context_before);
-        }
-        if !m.context_after.is_empty() {
-            println!("Context after: {:?}", m.context_after);
+        for line in &m.context {
+            let marker = if line.is_match { ">" } else { " " };
+            println!("{} {:>4} | {}", marker, line.line_number, line.content);
         }
     }
     
@@ -1,15 +1,16 @@
 use anyhow::Result;
 use eg_mcp::EgMcpServer;
 use rmcp::{ServiceExt, transport::stdio};
-use tracing_subscriber::{self, EnvFilter};
+use tracing_subscriber::{self, EnvFilter, fmt::format::FmtSpan};
 
 /// MCP server for the eg library
 /// Usage: eg-mcp
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
+    // Initialize tracing; closed spans report their duration, so `RUST_LOG=eg=debug` shows a phase timeline
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()))
+        .with_span_events(FmtSpan::CLOSE)
         .with_writer(std::io::stderr)
         .with_ansi(false)
         .init();
@@ -29,7 +30,8 @@ async fn main() -> Result<()> {
 }
 
 mod eg_mcp {
-    use eg::Eg;
+    use eg::{Eg, OutputFormat};
+    use eg::rust::{CrateSearcher, MatchMode};
     use rmcp::{
         ErrorData as McpError, RoleServer, ServerHandler,
         handler::server::{router::tool::ToolRouter, tool::Parameters},
@@ -47,12 +49,20 @@ mod eg_mcp {
         pub crate_name: String,
         /// Optional search pattern (regex)
         pub pattern: Option<String>,
+        /// Output format: "json" (default), "hunk" for unified-diff-style match context, "markdown", or "ndjson" for one JSON match per line
+        pub format: Option<String>,
+        /// Report absolute file paths instead of crate-relative ones (default false)
+        pub absolute_paths: Option<bool>,
+        /// Cut each snippet to at most this many lines around the match, marking the cuts
+        pub max_snippet_lines: Option<usize>,
     }
 
     #[derive(Debug, Deserialize, schemars::JsonSchema)]
     pub struct GetCrateSourceRequest {
         /// Name of the crate
         pub crate_name: String,
+        /// Also list the crate's example files with their sizes (default false)
+        pub include_listing: Option<bool>,
     }
 
     #[derive(Clone)]
@@ -71,9 +81,21 @@ mod eg_mcp {
         #[tool(description = "Search for patterns in Rust crate examples and source code")]
         async fn search_crate_examples(
             &self,
-            Parameters(SearchCrateExamplesRequest { crate_name, pattern }): Parameters<SearchCrateExamplesRequest>,
+            Parameters(SearchCrateExamplesRequest { crate_name, pattern, format, absolute_paths, max_snippet_lines }): Parameters<SearchCrateExamplesRequest>,
         ) -> Result<CallToolResult, McpError> {
-            let mut search = Eg::rust_crate(&crate_name);
+            let format = match format.as_deref() {
+                None | Some("json") => OutputFormat::Json,
+                Some("hunk") => OutputFormat::Hunk,
+                Some("markdown") => OutputFormat::Markdown,
+                Some("ndjson") => OutputFormat::JsonLines,
+                Some(other) => {
+                    let error_msg = format!("Unknown output format: {}", other);
+                    return Err(McpError::invalid_params(error_msg, None));
+                }
+            };
+
+            let mut search = Eg::rust_crate(&crate_name)
+                .absolute_paths(absolute_paths.unwrap_or(false));
             
             if let Some(pattern) = pattern {
                 search = search.pattern(&pattern).map_err(|e| {
@@ -83,8 +105,12 @@ mod eg_mcp {
             }
 
             match search.search().await {
-                Ok(result) => {
-                    let response = serde_json::to_string_pretty(&result).unwrap();
+                Ok(mut result) => {
+                    if let Some(max_lines) = max_snippet_lines {
+                        result.limit_snippets(max_lines);
+                    }
+                    // A parseable summary first, so the model gets the gist before the details
+                    let response = format!("{}\n{}", result.summary_line(), render(&result, format));
                     Ok(CallToolResult::success(vec![Content::text(response)]))
                 }
                 Err(e) => {
@@ -103,17 +129,34 @@ mod eg_mcp {
         #[tool(description = "Get the full path to an extracted crate for detailed exploration")]
         async fn get_crate_source(
             &self,
-            Parameters(GetCrateSourceRequest { crate_name }): Parameters<GetCrateSourceRequest>,
+            Parameters(GetCrateSourceRequest { crate_name, include_listing }): Parameters<GetCrateSourceRequest>,
         ) -> Result<CallToolResult, McpError> {
-            match Eg::rust_crate(&crate_name).search().await {
-                Ok(result) => {
-                    let response = json!({
+            match Eg::rust_crate(&crate_name).checkout_only().await {
+                Ok((version, checkout_path)) => {
+                    // With no patterns the walk reads nothing, it only counts files
+                    let stats = CrateSearcher::new()
+                        .search_crate_patterns(&checkout_path, &[], MatchMode::default(), 0)
+                        .map(|matches| matches.stats)
+                        .unwrap_or_default();
+                    let mut response = json!({
                         "crate_name": crate_name,
-                        "version": result.version,
-                        "checkout_path": result.checkout_path.to_string_lossy(),
-                        "message": format!("Crate {} v{} extracted to {}", 
-                                         crate_name, result.version, result.checkout_path.display())
+                        "version": version,
+                        "checkout_path": checkout_path.to_string_lossy(),
+                        "stats": stats,
+                        "message": format!("Crate {} v{} extracted to {} ({} files, {} examples)",
+                                         crate_name, version, checkout_path.display(),
+                                         stats.files_seen, stats.example_files)
                     });
+
+                    // Saves the agent a directory listing call to see what examples exist
+                    if include_listing.unwrap_or(false) {
+                        let listing = CrateSearcher::new().example_listing(&checkout_path).unwrap_or_default();
+                        response["examples"] = listing
+                            .into_iter()
+                            .map(|(path, bytes)| json!({ "path": path.to_string_lossy(), "bytes": bytes }))
+                            .collect();
+                    }
+
                     Ok(CallToolResult::success(vec![Content::text(response.to_string())]))
                 }
                 Err(e) => {
@@ -155,4 +198,20 @@ mod eg_mcp {
             Ok(self.get_info())
         }
     }
+
+    /// Render a search result for the model, with hunks in one `## path` section per file
+    fn render(result: &eg::SearchResult, format: OutputFormat) -> String {
+        if format != OutputFormat::Hunk {
+            return result.render(format);
+        }
+
+        result.by_file()
+            .iter()
+            .map(|file| {
+                let hunks: Vec<String> = file.matches.iter().map(|m| m.to_hunk()).collect();
+                format!("## {}\n\n{}", file.path.display(), hunks.join("\n"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
@@ -1,5 +1,6 @@
 //! Error types for the eg library
 
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// Result type alias for eg operations
@@ -23,9 +24,36 @@ pub enum EgError {
     /// Failed to download crate from registry
     #[error("Download error: {0}")]
     DownloadError(#[from] reqwest::Error),
+    /// A download was answered with an unsuccessful HTTP status
+    #[error("Download error: HTTP {status}")]
+    DownloadHttpStatus {
+        status: u16,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// The registry has no archive for this crate version
+    #[error("Version {version} of '{crate_name}' not found")]
+    VersionNotFound { crate_name: String, version: String },
     /// Failed to extract or process crate archive
     #[error("Extraction error: {0}")]
     ExtractionError(String),
+    /// Failed to read or unpack a crate archive, with the underlying I/O error as the source
+    #[error("Extraction error: {context}: {source}")]
+    ArchiveError {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// Failed to read a file while searching
+    #[error("Failed to read file {}: {source}", .path.display())]
+    ReadError {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A crates.io API request failed
+    #[error("Registry error: {0}")]
+    RegistryError(#[from] crates_io_api::Error),
     /// I/O error
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
@@ -35,6 +63,24 @@ pub enum EgError {
     /// No matching versions found
     #[error("No versions of '{crate_name}' match constraint '{constraint}'")]
     NoMatchingVersions { crate_name: String, constraint: String },
+    /// The configured user-agent can't be sent as an HTTP header
+    #[error("Invalid user-agent: {0}")]
+    InvalidUserAgent(#[from] reqwest::header::InvalidHeaderValue),
+    /// An external command, such as `cargo check`, could not be run
+    #[error("Failed to run {command}: {source}")]
+    CommandFailed {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A background task panicked or was aborted before finishing
+    #[error("Task failed: {0}")]
+    TaskFailed(#[from] tokio::task::JoinError),
+    /// The search's cancellation token fired before it finished
+    ///
+    /// Returned by whichever phase was running, never retried, and never wrapped in `Other`.
+    #[error("Search cancelled")]
+    Cancelled,
     /// Other error
     #[error("Error: {0}")]
     Other(String),
@@ -20,13 +20,31 @@
 //! }
 //! ```
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
 
 pub mod rust;
 pub mod error;
+pub mod metrics;
 
 pub use error::{EgError, Result};
 
+/// User-agent sent to crates.io when none is configured
+const DEFAULT_USER_AGENT: &str = "eg-library (https://github.com/socratic-shell/eg)";
+
+/// Process-wide user-agent override, see `Eg::set_user_agent`
+static USER_AGENT: RwLock<Option<String>> = RwLock::new(None);
+
+/// The user-agent to send when a request has no explicit one configured
+pub(crate) fn default_user_agent() -> String {
+    USER_AGENT
+        .read()
+        .ok()
+        .and_then(|ua| ua.clone())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string())
+}
+
 /// Main entry point for example searches
 pub struct Eg;
 
@@ -35,11 +53,91 @@ impl Eg {
     pub fn rust_crate(name: &str) -> rust::RustCrateSearch {
         rust::RustCrateSearch::new(name)
     }
+
+    /// Search a local `.crate` archive, e.g. `foo-1.2.3.crate`, without contacting any registry
+    pub fn rust_crate_file(path: &Path) -> rust::RustCrateSearch {
+        rust::RustCrateSearch::from_crate_file(path)
+    }
+
+    /// Search examples across the most downloaded crates whose names match a glob, e.g. `tokio-*`
+    pub fn rust_crates_matching(name_glob: &str) -> rust::RustCratesMatching {
+        rust::RustCratesMatching::new(name_glob)
+    }
+
+    /// Search the crate being developed in the current directory, with no name or registry involved
+    pub fn current_crate() -> rust::RustCrateSearch {
+        rust::RustCrateSearch::current_crate()
+    }
+
+    /// Search an already-extracted source tree, e.g. an unpacked tarball or a CI cache
+    pub fn rust_dir(root: &Path) -> rust::RustCrateSearch {
+        rust::RustCrateSearch::from_dir(root)
+    }
+
+    /// Set the user-agent sent with every registry request in this process
+    ///
+    /// crates.io asks clients to identify themselves, so tools embedding eg should set their own
+    /// name and contact here. A `user_agent` set on an individual search takes precedence.
+    pub fn set_user_agent(user_agent: &str) {
+        if let Ok(mut ua) = USER_AGENT.write() {
+            *ua = Some(user_agent.to_string());
+        }
+    }
+
+    /// Limit how many `.crate` archives are downloaded at once across this process (default 8)
+    ///
+    /// Further downloads queue until one finishes, so bursts of searches, e.g. in a server,
+    /// don't saturate the network or trip crates.io's rate limits. Values below 1 mean 1.
+    pub fn set_max_concurrent_downloads(max: usize) {
+        rust::set_max_concurrent_downloads(max)
+    }
+
+    /// Let `alias` stand for `crate_name` in searches that enable `resolve_aliases`
+    ///
+    /// Applies to the whole process and takes precedence over the built-in aliases.
+    pub fn add_crate_alias(alias: &str, crate_name: &str) {
+        rust::add_crate_alias(alias, crate_name)
+    }
+
+    /// Snapshot of how often searches hit the cache, extracted, or downloaded, across this process
+    pub fn metrics() -> metrics::MetricsSnapshot {
+        metrics::snapshot()
+    }
+
+    /// Find popular Rust crates in a crates.io category (e.g. "network-programming")
+    pub async fn find_by_category(category: &str) -> Result<Vec<rust::CrateSummary>> {
+        rust::CrateDiscovery::new().find_by_category(category).await
+    }
+
+    /// Find popular Rust crates tagged with a crates.io keyword (e.g. "http")
+    pub async fn find_by_keyword(keyword: &str) -> Result<Vec<rust::CrateSummary>> {
+        rust::CrateDiscovery::new().find_by_keyword(keyword).await
+    }
+
+    /// Resolve, download, and extract each (name, version constraint) pair so later searches are instant
+    ///
+    /// Crates are checked out concurrently, with downloads bounded by the process-wide limit
+    /// (see `set_max_concurrent_downloads`). An empty constraint means the latest version.
+    /// Returns one `WarmedCrate` per input, in the same order; one failure doesn't stop the rest.
+    pub async fn warm_cache(names_and_versions: &[(String, String)]) -> Vec<WarmedCrate> {
+        rust::warm_cache(&rust::RustCrateSearch::new(""), names_and_versions).await
+    }
+}
+
+/// Outcome of pre-extracting one crate with `Eg::warm_cache`
+#[derive(Debug)]
+pub struct WarmedCrate {
+    /// The crate name as given
+    pub crate_name: String,
+    /// The resolved version and extraction path, or why the crate couldn't be checked out
+    pub result: Result<(String, PathBuf)>,
 }
 
 /// Result of an example search
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct SearchResult {
+    /// Name of the crate that was searched, as published
+    pub crate_name: String,
     /// The exact version that was searched
     pub version: String,
     /// Path to the full crate extraction on disk
@@ -48,19 +146,614 @@ pub struct SearchResult {
     pub example_matches: Vec<Match>,
     /// Matches found elsewhere in the crate
     pub other_matches: Vec<Match>,
+    /// Files whose matches were cut short by `max_matches_per_file`
+    pub truncated_files: Vec<PathBuf>,
+    /// Whether some files went unsearched because `max_total_bytes` ran out
+    pub truncated: bool,
+    /// Matches found in dependencies when the crate itself had none (see `follow_deps`)
+    pub dependency_matches: Vec<DependencyMatches>,
+    /// Matches found in companion crates such as `serde_derive` (see `include_companions`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub companion_matches: Vec<DependencyMatches>,
+    /// What the extracted crate contained, to tell an example-less crate from a bad pattern
+    pub stats: ExtractionStats,
+    /// Every file the `.crate` archive contained with its size in bytes, with `record_file_list`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub file_list: Vec<(PathBuf, u64)>,
+    /// The package has no examples because its manifest's `include`/`exclude` left `examples/` out
+    ///
+    /// The repository likely still has them, so it is the place to look instead.
+    pub examples_excluded_from_package: bool,
+    /// The `documentation` URL from the crate's manifest, if set
+    pub documentation: Option<String>,
+    /// The `homepage` URL from the crate's manifest, if set
+    pub homepage: Option<String>,
+    /// Host of the manifest's `repository` when the crate has no examples and that host isn't
+    /// github.com
+    ///
+    /// eg does not search repositories on any host yet; GitHub is left out only because it is the
+    /// host a repository fallback is planned for. Informational: it points at where the examples
+    /// might live, and never fails a search.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unsupported_repo_host: Option<String>,
+    /// The crate's own `[dependencies]` as (package name, version requirement)
+    pub dependencies: Vec<(String, String)>,
+    /// Where the searched sources came from
+    pub source: CheckoutSource,
+    /// Time spent in each phase of the search, if requested with `collect_timings`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<SearchTimings>,
+    /// The name the search was given, when `resolve_aliases` replaced it with `crate_name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
 }
 
-/// A search match with context
+impl SearchResult {
+    /// Combine another result's matches into this one, skipping matches already present
+    ///
+    /// The version and checkout path of `self` are kept. Matches are considered duplicates
+    /// when their snippets, the context lines (or the matched line, without context), hash the
+    /// same, so the same code found under another path, e.g. in a repository checkout, is kept once.
+    pub fn merge(mut self, other: SearchResult) -> SearchResult {
+        merge_matches(&mut self.example_matches, other.example_matches);
+        merge_matches(&mut self.other_matches, other.other_matches);
+
+        for file in other.truncated_files {
+            if !self.truncated_files.contains(&file) {
+                self.truncated_files.push(file);
+            }
+        }
+        self.dependency_matches.extend(other.dependency_matches);
+        self.companion_matches.extend(other.companion_matches);
+        self.truncated |= other.truncated;
+
+        self
+    }
+
+    /// The `cargo run --example` command for each example packaged with the crate
+    ///
+    /// Paths are relative to `checkout_path`. Commands include `--features` when the manifest
+    /// lists `required-features` for the example. Fails if the checkout's manifest can't be read.
+    pub fn run_commands(&self) -> Result<Vec<(PathBuf, String)>> {
+        rust::CrateSearcher::new().run_commands(&self.checkout_path)
+    }
+
+    /// The on-disk path of one of this result's matches
+    ///
+    /// Joins `checkout_path` with the match's crate-relative `file_path`; a path that is already
+    /// absolute (see `absolute_paths`) is returned unchanged. For dependency matches, join with
+    /// `DependencyMatches::checkout_path` instead.
+    pub fn absolute_path(&self, m: &Match) -> PathBuf {
+        self.checkout_path.join(&m.file_path)
+    }
+
+    /// A one-line, space-separated `key=value` summary for a quick read before the details
+    ///
+    /// The line is always `matches={n} files={n} source={packaged|local} version={v} truncated={bool}`,
+    /// in that order. `matches` and `files` count example and other matches, not dependency
+    /// matches; `version` is `-` when unknown (e.g. `Eg::rust_dir`). `truncated` is true when any
+    /// file's matches were cut short or files went unsearched.
+    pub fn summary_line(&self) -> String {
+        let matches: Vec<&Match> = self.example_matches.iter().chain(&self.other_matches).collect();
+        let files: std::collections::HashSet<&Path> = matches.iter().map(|m| m.file_path.as_path()).collect();
+        let version = if self.version.is_empty() { "-" } else { &self.version };
+
+        format!(
+            "matches={} files={} source={} version={} truncated={}",
+            matches.len(),
+            files.len(),
+            self.source.as_str(),
+            version,
+            self.truncated || !self.truncated_files.is_empty(),
+        )
+    }
+
+    /// The example and other matches grouped by file, in the order each file first appears
+    ///
+    /// Example matches come first, as in `render`. Matches within a group keep their order.
+    pub fn by_file(&self) -> Vec<FileMatches<'_>> {
+        let mut groups: Vec<FileMatches<'_>> = Vec::new();
+        for m in self.example_matches.iter().chain(&self.other_matches) {
+            match groups.iter_mut().find(|group| group.path == m.file_path) {
+                Some(group) => group.matches.push(m),
+                None => groups.push(FileMatches { path: &m.file_path, matches: vec![m] }),
+            }
+        }
+        groups
+    }
+
+    /// Render the result as text in the given format
+    ///
+    /// `OutputFormat::Hunk` renders example matches, then other matches, one hunk each.
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            OutputFormat::Hunk => self.example_matches
+                .iter()
+                .chain(&self.other_matches)
+                .map(Match::to_hunk)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            OutputFormat::Markdown => self.to_markdown(),
+            OutputFormat::JsonLines => {
+                let mut lines = Vec::new();
+                self.write_json_lines(&mut lines).unwrap_or_default();
+                String::from_utf8(lines).unwrap_or_default()
+            }
+        }
+    }
+
+    /// Cut every match's context to at most `max_lines` lines, see `Match::limit_context`
+    ///
+    /// Keeps rendered output, such as an MCP response, within a budget when matches fall in
+    /// long functions or span many lines.
+    pub fn limit_snippets(&mut self, max_lines: usize) {
+        for m in self.example_matches.iter_mut().chain(&mut self.other_matches) {
+            m.limit_context(max_lines);
+        }
+        for dependency in self.dependency_matches.iter_mut().chain(&mut self.companion_matches) {
+            for m in dependency.example_matches.iter_mut().chain(&mut dependency.other_matches) {
+                m.limit_context(max_lines);
+            }
+        }
+    }
+
+    /// Write each match as a standalone JSON object on its own line, examples first
+    ///
+    /// Suits piping into tools that process results incrementally, where one JSON document
+    /// would have to be parsed whole. The result's other fields are not written.
+    pub fn write_json_lines(&self, mut writer: impl std::io::Write) -> Result<()> {
+        for m in self.example_matches.iter().chain(&self.other_matches) {
+            serde_json::to_writer(&mut writer, m).map_err(std::io::Error::from)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Render the result as a self-contained markdown document for sharing
+    ///
+    /// Starts with a table of contents, then each example match and each other match as a
+    /// fenced `rust` block annotated with its line range. Packaged results link every match to
+    /// its source on docs.rs; locally searched sources have no such page and get no link.
+    pub fn to_markdown(&self) -> String {
+        let title = match self.version.as_str() {
+            "" => self.crate_name.clone(),
+            version => format!("{} {}", self.crate_name, version),
+        };
+        let mut doc = format!("# {}\n\n", title);
+
+        let sections = [("Examples", &self.example_matches), ("Other matches", &self.other_matches)];
+        let numbered: Vec<(&str, usize, &Match)> = sections
+            .iter()
+            .flat_map(|(heading, matches)| matches.iter().map(move |m| (*heading, m)))
+            .enumerate()
+            .map(|(index, (heading, m))| (heading, index + 1, m))
+            .collect();
+
+        if numbered.is_empty() {
+            doc.push_str("No matches.\n");
+            return doc;
+        }
+
+        doc.push_str("## Contents\n\n");
+        for &(_, number, m) in &numbered {
+            doc.push_str(&format!("- [{}:{}](#match-{})\n", m.file_path.display(), m.line_number, number));
+        }
+
+        let mut current_heading = "";
+        for &(heading, number, m) in &numbered {
+            if heading != current_heading {
+                doc.push_str(&format!("\n## {}\n", heading));
+                current_heading = heading;
+            }
+
+            let mut numbered_lines = m.context.iter().filter(|line| !line.is_truncation_marker());
+            let first = numbered_lines.next().map_or(m.line_number, |line| line.line_number);
+            let last = numbered_lines.next_back().map_or(first, |line| line.line_number);
+            doc.push_str(&format!(
+                "\n<a id=\"match-{}\"></a>\n### {}:{}\n\n",
+                number,
+                m.file_path.display(),
+                m.line_number
+            ));
+            if let Some(url) = self.docs_rs_source_url(m) {
+                doc.push_str(&format!("[Source on docs.rs]({})\n\n", url));
+            }
+            doc.push_str(&format!("Lines {}-{}, match at line {}:\n\n```rust\n", first, last, m.line_number));
+            if m.context.is_empty() {
+                doc.push_str(&m.line_content);
+                doc.push('\n');
+            }
+            for line in &m.context {
+                doc.push_str(&line.content);
+                doc.push('\n');
+            }
+            doc.push_str("```\n");
+        }
+        doc
+    }
+
+    /// The docs.rs source page for a match, when the result came from a published version
+    fn docs_rs_source_url(&self, m: &Match) -> Option<String> {
+        if self.source != CheckoutSource::Packaged || self.crate_name.is_empty() || self.version.is_empty() {
+            return None;
+        }
+        let path = m.file_path.strip_prefix(&self.checkout_path).unwrap_or(&m.file_path);
+        Some(format!("https://docs.rs/crate/{}/{}/source/{}#{}", self.crate_name, self.version, path.display(), m.line_number))
+    }
+}
+
+/// Append `incoming` matches to `matches`, skipping any whose snippet is already present
+fn merge_matches(matches: &mut Vec<Match>, incoming: Vec<Match>) {
+    let mut seen: std::collections::HashSet<u64> = matches.iter().map(snippet_hash).collect();
+
+    for m in incoming {
+        if seen.insert(snippet_hash(&m)) {
+            matches.push(m);
+        }
+    }
+}
+
+/// Hash of a match's snippet text: its context lines, or the matched line when it has no context
+fn snippet_hash(m: &Match) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::hash::DefaultHasher::new();
+    if m.context.is_empty() {
+        m.line_content.hash(&mut hasher);
+    } else {
+        for line in &m.context {
+            line.content.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Time spent in each phase of one search
+///
+/// Phases that didn't run (e.g. `download` when the crate was already cached) are zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct SearchTimings {
+    /// Resolving the version, from the project or crates.io
+    pub resolve: Duration,
+    /// Looking for the crate in eg's and cargo's caches
+    pub locate: Duration,
+    /// Downloading the `.crate` archive
+    pub download: Duration,
+    /// Unpacking the archive
+    pub extract: Duration,
+    /// Matching patterns and collecting the result, including any dependency fallback
+    pub search: Duration,
+}
+
+impl SearchTimings {
+    /// The sum of all phases
+    pub fn total(&self) -> Duration {
+        self.resolve + self.locate + self.download + self.extract + self.search
+    }
+}
+
+/// Summary of the files in an extracted crate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ExtractionStats {
+    /// Number of files in the extraction, excluding `target` and, unless `include_hidden`, hidden directories
+    pub files_seen: usize,
+    /// Number of Rust files under an examples/ directory
+    pub example_files: usize,
+    /// Total size in bytes of the files the search actually read
+    pub bytes_read: u64,
+}
+
+/// The matches found in one file, see `SearchResult::by_file`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileMatches<'a> {
+    /// Path of the file, as in `Match::file_path`
+    pub path: &'a Path,
+    /// The file's matches, in result order
+    pub matches: Vec<&'a Match>,
+}
+
+/// Matches found in one of the searched crate's dependencies
 #[derive(Debug, Clone, serde::Serialize)]
+pub struct DependencyMatches {
+    /// Name of the dependency the matches came from
+    pub crate_name: String,
+    /// The exact version of the dependency that was searched
+    pub version: String,
+    /// Path to the dependency's extraction on disk; match paths are relative to it
+    pub checkout_path: PathBuf,
+    /// Matches found in the dependency's examples/ directory
+    pub example_matches: Vec<Match>,
+    /// Matches found elsewhere in the dependency
+    pub other_matches: Vec<Match>,
+}
+
+/// A complete example file
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Example {
+    /// Relative path within the crate, always `/`-separated
+    pub file_path: PathBuf,
+    /// Full contents of the file, or of the code block for examples cut from a larger file
+    pub content: String,
+    /// 1-based line of `file_path` where `content` starts, for examples cut from a larger file such as a README
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<u32>,
+    /// How rustdoc would treat the code block, from its fence's info string (`None` for whole files)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc_block: Option<DocBlockKind>,
+    /// Whether the code has a `fn main`, and what kind
+    pub entry: EntrySignature,
+}
+
+/// How an example is meant to be run, e.g. to label "runnable async example" in a UI
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct EntrySignature {
+    /// Whether there is a top-level `fn main`; without one the code is a library-style snippet
+    pub has_main: bool,
+    /// Whether `main` is `async`
+    pub is_async: bool,
+    /// Whether `main` returns a `Result`, so the example can use `?`
+    pub returns_result: bool,
+    /// The attribute that runs an async `main`, e.g. `tokio::main`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+}
+
+/// What a code block's fence attributes (`rust,no_run`, `ignore`, ...) say about its code
+///
+/// When a fence carries several attributes, the one saying least about the code working wins:
+/// `compile_fail` over `ignore` over `no_run` over `should_panic`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocBlockKind {
+    /// Compiled and run as a doctest
+    Run,
+    /// Compiled but not run, e.g. because it needs a network
+    NoRun,
+    /// Expected to panic when run
+    ShouldPanic,
+    /// Not compiled at all; may be pseudocode
+    Ignore,
+    /// Demonstrates code that must not compile: what not to do
+    CompileFail,
+}
+
+/// A search match with context
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[allow(deprecated)]
 pub struct Match {
-    /// Relative path within the crate
+    /// Relative path within the crate, always `/`-separated (absolute with `absolute_paths`)
     pub file_path: PathBuf,
-    /// 1-based line number where match was found
+    /// 1-based line number where match was found (0 for whole-file matches)
     pub line_number: u32,
     /// The line containing the match
     pub line_content: String,
+    /// Byte offset of the matched text within the file (0 for whole-file matches)
+    pub byte_start: usize,
+    /// Byte offset just past the matched text, so `&contents[byte_start..byte_end]` is the match
+    pub byte_end: usize,
     /// Lines before the match for context
+    #[deprecated(note = "use `context`, which carries line numbers")]
     pub context_before: Vec<String>,
     /// Lines after the match for context
+    #[deprecated(note = "use `context`, which carries line numbers")]
     pub context_after: Vec<String>,
+    /// The matched lines together with their surrounding context, in file order
+    pub context: Vec<ContextLine>,
+    /// What kind of file the match came from
+    pub source: MatchSource,
+    /// Whether the match's snippet compiles against the crate, if checked with `verify_compiles`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compiles: Option<bool>,
+    /// Doc comment of the item the match falls in or just follows, with `attach_doc_comments`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc_comment: Option<String>,
+    /// The `#[test]` function the match falls in, with `include_test_context`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_context: Option<TestContext>,
+}
+
+impl Match {
+    /// The matched file with its local `mod foo;` declarations and `include!`s inlined
+    ///
+    /// `checkout_path` is the crate root the match's `file_path` is relative to, usually
+    /// `SearchResult::checkout_path`. Useful for examples split across several files.
+    pub fn resolved_snippet(&self, checkout_path: &Path) -> Result<String> {
+        rust::CrateSearcher::new().resolved_snippet(checkout_path, &self.file_path)
+    }
+
+    /// How the matched file is meant to be run: `fn main`, `async fn main`, or a snippet
+    ///
+    /// `checkout_path` is the crate root, as for `resolved_snippet`.
+    pub fn entry_signature(&self, checkout_path: &Path) -> Result<EntrySignature> {
+        let path = checkout_path.join(&self.file_path);
+        let content = std::fs::read_to_string(&path).map_err(|e| EgError::ReadError { path, source: e })?;
+        Ok(rust::entry_signature(&content))
+    }
+
+    /// Cut the context down to at most `max_lines` lines, keeping the matched lines centered
+    ///
+    /// Removed runs are replaced by a `// ... (truncated) ...` marker line (line number 0). When
+    /// the matched lines alone exceed the limit, their first `max_lines` lines are kept, and a
+    /// multi-line `line_content` is cut the same way, ending in the marker.
+    pub fn limit_context(&mut self, max_lines: usize) {
+        if let Some((cut, _)) = self.line_content.match_indices('\n').nth(max_lines.saturating_sub(1)) {
+            self.line_content.truncate(cut);
+            self.line_content.push('\n');
+            self.line_content.push_str(TRUNCATION_MARKER);
+        }
+
+        if self.context.len() <= max_lines {
+            return;
+        }
+
+        let first_match = self.context.iter().position(|line| line.is_match).unwrap_or(0);
+        let last_match = self.context.iter().rposition(|line| line.is_match).unwrap_or(first_match);
+        let matched = last_match - first_match + 1;
+
+        // Split the spare lines evenly, giving any a side can't use to the other
+        let (start, end) = if matched >= max_lines {
+            (first_match, first_match + max_lines)
+        } else {
+            let spare = max_lines - matched;
+            let before = (spare / 2).min(first_match);
+            let after = (spare - before).min(self.context.len() - last_match - 1);
+            let before = (spare - after).min(first_match);
+            (first_match - before, last_match + after + 1)
+        };
+
+        let total = self.context.len();
+        let mut kept = Vec::with_capacity(max_lines + 2);
+        if start > 0 {
+            kept.push(ContextLine::truncation_marker());
+        }
+        kept.extend(self.context.drain(start..end));
+        if end < total {
+            kept.push(ContextLine::truncation_marker());
+        }
+        self.context = kept;
+    }
+
+    /// Render the match and its context as a unified-diff-style hunk
+    ///
+    /// The header is `@@ -{first line},{line count} @@ {file_path}`, covering the context
+    /// window; matched lines are prefixed with `>` and context lines with a space.
+    pub fn to_hunk(&self) -> String {
+        let start = self.context.iter()
+            .find(|line| !line.is_truncation_marker())
+            .map_or(self.line_number, |line| line.line_number);
+        let mut hunk = format!("@@ -{},{} @@ {}\n", start, self.context.len(), self.file_path.display());
+
+        for line in &self.context {
+            let marker = if line.is_match { '>' } else { ' ' };
+            hunk.push(marker);
+            hunk.push_str(&line.content);
+            hunk.push('\n');
+        }
+        hunk
+    }
+}
+
+/// How a search result is rendered as text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The full result as pretty-printed JSON
+    #[default]
+    Json,
+    /// Each match as a unified-diff-style hunk with `>` on matched lines
+    Hunk,
+    /// A shareable markdown document, see `SearchResult::to_markdown`
+    Markdown,
+    /// One JSON match object per line (JSON Lines), see `SearchResult::write_json_lines`
+    JsonLines,
+}
+
+/// Where a search's sources came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckoutSource {
+    /// A published `.crate` archive, from cargo's cache, eg's cache, a download, or a local file
+    #[default]
+    Packaged,
+    /// Sources searched in place: a directory given to `Eg::rust_dir`, or a path, git, or patched dependency
+    Local,
+}
+
+impl CheckoutSource {
+    /// The name used in `SearchResult::summary_line`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckoutSource::Packaged => "packaged",
+            CheckoutSource::Local => "local",
+        }
+    }
+}
+
+/// The kind of file a match came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchSource {
+    /// A file under an examples/ directory
+    Example,
+    /// Any other Rust source file
+    #[default]
+    Code,
+    /// A markdown document such as README.md or CHANGELOG.md
+    Docs,
+    /// The crate's `build.rs` build script, see `include_build_scripts`
+    Build,
+}
+
+/// A line of context around a match
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ContextLine {
+    /// 1-based line number within the file (0 for a truncation marker)
+    pub line_number: u32,
+    /// The line's text
+    pub content: String,
+    /// Whether this line is part of the match
+    pub is_match: bool,
+}
+
+/// The test function around a match, see `include_test_context`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct TestContext {
+    /// Name of the test function
+    pub name: String,
+    /// The whole function, attributes included, with the matched lines marked `is_match`
+    pub lines: Vec<ContextLine>,
+    /// Line numbers of the function's assertion macros, such as `assert!` and `assert_eq!`
+    pub assertion_lines: Vec<u32>,
+}
+
+/// Text of the line standing in for context removed by `Match::limit_context`
+const TRUNCATION_MARKER: &str = "// ... (truncated) ...";
+
+impl ContextLine {
+    /// The line standing in for context removed by `Match::limit_context`
+    fn truncation_marker() -> Self {
+        ContextLine { line_number: 0, content: TRUNCATION_MARKER.to_string(), is_match: false }
+    }
+
+    /// Whether this line marks removed context rather than a line of the file
+    pub fn is_truncation_marker(&self) -> bool {
+        self.line_number == 0 && !self.is_match && self.content == TRUNCATION_MARKER
+    }
+}
+
+/// Example files that changed between two versions of a crate, see `diff_examples`
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ExampleDiff {
+    /// The version compared from
+    pub from_version: String,
+    /// The version compared to
+    pub to_version: String,
+    /// Example files only the newer version has
+    pub added: Vec<PathBuf>,
+    /// Example files only the older version has
+    pub removed: Vec<PathBuf>,
+    /// Example files both versions have, with different contents
+    pub modified: Vec<ModifiedExample>,
+}
+
+/// An example file whose contents changed between two versions
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ModifiedExample {
+    /// Crate-relative path of the example, always `/`-separated
+    pub file_path: PathBuf,
+    /// Lines only the older version has (only those matching the search's patterns, if set)
+    pub removed_lines: Vec<String>,
+    /// Lines only the newer version has (only those matching the search's patterns, if set)
+    pub added_lines: Vec<String>,
+}
+
+/// Result of downloading a crate without searching it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DownloadedCrate {
+    /// The exact version that was downloaded
+    pub version: String,
+    /// Path to the `.crate` archive written to the destination
+    pub crate_path: PathBuf,
+    /// Path to the extracted sources, if extraction was requested
+    pub extracted_path: Option<PathBuf>,
 }
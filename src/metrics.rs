@@ -0,0 +1,55 @@
+//! Process-wide counters of where searches got their crate sources
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static EXTRACTIONS: AtomicU64 = AtomicU64::new(0);
+static DOWNLOADS: AtomicU64 = AtomicU64::new(0);
+static RESULT_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+
+/// A decision point worth counting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// Sources were already extracted, by eg or by cargo
+    CacheHit,
+    /// A `.crate` archive was unpacked
+    Extraction,
+    /// A `.crate` archive was downloaded from crates.io
+    Download,
+    /// A search was answered from the result cache
+    ResultCacheHit,
+}
+
+/// Count one occurrence of `event`
+pub(crate) fn record(event: Event) {
+    let counter = match event {
+        Event::CacheHit => &CACHE_HITS,
+        Event::Extraction => &EXTRACTIONS,
+        Event::Download => &DOWNLOADS,
+        Event::ResultCacheHit => &RESULT_CACHE_HITS,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counter values at one point in time, see `Eg::metrics`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct MetricsSnapshot {
+    /// Checkouts served from already-extracted sources
+    pub cache_hits: u64,
+    /// `.crate` archives unpacked, whether from cargo's cache, a download, or a local file
+    pub extractions: u64,
+    /// `.crate` archives downloaded from crates.io
+    pub downloads: u64,
+    /// Searches answered from the result cache (see `result_cache`)
+    pub result_cache_hits: u64,
+}
+
+/// Read every counter
+pub fn snapshot() -> MetricsSnapshot {
+    MetricsSnapshot {
+        cache_hits: CACHE_HITS.load(Ordering::Relaxed),
+        extractions: EXTRACTIONS.load(Ordering::Relaxed),
+        downloads: DOWNLOADS.load(Ordering::Relaxed),
+        result_cache_hits: RESULT_CACHE_HITS.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,52 @@
+//! Conceptual names that stand for a specific crate, e.g. `json` for `serde_json`
+
+use std::sync::RwLock;
+
+/// Built-in aliases, as (alias, crate name)
+const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("json", "serde_json"),
+    ("yaml", "serde_yaml"),
+    ("serialization", "serde"),
+    ("http-client", "reqwest"),
+    ("web-framework", "axum"),
+    ("async-runtime", "tokio"),
+    ("cli", "clap"),
+    ("args", "clap"),
+    ("logging", "tracing"),
+    ("random", "rand"),
+    ("datetime", "chrono"),
+    ("regexp", "regex"),
+    ("error-handling", "anyhow"),
+];
+
+/// Aliases added with `Eg::add_crate_alias`, consulted before the built-in ones
+static USER_ALIASES: RwLock<Vec<(String, String)>> = RwLock::new(Vec::new());
+
+/// Register an alias for this process, replacing any earlier one for the same name
+pub(crate) fn add(alias: &str, crate_name: &str) {
+    let alias = normalize(alias);
+    if let Ok(mut aliases) = USER_ALIASES.write() {
+        aliases.retain(|(existing, _)| *existing != alias);
+        aliases.push((alias, crate_name.to_string()));
+    }
+}
+
+/// The crate an alias stands for, if `name` is one
+///
+/// Matching ignores case and treats `-` and `_` alike, like crate names themselves.
+pub(crate) fn lookup(name: &str) -> Option<String> {
+    let name = normalize(name);
+    let user = USER_ALIASES.read().ok().and_then(|aliases| {
+        aliases.iter().find(|(alias, _)| *alias == name).map(|(_, crate_name)| crate_name.clone())
+    });
+
+    user.or_else(|| {
+        BUILTIN_ALIASES.iter()
+            .find(|(alias, _)| *alias == name)
+            .map(|(_, crate_name)| crate_name.to_string())
+    })
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace('_', "-")
+}
@@ -1,7 +1,9 @@
 //! Cache management for extracted crates
 
-use crate::{Result, EgError};
+use crate::{Result, EgError, SearchTimings};
+use crate::metrics::{self, Event};
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 /// Manages access to cargo's cache and our extraction cache
 pub struct CacheManager {
@@ -29,6 +31,14 @@ impl CacheManager {
         })
     }
 
+    /// Use this cargo home and extraction cache instead of the defaults, e.g. for a sandboxed tool
+    pub fn with_dirs(cargo_home: &Path, extraction_cache_dir: &Path) -> Self {
+        Self {
+            cargo_cache_dir: cargo_home.join("registry"),
+            extraction_cache_dir: extraction_cache_dir.to_path_buf(),
+        }
+    }
+
     /// Get or extract a crate, returning the path to the extracted source
     pub async fn get_or_extract_crate(
         &self,
@@ -36,54 +46,83 @@ impl CacheManager {
         version: &str,
         extractor: &super::CrateExtractor,
     ) -> Result<PathBuf> {
+        self.get_or_extract_crate_timed(crate_name, version, extractor, &mut SearchTimings::default()).await
+    }
+
+    /// Like `get_or_extract_crate`, adding the time spent to the `locate`, `download`, and `extract` phases
+    pub(crate) async fn get_or_extract_crate_timed(
+        &self,
+        crate_name: &str,
+        version: &str,
+        extractor: &super::CrateExtractor,
+        timings: &mut SearchTimings,
+    ) -> Result<PathBuf> {
+        let started = Instant::now();
+
         // 1. Check if already extracted in our cache
         let extraction_path = self.extraction_cache_dir.join(format!("{}-{}", crate_name, version));
         if extraction_path.exists() {
+            metrics::record(Event::CacheHit);
+            timings.locate += started.elapsed();
             return Ok(extraction_path);
         }
 
-        // 2. Check cargo's extracted sources
-        if let Some(cargo_src_path) = self.find_cargo_extracted_crate(crate_name, version)? {
+        // 2. Check cargo's extracted sources, which need no decompression at all
+        if let Some(cargo_src_path) = self.find_extracted_src(crate_name, version)? {
+            metrics::record(Event::CacheHit);
+            timings.locate += started.elapsed();
             return Ok(cargo_src_path);
         }
 
         // 3. Check cargo's .crate cache
-        if let Some(cached_crate_path) = self.find_cached_crate(crate_name, version)? {
-            return extractor.extract_crate_to_cache(&cached_crate_path, &extraction_path).await;
+        let cached_crate_path = self.find_cached_crate(crate_name, version)?;
+        timings.locate += started.elapsed();
+        if let Some(cached_crate_path) = cached_crate_path {
+            let started = Instant::now();
+            let extracted = extractor.extract_crate_to_cache(&cached_crate_path, &extraction_path).await;
+            timings.extract += started.elapsed();
+            return extracted;
         }
 
         // 4. Download and extract
-        extractor.download_and_extract_crate(crate_name, version, &extraction_path).await
+        let started = Instant::now();
+        let bytes = extractor.download_crate(crate_name, version).await?;
+        timings.download += started.elapsed();
+
+        let started = Instant::now();
+        let extracted = extractor.extract_bytes_to_cache(&bytes, &extraction_path).await;
+        timings.extract += started.elapsed();
+        extracted
     }
 
-    /// Find extracted crate in cargo's src cache
-    fn find_cargo_extracted_crate(&self, crate_name: &str, version: &str) -> Result<Option<PathBuf>> {
+    /// Find the sources cargo already unpacked under `registry/src/*/{crate}-{version}/`
+    ///
+    /// Every registry directory is scanned, both sparse (`index.crates.io-*`) and git-index
+    /// (`github.com-*`) ones. A directory without a `Cargo.toml` is an unpacking cargo didn't
+    /// finish and is skipped.
+    pub fn find_extracted_src(&self, crate_name: &str, version: &str) -> Result<Option<PathBuf>> {
         let src_dir = self.cargo_cache_dir.join("src");
         if !src_dir.exists() {
             return Ok(None);
         }
 
         let crate_dir_name = format!("{}-{}", crate_name, version);
-        
-        // Look for registry directories (e.g., index.crates.io-*)
+
         for entry in std::fs::read_dir(src_dir)? {
             let entry = entry?;
             if entry.file_type()?.is_dir() {
-                let registry_name = entry.file_name();
-                if registry_name.to_string_lossy().starts_with("index.") {
-                    let crate_path = entry.path().join(&crate_dir_name);
-                    if crate_path.exists() {
-                        return Ok(Some(crate_path));
-                    }
+                let crate_path = entry.path().join(&crate_dir_name);
+                if crate_path.join("Cargo.toml").is_file() {
+                    return Ok(Some(crate_path));
                 }
             }
         }
-        
+
         Ok(None)
     }
 
     /// Find a cached .crate file for the given crate and version
-    fn find_cached_crate(&self, crate_name: &str, version: &str) -> Result<Option<PathBuf>> {
+    pub fn find_cached_crate(&self, crate_name: &str, version: &str) -> Result<Option<PathBuf>> {
         let cache_dir = self.cargo_cache_dir.join("cache");
         if !cache_dir.exists() {
             return Ok(None);
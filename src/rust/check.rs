@@ -0,0 +1,71 @@
+//! Compile-checking of example snippets against the searched crate
+
+use crate::{Result, EgError};
+use std::fs;
+use std::path::PathBuf;
+
+/// Checks whether Rust sources compile with a given crate version as their only dependency
+pub struct SnippetChecker {
+    crate_name: String,
+    version: String,
+    target_dir: PathBuf,
+}
+
+impl SnippetChecker {
+    /// Create a checker for `crate_name` at exactly `version`
+    ///
+    /// Each snippet is checked in its own scratch crate, so concurrent checks never overwrite
+    /// each other's sources. They share a target directory under the system temp directory,
+    /// so the dependency is only compiled once.
+    pub fn new(crate_name: &str, version: &str) -> Self {
+        let target_dir = std::env::temp_dir()
+            .join("eg-check")
+            .join(format!("{}-{}", crate_name, version))
+            .join("target");
+
+        Self {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+            target_dir,
+        }
+    }
+
+    /// Run `cargo check` on `source` as a binary's `main.rs`, returning whether it compiled
+    pub async fn check(&self, source: &str) -> Result<bool> {
+        let work_dir = tempfile::Builder::new().prefix("eg-check-").tempdir()?;
+        fs::create_dir_all(work_dir.path().join("src"))?;
+        fs::write(
+            work_dir.path().join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"eg-check\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+                 [dependencies]\n{} = \"={}\"\n\n[workspace]\n",
+                self.crate_name, self.version
+            ),
+        )?;
+        fs::write(work_dir.path().join("src/main.rs"), source)?;
+
+        let status = tokio::process::Command::new("cargo")
+            .args(["check", "--quiet"])
+            .current_dir(work_dir.path())
+            .env("CARGO_TARGET_DIR", &self.target_dir)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await
+            .map_err(|source| EgError::CommandFailed { command: "cargo check".to_string(), source })?;
+
+        Ok(status.success())
+    }
+}
+
+/// Turn a match's context into a program `cargo check` can build
+///
+/// Snippets with their own `fn main` are used as-is; anything else, such as a few statements
+/// cut from a function body, becomes the body of one.
+pub(crate) fn snippet_program(snippet: &str) -> String {
+    if super::entry_signature(snippet).has_main {
+        snippet.to_string()
+    } else {
+        format!("#![allow(unused)]\nfn main() {{\n{}\n}}\n", snippet)
+    }
+}
@@ -0,0 +1,185 @@
+//! Crate discovery through crates.io categories and keywords
+
+use crate::{Result, EgError};
+use super::registry;
+use crates_io_api::{CratesPage, CratesQuery, Sort};
+
+/// Number of crates returned by a discovery query
+const PAGE_SIZE: u64 = 50;
+
+/// The crates.io web API, used directly where `crates_io_api` lacks a query
+const CRATES_IO_API: &str = "https://crates.io/api/v1";
+
+/// Summary of a crate found through discovery
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CrateSummary {
+    /// Crate name
+    pub name: String,
+    /// Short description from the crate's manifest
+    pub description: Option<String>,
+    /// All-time download count
+    pub downloads: u64,
+    /// Downloads in the last 90 days, to tell a crate that is still in use from a past favorite
+    pub recent_downloads: Option<u64>,
+    /// Number of published versions, when the listing includes them
+    pub version_count: Option<usize>,
+    /// Latest published version
+    pub max_version: String,
+}
+
+/// Finds crates on crates.io by category or keyword
+pub struct CrateDiscovery {
+    user_agent: Option<String>,
+    min_downloads: u64,
+    api_base_url: Option<String>,
+}
+
+impl Default for CrateDiscovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CrateDiscovery {
+    pub fn new() -> Self {
+        Self {
+            user_agent: None,
+            min_downloads: 0,
+            api_base_url: None,
+        }
+    }
+
+    /// Drop crates with fewer than `downloads` all-time downloads from the results
+    ///
+    /// Keeps abandoned or placeholder crates out of keyword and category listings.
+    pub fn min_downloads(mut self, downloads: u64) -> Self {
+        self.min_downloads = downloads;
+        self
+    }
+
+    /// Send `find_by_keyword` queries to this API root instead of `https://crates.io/api/v1`
+    ///
+    /// For mirrors and tests; the other queries go through `crates_io_api`, which always uses crates.io.
+    pub fn api_base_url(mut self, base_url: &str) -> Self {
+        self.api_base_url = Some(base_url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Identify requests with this user-agent instead of the process default
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Find the most downloaded crates in a category (e.g. "network-programming")
+    pub async fn find_by_category(&self, category: &str) -> Result<Vec<CrateSummary>> {
+        let client = registry::api_client(&self.user_agent_header())?;
+
+        let page = registry::with_backoff(|| async {
+            let query = CratesQuery::builder()
+                .category(category)
+                .sort(Sort::Downloads)
+                .page_size(PAGE_SIZE)
+                .build();
+            Ok(client.crates(query).await?)
+        }).await?;
+
+        Ok(self.summarize(page))
+    }
+
+    /// Find the most downloaded crates whose names match a glob such as `tokio-*`
+    ///
+    /// `*` matches any run of characters and `?` a single one; matching ignores case and treats
+    /// `-` and `_` alike, as crates.io does. Only the top search results are considered.
+    pub async fn find_by_name(&self, name_glob: &str) -> Result<Vec<CrateSummary>> {
+        let client = registry::api_client(&self.user_agent_header())?;
+
+        // crates.io search is full-text, so query with the literal parts and filter names locally
+        let search_term: String = name_glob.chars().filter(|c| !matches!(c, '*' | '?')).collect();
+        let page = registry::with_backoff(|| async {
+            let query = CratesQuery::builder()
+                .search(search_term.trim_matches(|c| c == '-' || c == '_'))
+                .sort(Sort::Downloads)
+                .page_size(PAGE_SIZE)
+                .build();
+            Ok(client.crates(query).await?)
+        }).await?;
+        let name_pattern = glob_to_regex(name_glob)?;
+
+        let mut summaries = self.summarize(page);
+        summaries.retain(|summary| name_pattern.is_match(&summary.name.replace('_', "-")));
+        Ok(summaries)
+    }
+
+    /// Find the most downloaded crates tagged with a keyword (e.g. "http")
+    pub async fn find_by_keyword(&self, keyword: &str) -> Result<Vec<CrateSummary>> {
+        // crates_io_api has no keyword filter, so query the same endpoint directly
+        let client = registry::http_client();
+        let url = format!("{}/crates", self.api_base_url.as_deref().unwrap_or(CRATES_IO_API));
+        let user_agent = self.user_agent_header();
+
+        let page: CratesPage = registry::with_backoff(|| async {
+            registry::api_rate_limit().await;
+            let response = client
+                .get(&url)
+                .header(reqwest::header::USER_AGENT, &user_agent)
+                .query(&[
+                    ("keyword", keyword),
+                    ("sort", "downloads"),
+                    ("per_page", PAGE_SIZE.to_string().as_str()),
+                ])
+                .send()
+                .await?;
+
+            let status = response.status().as_u16();
+            let response = response
+                .error_for_status()
+                .map_err(|source| EgError::DownloadHttpStatus { status, source })?;
+            Ok(response.json().await?)
+        }).await?;
+
+        Ok(self.summarize(page))
+    }
+
+    /// The configured user-agent, falling back to the process default
+    fn user_agent_header(&self) -> String {
+        self.user_agent.clone().unwrap_or_else(crate::default_user_agent)
+    }
+
+    /// Convert a page of crates into summaries, most downloaded first, dropping those below `min_downloads`
+    fn summarize(&self, page: CratesPage) -> Vec<CrateSummary> {
+        let mut summaries: Vec<_> = page.crates
+            .into_iter()
+            .filter(|krate| krate.downloads >= self.min_downloads)
+            .map(|krate| CrateSummary {
+                name: krate.name,
+                description: krate.description,
+                downloads: krate.downloads,
+                recent_downloads: krate.recent_downloads,
+                version_count: krate.versions.map(|versions| versions.len()),
+                max_version: krate.max_version,
+            })
+            .collect();
+
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.downloads));
+        summaries
+    }
+}
+
+/// Compile a crate name glob into an anchored, case-insensitive regex with `_` normalized to `-`
+fn glob_to_regex(glob: &str) -> Result<regex::Regex> {
+    let pattern: String = glob
+        .replace('_', "-")
+        .chars()
+        .map(|c| match c {
+            '*' => ".*".to_string(),
+            '?' => ".".to_string(),
+            c => regex::escape(&c.to_string()),
+        })
+        .collect();
+
+    regex::RegexBuilder::new(&format!("^{}$", pattern))
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| EgError::Other(format!("Invalid crate name pattern: {}", e)))
+}
@@ -0,0 +1,124 @@
+//! Finding the doc comment of the item a match falls in, for `attach_doc_comments`
+
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{AttrStyle, Attribute, Expr, Lit, Meta};
+
+/// An item spanning 1-based lines `start..=end` (its doc comment included), and that doc comment
+pub(crate) struct DocumentedItem {
+    start: usize,
+    end: usize,
+    doc: Option<String>,
+}
+
+/// Every item, impl item, and trait item in `content`, documented or not, or `None` if it doesn't parse
+pub(crate) fn documented_items(content: &str) -> Option<Vec<DocumentedItem>> {
+    let file = syn::parse_file(content).ok()?;
+    let mut collector = DocumentedItems { items: Vec::new() };
+    collector.visit_file(&file);
+    Some(collector.items)
+}
+
+/// The doc comment for 1-based `line`
+///
+/// A line just below an item, such as a trailing comment, belongs to that item: the nearest
+/// item ending above the line within the same enclosing item (or at the top level) is used, if
+/// it is documented. Otherwise it's the innermost documented item spanning the line.
+pub(crate) fn doc_for_line(items: &[DocumentedItem], line: usize) -> Option<&str> {
+    let spans = |item: &DocumentedItem| item.start <= line && line <= item.end;
+    let enclosing = items.iter()
+        .filter(|item| spans(item))
+        .min_by_key(|item| item.end - item.start);
+
+    // Of the items ending at the same line, the outermost is the one the line follows
+    let preceding = items.iter()
+        .filter(|item| item.end < line)
+        .filter(|item| enclosing.is_none_or(|outer| outer.start < item.start))
+        .max_by_key(|item| (item.end, std::cmp::Reverse(item.start)));
+    if let Some(doc) = preceding.and_then(|item| item.doc.as_deref()) {
+        return Some(doc);
+    }
+
+    items.iter()
+        .filter(|item| spans(item))
+        .filter_map(|item| Some((item.end - item.start, item.doc.as_deref()?)))
+        .min_by_key(|(len, _)| *len)
+        .map(|(_, doc)| doc)
+}
+
+struct DocumentedItems {
+    items: Vec<DocumentedItem>,
+}
+
+impl DocumentedItems {
+    /// Record the node's span, with the doc comment from its outer attributes if there is one
+    fn record(&mut self, attrs: &[Attribute], node: &impl Spanned) {
+        // A node's span starts at its first outer attribute, so the doc comment is covered too
+        let span = node.span();
+        self.items.push(DocumentedItem { start: span.start().line, end: span.end().line, doc: doc_text(attrs) });
+    }
+}
+
+impl<'ast> Visit<'ast> for DocumentedItems {
+    fn visit_item(&mut self, node: &'ast syn::Item) {
+        let attrs: &[Attribute] = match node {
+            syn::Item::Const(item) => &item.attrs,
+            syn::Item::Enum(item) => &item.attrs,
+            syn::Item::Fn(item) => &item.attrs,
+            syn::Item::Impl(item) => &item.attrs,
+            syn::Item::Macro(item) => &item.attrs,
+            syn::Item::Mod(item) => &item.attrs,
+            syn::Item::Static(item) => &item.attrs,
+            syn::Item::Struct(item) => &item.attrs,
+            syn::Item::Trait(item) => &item.attrs,
+            syn::Item::Type(item) => &item.attrs,
+            syn::Item::Union(item) => &item.attrs,
+            // `use`, `extern crate`, and the like: still items a line can follow
+            _ => &[],
+        };
+        self.record(attrs, node);
+        visit::visit_item(self, node);
+    }
+
+    fn visit_impl_item(&mut self, node: &'ast syn::ImplItem) {
+        let attrs: &[Attribute] = match node {
+            syn::ImplItem::Const(item) => &item.attrs,
+            syn::ImplItem::Fn(item) => &item.attrs,
+            syn::ImplItem::Type(item) => &item.attrs,
+            syn::ImplItem::Macro(item) => &item.attrs,
+            _ => &[],
+        };
+        self.record(attrs, node);
+        visit::visit_impl_item(self, node);
+    }
+
+    fn visit_trait_item(&mut self, node: &'ast syn::TraitItem) {
+        let attrs: &[Attribute] = match node {
+            syn::TraitItem::Const(item) => &item.attrs,
+            syn::TraitItem::Fn(item) => &item.attrs,
+            syn::TraitItem::Type(item) => &item.attrs,
+            syn::TraitItem::Macro(item) => &item.attrs,
+            _ => &[],
+        };
+        self.record(attrs, node);
+        visit::visit_trait_item(self, node);
+    }
+}
+
+/// The text of an item's outer `///` or `#[doc = "..."]` attributes, one line each
+fn doc_text(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs.iter()
+        .filter(|attr| matches!(attr.style, AttrStyle::Outer) && attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(doc) => match &doc.value {
+                Expr::Lit(syn::ExprLit { lit: Lit::Str(text), .. }) => Some(text.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        // `/// text` is stored as " text"
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect();
+
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
@@ -0,0 +1,64 @@
+//! Detecting how an example file is meant to be run
+
+use crate::EntrySignature;
+use syn::{Item, ReturnType, Type};
+
+/// Describe the `fn main` of a Rust source, if it has one
+///
+/// Parsed with `syn` when possible; sources that don't parse as a file, such as README
+/// snippets made of bare statements, fall back to scanning for a `fn main` line.
+pub(crate) fn detect(content: &str) -> EntrySignature {
+    let Ok(file) = syn::parse_file(content) else {
+        return scan_lines(content);
+    };
+
+    let main = file.items.iter().find_map(|item| match item {
+        Item::Fn(function) if function.sig.ident == "main" => Some(function),
+        _ => None,
+    });
+    let Some(main) = main else {
+        return EntrySignature::default();
+    };
+
+    // `#[tokio::main]`, `#[async_std::main]`, ...: any attribute path ending in `main`
+    let runtime = main.attrs.iter()
+        .map(|attr| attr.path())
+        .find(|path| path.segments.len() > 1 && path.segments.last().is_some_and(|segment| segment.ident == "main"))
+        .map(|path| {
+            path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>().join("::")
+        });
+
+    EntrySignature {
+        has_main: true,
+        is_async: main.sig.asyncness.is_some(),
+        returns_result: returns_result(&main.sig.output),
+        runtime,
+    }
+}
+
+/// Whether a return type is some `Result`, e.g. `Result<(), Box<dyn Error>>` or `anyhow::Result<()>`
+fn returns_result(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => match ty.as_ref() {
+            Type::Path(path) => path.path.segments.last().is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    }
+}
+
+/// Line-based fallback for sources `syn` can't parse
+fn scan_lines(content: &str) -> EntrySignature {
+    let Some(line) = content.lines().map(str::trim).find(|line| {
+        line.starts_with("fn main(") || line.starts_with("async fn main(") || line.starts_with("pub fn main(")
+    }) else {
+        return EntrySignature::default();
+    };
+
+    EntrySignature {
+        has_main: true,
+        is_async: line.starts_with("async"),
+        returns_result: line.contains("-> Result") || line.contains("::Result<"),
+        runtime: None,
+    }
+}
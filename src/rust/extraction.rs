@@ -1,57 +1,349 @@
 //! Crate extraction to local cache
 
 use crate::{Result, EgError};
+use crate::metrics::{self, Event};
+use super::registry;
+use super::search::{is_example_path, normalize_path_separators};
 use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
 use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
 use tar::Archive;
+use tokio::sync::Semaphore;
+
+/// Download endpoint for `.crate` archives on crates.io
+const CRATES_IO_DOWNLOADS: &str = "https://static.crates.io/crates";
+
+/// Environment variable overriding the download endpoint, e.g. for a mirror
+const DOWNLOAD_URL_ENV: &str = "EG_CRATES_DL_URL";
+
+/// Downloads allowed in flight at once unless `Eg::set_max_concurrent_downloads` says otherwise
+const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 8;
+
+/// Permits every download in the process queues behind, created on first use
+static DOWNLOAD_PERMITS: RwLock<Option<Arc<Semaphore>>> = RwLock::new(None);
+
+/// Limit how many `.crate` downloads run at once across the process (at least one)
+///
+/// Downloads already in flight keep their permits from the previous limit.
+pub(crate) fn set_max_concurrent_downloads(max: usize) {
+    if let Ok(mut permits) = DOWNLOAD_PERMITS.write() {
+        *permits = Some(Arc::new(Semaphore::new(max.max(1))));
+    }
+}
+
+/// The semaphore downloads currently acquire their permits from
+fn download_permits() -> Arc<Semaphore> {
+    if let Ok(permits) = DOWNLOAD_PERMITS.read()
+        && let Some(semaphore) = permits.as_ref()
+    {
+        return semaphore.clone();
+    }
+    match DOWNLOAD_PERMITS.write() {
+        Ok(mut permits) => permits
+            .get_or_insert_with(|| Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_DOWNLOADS)))
+            .clone(),
+        Err(_) => Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_DOWNLOADS)),
+    }
+}
 
 /// Handles extraction of .crate files to local cache
-pub struct CrateExtractor;
+pub struct CrateExtractor {
+    user_agent: Option<String>,
+    base_url: Option<String>,
+}
+
+impl Default for CrateExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl CrateExtractor {
     pub fn new() -> Self {
-        Self
+        Self { user_agent: None, base_url: None }
+    }
+
+    /// Download archives from this endpoint instead of crates.io's
+    ///
+    /// URLs are formed as `{base_url}/{crate}/{crate}-{version}.crate`. Without this,
+    /// `EG_CRATES_DL_URL` is used if set, then `https://static.crates.io/crates`.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    /// The download endpoint in effect: configured, then from the environment, then crates.io
+    fn download_base_url(&self) -> String {
+        self.base_url.clone()
+            .or_else(|| std::env::var(DOWNLOAD_URL_ENV).ok().filter(|url| !url.is_empty()))
+            .unwrap_or_else(|| CRATES_IO_DOWNLOADS.to_string())
+    }
+
+    /// Identify downloads with this user-agent instead of the process default
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
     }
 
     /// Extract a cached .crate file to the extraction cache
+    #[tracing::instrument(level = "debug", skip(self), fields(crate_path = %crate_path.display()))]
     pub async fn extract_crate_to_cache(
         &self,
         crate_path: &Path,
         extraction_path: &PathBuf,
     ) -> Result<PathBuf> {
+        let bytes = fs::read(crate_path)?;
+        self.extract_bytes_to_cache(&bytes, extraction_path).await
+    }
+
+    /// Download the `.crate` archive for a crate version from crates.io (or the configured endpoint)
+    #[tracing::instrument(level = "debug", skip(self), fields(bytes = tracing::field::Empty))]
+    pub async fn download_crate(&self, crate_name: &str, version: &str) -> Result<Vec<u8>> {
+        let bytes = self.download_crate_from(&self.download_base_url(), crate_name, version).await?;
+        tracing::Span::current().record("bytes", bytes.len());
+        Ok(bytes)
+    }
+
+    /// Download the `.crate` archive for a crate version from a registry's download endpoint
+    ///
+    /// `base_url` is the prefix of `{base_url}/{crate}/{crate}-{version}.crate` URLs. A 404 is
+    /// reported as `EgError::VersionNotFound`; other failed statuses as `DownloadHttpStatus`.
+    pub async fn download_crate_from(&self, base_url: &str, crate_name: &str, version: &str) -> Result<Vec<u8>> {
+        let download_url = crate_url(base_url, crate_name, version);
+
+        let bytes = self.download_url(&download_url).await
+            .map_err(|e| not_found_as_missing_version(e, crate_name, version))?;
+        metrics::record(Event::Download);
+        Ok(bytes)
+    }
+
+    /// Download a `.crate` archive from any URL, such as a registry mirror
+    ///
+    /// Waits for a slot if the process-wide download limit is reached. Timeouts, connection
+    /// failures, 429s, and 5xx statuses are retried with backoff.
+    pub async fn download_url(&self, url: &str) -> Result<Vec<u8>> {
+        // The semaphore is never closed, so this holds a permit until the body is read
+        let _permit = download_permits().acquire_owned().await;
+        registry::with_backoff(|| async {
+            let response = self.request(reqwest::Method::GET, url).await?;
+            Ok(response.bytes().await?.to_vec())
+        }).await
+    }
+
+    /// Size in bytes of a crate version's `.crate` archive on crates.io (or the configured endpoint), without downloading it
+    pub async fn download_size(&self, crate_name: &str, version: &str) -> Result<Option<u64>> {
+        self.download_size_from(&self.download_base_url(), crate_name, version).await
+    }
+
+    /// Size in bytes of a `.crate` archive, from the `Content-Length` of a HEAD request
+    ///
+    /// `None` if the server doesn't report a length. Statuses are reported as by `download_crate_from`.
+    pub async fn download_size_from(&self, base_url: &str, crate_name: &str, version: &str) -> Result<Option<u64>> {
+        let url = crate_url(base_url, crate_name, version);
+        let response = self.request(reqwest::Method::HEAD, &url).await
+            .map_err(|e| not_found_as_missing_version(e, crate_name, version))?;
+
+        // `Response::content_length` reflects the (empty) HEAD body, so read the header itself
+        let length = response.headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        Ok(length)
+    }
+
+    /// Send a request with the configured user-agent, failing on an unsuccessful status
+    async fn request(&self, method: reqwest::Method, url: &str) -> Result<reqwest::Response> {
+        let user_agent = self.user_agent.clone().unwrap_or_else(crate::default_user_agent);
+
+        let response = registry::http_client()
+            .request(method, url)
+            .header(reqwest::header::USER_AGENT, user_agent)
+            .send()
+            .await?;
+
+        // Keep the failed status as the error's source rather than flattening it into a message
+        let status = response.status().as_u16();
+        response
+            .error_for_status()
+            .map_err(|source| EgError::DownloadHttpStatus { status, source })
+    }
+
+    /// Extract a `.crate` file into `dest`, outside of the extraction cache
+    pub async fn extract_crate_to_dir(&self, crate_path: &Path, dest: &PathBuf) -> Result<PathBuf> {
         let file = fs::File::open(crate_path)?;
-        self.extract_from_reader(file, extraction_path).await?;
-        Ok(extraction_path.clone())
+        self.extract_from_reader(file, dest).await?;
+        Ok(dest.clone())
     }
 
-    /// Download and extract a crate to the extraction cache
-    pub async fn download_and_extract_crate(
+    /// Extract `.crate` bytes into a content-addressed directory and link `extraction_path` to it
+    ///
+    /// Extracted sources live in `content/{sha256}` next to `extraction_path`, so identical
+    /// archives requested under different names share one copy on disk, and a re-released
+    /// version with different contents never reuses a stale extraction.
+    pub async fn extract_bytes_to_cache(
         &self,
-        crate_name: &str,
-        version: &str,
-        extraction_path: &PathBuf,
+        bytes: &[u8],
+        extraction_path: &Path,
     ) -> Result<PathBuf> {
-        let download_url = format!(
-            "https://static.crates.io/crates/{}/{}-{}.crate",
-            crate_name, crate_name, version
-        );
-
-        let response = reqwest::get(&download_url).await?;
-        if !response.status().is_success() {
-            return Err(EgError::Other(format!(
-                "Failed to download crate: HTTP {}",
-                response.status()
-            )));
+        let cache_dir = extraction_path.parent().ok_or_else(|| {
+            EgError::ExtractionError(format!("Extraction path has no parent: {}", extraction_path.display()))
+        })?;
+        let content_path = self.extract_bytes_to_content(bytes, cache_dir).await?;
+
+        self.link_extraction(&content_path, extraction_path)?;
+        Ok(extraction_path.to_path_buf())
+    }
+
+    /// Extract `.crate` bytes into `cache_dir/content/{sha256}`, reusing an existing extraction
+    #[tracing::instrument(
+        level = "debug",
+        skip(self, bytes),
+        fields(bytes = bytes.len(), checksum = tracing::field::Empty),
+    )]
+    pub async fn extract_bytes_to_content(&self, bytes: &[u8], cache_dir: &Path) -> Result<PathBuf> {
+        let checksum = format!("{:x}", Sha256::digest(bytes));
+        tracing::Span::current().record("checksum", checksum.as_str());
+
+        let content_dir = cache_dir.join("content");
+        let content_path = content_dir.join(&checksum);
+
+        if content_path.exists() {
+            metrics::record(Event::CacheHit);
+        } else {
+            // Extract into a private directory and rename it into place, so neither an interrupted
+            // extraction nor a concurrent one for the same archive is ever seen half-written
+            fs::create_dir_all(&content_dir)?;
+            let partial = tempfile::Builder::new()
+                .prefix(&format!("{}.partial", checksum))
+                .tempdir_in(&content_dir)?;
+            self.extract_from_reader(bytes, &partial.path().to_path_buf()).await?;
+            match fs::rename(partial.path(), &content_path) {
+                Ok(()) => metrics::record(Event::Extraction),
+                // Another extraction of the same archive finished first; `partial` is removed on drop
+                Err(_) if content_path.exists() => metrics::record(Event::CacheHit),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(content_path)
+    }
+
+    /// Point `link` at the `target` content directory, replacing any stale link or directory
+    ///
+    /// The link is created under a temporary name and renamed over `link`, so concurrent
+    /// searches never see it missing.
+    fn link_extraction(&self, target: &Path, link: &Path) -> Result<()> {
+        if let Ok(metadata) = fs::symlink_metadata(link)
+            && metadata.is_dir()
+        {
+            fs::remove_dir_all(link)?;
+        }
+
+        static LINK_COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let mut temp_name = link.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(format!(
+            ".{}-{}.link",
+            std::process::id(),
+            LINK_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_link = link.with_file_name(temp_name);
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, &temp_link)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_dir(target, &temp_link)?;
+
+        if let Err(e) = fs::rename(&temp_link, link) {
+            let _ = fs::remove_file(&temp_link);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Lazily iterate over the example files in a `.crate` archive
+    ///
+    /// Yields each `.rs` file under an `examples` directory as a crate-relative path (the
+    /// `{crate}-{version}/` prefix is stripped) together with its contents, so callers can run
+    /// their own analysis instead of a regex search. Nothing is written to disk.
+    ///
+    /// The archive is decoded on a background thread that runs at most one file ahead of the
+    /// caller, so memory stays bounded by the largest example file. This is why the reader must
+    /// be `Send + 'static`: it is moved to that thread and consumed as iteration advances, in
+    /// archive order, and cannot be rewound. Dropping the iterator stops decoding early.
+    pub fn iter_example_files<R: Read + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> impl Iterator<Item = Result<(PathBuf, String)>> {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(0);
+
+        std::thread::spawn(move || {
+            let mut archive = Archive::new(GzDecoder::new(reader));
+            let entries = match archive.entries() {
+                Ok(entries) => entries,
+                Err(e) => {
+                    let _ = sender.send(Err(EgError::ArchiveError {
+                        context: "Failed to read archive".to_string(),
+                        source: e,
+                    }));
+                    return;
+                }
+            };
+
+            for entry in entries {
+                let item = match entry {
+                    Ok(entry) => Self::read_example_entry(entry),
+                    Err(e) => Some(Err(EgError::ArchiveError {
+                        context: "Failed to read archive entry".to_string(),
+                        source: e,
+                    })),
+                };
+
+                if let Some(item) = item {
+                    // A send error means the iterator was dropped, so stop decoding
+                    if sender.send(item).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        receiver.into_iter()
+    }
+
+    /// Read an archive entry if it is an example file, returning `None` for anything else
+    fn read_example_entry<R: Read>(mut entry: tar::Entry<'_, R>) -> Option<Result<(PathBuf, String)>> {
+        let path = match entry.path() {
+            Ok(path) => path.into_owned(),
+            Err(e) => return Some(Err(EgError::ArchiveError {
+                context: "Invalid archive path".to_string(),
+                source: e,
+            })),
+        };
+
+        // Strip the `{crate}-{version}/` directory that wraps every packaged crate
+        let relative_path = normalize_path_separators(&path.components().skip(1).collect::<PathBuf>());
+        let is_example = is_example_path(&relative_path);
+        let is_rust = relative_path.extension().is_some_and(|ext| ext == "rs");
+        if !entry.header().entry_type().is_file() || !is_example || !is_rust {
+            return None;
         }
 
-        let bytes = response.bytes().await?;
-        self.extract_from_reader(std::io::Cursor::new(bytes), extraction_path).await?;
-        Ok(extraction_path.clone())
+        let mut contents = String::new();
+        match entry.read_to_string(&mut contents) {
+            Ok(_) => Some(Ok((relative_path, contents))),
+            Err(e) => Some(Err(EgError::ArchiveError {
+                context: format!("Failed to read {}", relative_path.display()),
+                source: e,
+            })),
+        }
     }
 
     /// Extract from any reader to the specified directory
+    #[tracing::instrument(level = "debug", skip(self, reader), fields(extraction_path = %extraction_path.display()))]
     async fn extract_from_reader<R: Read>(
         &self,
         reader: R,
@@ -65,7 +357,10 @@ impl CrateExtractor {
 
         // Extract all files
         archive.unpack(extraction_path)
-            .map_err(|e| EgError::ExtractionError(format!("Failed to extract archive: {}", e)))?;
+            .map_err(|e| EgError::ArchiveError {
+                context: "Failed to extract archive".to_string(),
+                source: e,
+            })?;
 
         // The archive typically contains a single directory with the crate name-version
         // We want to flatten this structure
@@ -126,3 +421,19 @@ impl CrateExtractor {
         Ok(())
     }
 }
+
+/// The URL of a crate version's archive under a registry download endpoint
+fn crate_url(base_url: &str, crate_name: &str, version: &str) -> String {
+    format!("{}/{}/{}-{}.crate", base_url.trim_end_matches('/'), crate_name, crate_name, version)
+}
+
+/// Report a 404 for a crate archive as the version not existing
+fn not_found_as_missing_version(e: EgError, crate_name: &str, version: &str) -> EgError {
+    match e {
+        EgError::DownloadHttpStatus { status: 404, .. } => EgError::VersionNotFound {
+            crate_name: crate_name.to_string(),
+            version: version.to_string(),
+        },
+        e => e,
+    }
+}
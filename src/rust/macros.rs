@@ -0,0 +1,56 @@
+//! Locating macro invocations, derives, and attribute macros in Rust sources
+
+use proc_macro2::{LineColumn, Span};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{Attribute, Path, Token};
+
+/// Where the macro `name` is used in `content`, as (start, end) locations sorted by position
+///
+/// Finds bang invocations (`name!(...)`, also through a path like `serde_json::json!`),
+/// `#[derive(name)]` entries, and attribute macros (`#[name]`). Macros inside another macro's
+/// input are opaque tokens and aren't found. `None` if the file doesn't parse.
+pub(crate) fn invocation_spans(content: &str, name: &str) -> Option<Vec<(LineColumn, LineColumn)>> {
+    let file = syn::parse_file(content).ok()?;
+    let mut uses = MacroUses { name, spans: Vec::new() };
+    uses.visit_file(&file);
+
+    let mut spans: Vec<_> = uses.spans.iter().map(|span| (span.start(), span.end())).collect();
+    spans.sort_by_key(|(start, _)| (start.line, start.column));
+    Some(spans)
+}
+
+struct MacroUses<'a> {
+    name: &'a str,
+    spans: Vec<Span>,
+}
+
+impl MacroUses<'_> {
+    fn is_named(&self, path: &Path) -> bool {
+        path.segments.last().is_some_and(|segment| segment.ident == self.name)
+    }
+}
+
+impl<'ast> Visit<'ast> for MacroUses<'_> {
+    fn visit_macro(&mut self, node: &'ast syn::Macro) {
+        if self.is_named(&node.path) {
+            self.spans.push(node.span());
+        }
+        visit::visit_macro(self, node);
+    }
+
+    fn visit_attribute(&mut self, node: &'ast Attribute) {
+        if node.path().is_ident("derive") {
+            let derived = node.parse_args_with(Punctuated::<Path, Token![,]>::parse_terminated);
+            for path in derived.iter().flatten() {
+                if self.is_named(path) {
+                    self.spans.push(path.span());
+                }
+            }
+        } else if self.is_named(node.path()) {
+            self.spans.push(node.span());
+        }
+        visit::visit_attribute(self, node);
+    }
+}
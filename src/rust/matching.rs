@@ -0,0 +1,104 @@
+//! Searching every crate whose name matches a pattern
+
+use crate::{Result, SearchResult};
+use super::RustCrateSearch;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Default number of matching crates searched
+const DEFAULT_MAX_CRATES: usize = 10;
+
+/// Hard upper bound on `max_crates`, to keep one query from fetching half the registry
+const MAX_CRATES_LIMIT: usize = 50;
+
+/// Default number of crates searched at once
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Builder for searching examples across all crates whose names match a glob
+#[derive(Clone)]
+pub struct RustCratesMatching {
+    name_glob: String,
+    template: RustCrateSearch,
+    max_crates: usize,
+    concurrency: usize,
+}
+
+/// Search result for one of the crates matched by name
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MatchedCrateResult {
+    /// Name of the crate that was searched
+    pub crate_name: String,
+    /// The crate's search result
+    pub result: SearchResult,
+}
+
+impl RustCratesMatching {
+    /// Create a new search over crates whose names match `name_glob`, e.g. `tokio-*`
+    pub fn new(name_glob: &str) -> Self {
+        Self {
+            name_glob: name_glob.to_string(),
+            template: RustCrateSearch::new(""),
+            max_crates: DEFAULT_MAX_CRATES,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Specify a regex pattern to search for within each crate
+    pub fn pattern(mut self, pattern: &str) -> Result<Self> {
+        self.template = self.template.pattern(pattern)?;
+        Ok(self)
+    }
+
+    /// Apply every other option of `search` to each per-crate search; its crate name is ignored
+    pub fn template(mut self, search: RustCrateSearch) -> Self {
+        self.template = search;
+        self
+    }
+
+    /// Search at most `max` of the most downloaded matching crates (default 10, at most 50)
+    pub fn max_crates(mut self, max: usize) -> Self {
+        self.max_crates = max.min(MAX_CRATES_LIMIT);
+        self
+    }
+
+    /// Search at most `concurrency` crates at once (default 4)
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Find the matching crates and search each, most downloaded first
+    ///
+    /// Crates whose search fails (e.g. a crate that can't be downloaded) are skipped and logged,
+    /// so one bad crate doesn't fail the whole query.
+    pub async fn search(self) -> Result<Vec<MatchedCrateResult>> {
+        let mut crates = self.template.discovery().find_by_name(&self.name_glob).await?;
+        crates.truncate(self.max_crates);
+
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let mut tasks = JoinSet::new();
+        for (rank, summary) in crates.into_iter().enumerate() {
+            let search = self.template.clone().crate_name(&summary.name);
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                (rank, summary.name, search.search().await)
+            });
+        }
+
+        let mut results = Vec::new();
+        while let Some(joined) = tasks.join_next().await {
+            let Ok((rank, crate_name, result)) = joined else {
+                continue;
+            };
+            match result {
+                Ok(result) => results.push((rank, MatchedCrateResult { crate_name, result })),
+                Err(e) => tracing::debug!("Skipping crate {}: {}", crate_name, e),
+            }
+        }
+
+        results.sort_by_key(|(rank, _)| *rank);
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+}
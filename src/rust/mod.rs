@@ -1,24 +1,158 @@
 //! Rust-specific example searching functionality
 
-use crate::{Result, SearchResult};
-use regex::Regex;
+use crate::{CheckoutSource, DependencyMatches, DocBlockKind, DownloadedCrate, EgError, Example, ExampleDiff, ModifiedExample, Result, SearchResult, SearchTimings, WarmedCrate};
+use regex::{Regex, RegexBuilder};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use std::future::Future;
+use tokio::sync::Semaphore;
 
 mod version;
 mod cache;
 mod extraction;
 mod search;
+mod discovery;
+mod result_cache;
+mod matching;
+mod simplify;
+mod macros;
+mod docs;
+mod test_fns;
+mod entry;
+mod aliases;
+mod registry;
+#[cfg(feature = "check")]
+mod check;
 
-pub use version::VersionResolver;
+/// Register a process-wide crate alias, see `Eg::add_crate_alias`
+pub(crate) fn add_crate_alias(alias: &str, crate_name: &str) {
+    aliases::add(alias, crate_name)
+}
+
+/// Check out every (name, version constraint) pair concurrently, configured like `template`, see `Eg::warm_cache`
+pub(crate) async fn warm_cache(template: &RustCrateSearch, names_and_versions: &[(String, String)]) -> Vec<WarmedCrate> {
+    let tasks: Vec<_> = names_and_versions
+        .iter()
+        .map(|(name, version)| {
+            let mut search = template.clone().crate_name(name);
+            if !version.is_empty() {
+                search = search.version(version);
+            }
+            (name.clone(), tokio::spawn(search.checkout_only()))
+        })
+        .collect();
+
+    let mut warmed = Vec::with_capacity(tasks.len());
+    for (crate_name, task) in tasks {
+        let result = task.await.unwrap_or_else(|e| Err(e.into()));
+        warmed.push(WarmedCrate { crate_name, result });
+    }
+    warmed
+}
+
+/// Compile a search pattern, optionally letting `.` match newlines
+///
+/// `^` and `$` always match at line boundaries, as they would in a line-by-line search, and
+/// `crlf` lets them treat `\r\n` as a line ending in files from Windows.
+fn compile_pattern(pattern: &str, dot_matches_newline: bool) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .dot_matches_new_line(dot_matches_newline)
+        .multi_line(true)
+        .crlf(true)
+        .build()
+        .map_err(|e| crate::EgError::Other(format!("Invalid regex pattern: {}", e)))
+}
+
+/// Describe how a Rust source is meant to be run, see `EntrySignature`
+pub(crate) fn entry_signature(content: &str) -> crate::EntrySignature {
+    entry::detect(content)
+}
+
+/// Upper bound on the number of dependencies searched by `follow_deps`
+const MAX_FOLLOWED_CRATES: usize = 16;
+
+/// Name suffixes that mark a direct dependency as a companion of the facade, see `include_companions`
+const COMPANION_SUFFIXES: &[&str] = &["core", "derive", "macros", "impl"];
+
+/// Upper bound on the number of versions searched by `all_matching_versions`, newest kept
+const MAX_MATCHING_VERSIONS: usize = 16;
+
+/// Number of versions `search_versions` searches at once
+const VERSION_SEARCH_CONCURRENCY: usize = 4;
+
+/// Repository hosts the planned example fallback will cover; nothing searches them yet
+const SUPPORTED_REPO_HOSTS: &[&str] = &["github.com"];
+
+/// Upper bound on the number of example snippets compile-checked by `verify_compiles`
+#[cfg(feature = "check")]
+const MAX_CHECKED_SNIPPETS: usize = 8;
+
+pub use tokio_util::sync::CancellationToken;
+pub use version::{AvailableVersion, DependencyKind, ManifestDetails, ResolvedCrate, VersionResolver};
 pub use cache::CacheManager;
 pub use extraction::CrateExtractor;
-pub use search::CrateSearcher;
+pub(crate) use extraction::set_max_concurrent_downloads;
+pub use search::{CrateMatches, CrateSearcher, MatchMode, Matcher, SearchRange, SearchScope, normalize_path_separators};
+use search::TokenQuery;
+pub use discovery::{CrateDiscovery, CrateSummary};
+pub use result_cache::ResultCache;
+pub use matching::{MatchedCrateResult, RustCratesMatching};
+#[cfg(feature = "check")]
+pub use check::SnippetChecker;
 
 /// Builder for searching Rust crate examples
+///
+/// A configured builder is `Clone + Send + Sync` and cheap to clone (compiled patterns are
+/// shared), so servers can keep one around and `clone()` it for each search, retry, or task.
+#[derive(Clone)]
 pub struct RustCrateSearch {
     crate_name: String,
+    resolve_aliases: bool,
+    alias: Option<String>,
     version_spec: Option<String>,
-    pattern: Option<Regex>,
+    resolved_version: Option<String>,
+    all_matching_versions: bool,
+    patterns: Vec<Arc<Regex>>,
+    match_mode: MatchMode,
     context_lines: usize,
+    match_filenames: bool,
+    dot_matches_newline: bool,
+    max_matches_per_file: Option<usize>,
+    max_total_bytes: Option<u64>,
+    first_match_only: bool,
+    follow_deps: u8,
+    include_companions: bool,
+    crate_file: Option<PathBuf>,
+    crate_dir: Option<PathBuf>,
+    current_crate: bool,
+    scope: SearchScope,
+    extensions: Vec<String>,
+    include_hidden: bool,
+    attach_doc_comments: bool,
+    include_test_context: bool,
+    record_file_list: bool,
+    code_only: bool,
+    token_query: Option<TokenQuery>,
+    matcher: Option<Arc<dyn Matcher>>,
+    excluded_doc_blocks: Vec<DocBlockKind>,
+    simplify_snippets: bool,
+    stable_only: bool,
+    result_cache: bool,
+    user_agent: Option<String>,
+    download_base_url: Option<String>,
+    api_base_url: Option<String>,
+    cache_dirs: Option<(PathBuf, PathBuf)>,
+    locked: bool,
+    dep_kind: Option<DependencyKind>,
+    manifest_path: Option<PathBuf>,
+    absolute_paths: bool,
+    collect_timings: bool,
+    cancel_token: Option<CancellationToken>,
+    #[cfg(feature = "check")]
+    verify_compiles: bool,
 }
 
 impl RustCrateSearch {
@@ -26,58 +160,1418 @@ impl RustCrateSearch {
     pub fn new(name: &str) -> Self {
         Self {
             crate_name: name.to_string(),
+            resolve_aliases: false,
+            alias: None,
             version_spec: None,
-            pattern: None,
+            resolved_version: None,
+            all_matching_versions: false,
+            patterns: Vec::new(),
+            match_mode: MatchMode::default(),
             context_lines: 2, // Default context
+            match_filenames: false,
+            dot_matches_newline: false,
+            max_matches_per_file: None,
+            max_total_bytes: None,
+            first_match_only: false,
+            follow_deps: 0,
+            include_companions: false,
+            crate_file: None,
+            crate_dir: None,
+            current_crate: false,
+            scope: SearchScope::default(),
+            extensions: vec!["rs".to_string()],
+            include_hidden: false,
+            attach_doc_comments: false,
+            include_test_context: false,
+            record_file_list: false,
+            code_only: false,
+            token_query: None,
+            matcher: None,
+            excluded_doc_blocks: Vec::new(),
+            simplify_snippets: false,
+            stable_only: true,
+            result_cache: false,
+            user_agent: None,
+            download_base_url: None,
+            api_base_url: None,
+            cache_dirs: None,
+            locked: false,
+            dep_kind: None,
+            manifest_path: None,
+            absolute_paths: false,
+            collect_timings: false,
+            cancel_token: None,
+            #[cfg(feature = "check")]
+            verify_compiles: false,
+        }
+    }
+
+    /// Create a new search over a local `.crate` archive, without any registry interaction
+    ///
+    /// The version is taken from a `{crate}-{version}.crate` file name, falling back to the
+    /// archive's own `Cargo.toml`. Any `.version()` constraint is ignored.
+    pub fn from_crate_file(path: &Path) -> Self {
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let name = parse_crate_file_stem(&stem).map_or(stem, |(name, _)| name);
+
+        let mut search = Self::new(&name);
+        search.crate_file = Some(path.to_path_buf());
+        search
+    }
+
+    /// Create a new search over an already-extracted source tree, without any registry interaction
+    ///
+    /// The tree is searched in place with the usual example and source filters. The result's
+    /// `version` is empty and its `checkout_path` is `root`. Any `.version()` constraint is ignored.
+    pub fn from_dir(root: &Path) -> Self {
+        let name = root.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+        let mut search = Self::new(&name);
+        search.crate_dir = Some(root.to_path_buf());
+        search
+    }
+
+    /// Create a new search over the package of the project being developed, in place
+    ///
+    /// The package is read from the project containing the current directory (or `manifest_path`)
+    /// when the search runs, and its `examples/` and `src/` are searched on disk.
+    pub fn current_crate() -> Self {
+        let mut search = Self::new("");
+        search.current_crate = true;
+        search
+    }
+
+    /// Point a configured search at a different crate, keeping every other option
+    pub(crate) fn crate_name(mut self, name: &str) -> Self {
+        self.alias = None;
+        self.crate_name = name.to_string();
+        self.apply_alias();
+        self
+    }
+
+    /// Treat conceptual names like `json` or `http-client` as the crate they usually mean
+    ///
+    /// Uses a small built-in table (e.g. `json` → `serde_json`, `http-client` → `reqwest`)
+    /// plus any aliases added with `Eg::add_crate_alias`, which take precedence. The name
+    /// as given is reported in `SearchResult::alias` when a substitution happens.
+    pub fn resolve_aliases(mut self, enabled: bool) -> Self {
+        self.resolve_aliases = enabled;
+        self.apply_alias();
+        self
+    }
+
+    /// Substitute an aliased crate name, or restore the name as given when aliases are off
+    fn apply_alias(&mut self) {
+        if let Some(original) = self.alias.take() {
+            self.crate_name = original;
+        }
+
+        let registry_crate = self.crate_file.is_none() && self.crate_dir.is_none() && !self.current_crate;
+        if self.resolve_aliases
+            && registry_crate
+            && let Some(target) = aliases::lookup(&self.crate_name)
+        {
+            self.alias = Some(std::mem::replace(&mut self.crate_name, target));
         }
     }
 
     /// Specify a version constraint (e.g., "^1.0", "=1.2.3")
+    ///
+    /// `"latest"` and `"*"` select the newest published version, ignoring the version the
+    /// current project depends on.
     pub fn version(mut self, version: &str) -> Self {
         self.version_spec = Some(version.to_string());
         self
     }
 
+    /// Use this exact version without consulting the registry or the current project
+    ///
+    /// For repeated searches of a version resolved earlier (e.g. with `resolve_only`), this saves
+    /// a crates.io round-trip per search. The crate name must then be the published package name.
+    /// Fails if `version` isn't a valid semver version.
+    pub fn resolved_version(mut self, version: &str) -> Result<Self> {
+        let version = semver::Version::parse(version.trim())?;
+        self.resolved_version = Some(version.to_string());
+        Ok(self)
+    }
+
+    /// Make `search_versions` search every version matching the constraint, not just the newest
+    ///
+    /// Yanked versions are skipped, and at most the 16 newest matches are searched.
+    pub fn all_matching_versions(mut self, enabled: bool) -> Self {
+        self.all_matching_versions = enabled;
+        self
+    }
+
     /// Specify a regex pattern to search for within the crate
     pub fn pattern(mut self, pattern: &str) -> Result<Self> {
-        let regex = Regex::new(pattern)
-            .map_err(|e| crate::EgError::Other(format!("Invalid regex pattern: {}", e)))?;
-        self.pattern = Some(regex);
+        self.patterns = vec![Arc::new(self.build_regex(pattern)?)];
+        self.match_mode = MatchMode::default();
+        self.token_query = None;
+        Ok(self)
+    }
+
+    /// Search for uses of the identifier `name`, with no false positives from comments, strings, or substrings
+    ///
+    /// The most precise way to find an API: Rust files are tokenized and only identifier tokens
+    /// exactly equal to `name` match, so `find_ident("spawn")` skips `spawn_blocking` and
+    /// `// spawn`. Files that fail to tokenize, and markdown, are searched for `\bname\b` instead.
+    pub fn find_ident(mut self, name: &str) -> Result<Self> {
+        self.patterns = vec![Arc::new(self.build_regex(&format!(r"\b{}\b", regex::escape(name)))?)];
+        self.match_mode = MatchMode::default();
+        self.token_query = Some(TokenQuery::Ident(name.to_string()));
         Ok(self)
     }
 
+    /// Search for uses of the macro `name`: `name!(...)`, `#[derive(name)]`, or `#[name]`
+    ///
+    /// Rust files are parsed with `syn`, so an invocation whose delimiters span several lines is
+    /// one match covering all of them, and a function that shares the macro's name never matches.
+    /// A trailing `!` in `name` is ignored. Files that fail to parse, and markdown, are searched
+    /// for `\bname\s*!` instead.
+    pub fn find_macro(mut self, name: &str) -> Result<Self> {
+        let name = name.trim_end_matches('!');
+        self.patterns = vec![Arc::new(self.build_regex(&format!(r"\b{}\s*!", regex::escape(name)))?)];
+        self.match_mode = MatchMode::default();
+        self.token_query = Some(TokenQuery::Macro(name.to_string()));
+        Ok(self)
+    }
+
+    /// Replace the regex search with custom match logic, such as a tree-sitter query
+    ///
+    /// The matcher sees every file the search would read (Rust sources, plus markdown with
+    /// `include_markdown`) and its spans go through the usual pipeline: context lines,
+    /// example/other categorization, and per-file and byte budgets. Patterns, `find_ident`,
+    /// `find_macro`, and `code_only` are ignored while a matcher is set. Searches with a custom
+    /// matcher are never answered from the result cache, since the matcher can't be compared.
+    pub fn matcher(mut self, matcher: Box<dyn Matcher>) -> Self {
+        self.matcher = Some(Arc::from(matcher));
+        self
+    }
+
+    /// Search for several regex patterns, combined according to `mode`
+    ///
+    /// With `MatchMode::AllInFile`, files missing any one pattern are skipped entirely.
+    pub fn patterns(mut self, patterns: &[&str], mode: MatchMode) -> Result<Self> {
+        self.patterns = patterns
+            .iter()
+            .map(|pattern| self.build_regex(pattern).map(Arc::new))
+            .collect::<Result<_>>()?;
+        self.match_mode = mode;
+        self.token_query = None;
+        Ok(self)
+    }
+
+    /// Use a precompiled regex, shared rather than recompiled when searching many crates
+    pub fn pattern_arc(mut self, pattern: Arc<Regex>) -> Self {
+        self.patterns = vec![pattern];
+        self.match_mode = MatchMode::default();
+        self.token_query = None;
+        self
+    }
+
+    /// The compiled pattern this search will use, if any (the first, when several were given)
+    pub fn compiled_pattern(&self) -> Option<&Arc<Regex>> {
+        self.patterns.first()
+    }
+
+    /// Compile a pattern with this search's regex options
+    fn build_regex(&self, pattern: &str) -> Result<Regex> {
+        compile_pattern(pattern, self.dot_matches_newline)
+    }
+
+    /// Let `.` match newlines and allow matches to span multiple lines
+    ///
+    /// Multi-line matches are reported at their starting line, with every matched line in `line_content`.
+    /// Patterns already set are recompiled, so this fails if one no longer compiles.
+    pub fn dot_matches_newline(mut self, enabled: bool) -> Result<Self> {
+        // Recompile already-set patterns so the option works regardless of call order
+        if enabled != self.dot_matches_newline {
+            self.patterns = self.patterns
+                .iter()
+                .map(|regex| compile_pattern(regex.as_str(), enabled).map(Arc::new))
+                .collect::<Result<_>>()?;
+        }
+        self.dot_matches_newline = enabled;
+        Ok(self)
+    }
+
+    /// Limit how many matches any single file contributes
+    ///
+    /// Keeps one large file from crowding out the rest; files that hit the limit are listed in
+    /// `SearchResult::truncated_files`.
+    pub fn max_matches_per_file(mut self, max: usize) -> Self {
+        self.max_matches_per_file = Some(max);
+        self
+    }
+
+    /// Report at most one match per example file: its first occurrence
+    ///
+    /// For a quick "which examples use X" overview. Unlike `max_matches_per_file(1)`, this only
+    /// applies to examples and doesn't list the files in `truncated_files`, since nothing was
+    /// cut short unexpectedly.
+    pub fn first_match_only(mut self, enabled: bool) -> Self {
+        self.first_match_only = enabled;
+        self
+    }
+
+    /// Stop reading files before the total read would exceed `max` bytes
+    ///
+    /// A hard ceiling on the work done for untrusted or pathological crates, e.g. ones with
+    /// thousands of generated examples. Files left unread set `SearchResult::truncated`; the
+    /// matches from files already read are still returned.
+    pub fn max_total_bytes(mut self, max: u64) -> Self {
+        self.max_total_bytes = Some(max);
+        self
+    }
+
+    /// When the crate itself has no matches, also search its dependencies up to `depth` levels deep
+    ///
+    /// Helps with facade crates that re-export another crate's API. Only normal dependencies
+    /// are followed, at most 16 crates in total; path dependencies are searched in place. Matches
+    /// are grouped per dependency in `SearchResult::dependency_matches`.
+    pub fn follow_deps(mut self, depth: u8) -> Self {
+        self.follow_deps = depth;
+        self
+    }
+
+    /// Also search the crate's companion packages, such as `serde_derive` for `serde`
+    ///
+    /// A companion is a direct dependency named after the crate plus a `-core`, `-derive`,
+    /// `-macros`, or `-impl` suffix (`-` or `_` separated). Unlike `follow_deps`, companions are
+    /// searched even when the crate itself has matches; theirs land in
+    /// `SearchResult::companion_matches`, attributed to the companion.
+    pub fn include_companions(mut self, enabled: bool) -> Self {
+        self.include_companions = enabled;
+        self
+    }
+
+    /// Also search `*.md` files at the crate root, such as README.md and CHANGELOG.md
+    ///
+    /// These matches land in `other_matches` tagged `MatchSource::Docs`, and their context
+    /// is widened so fenced code blocks are never split.
+    pub fn include_markdown(mut self, enabled: bool) -> Self {
+        self.scope = self.scope.set_markdown(enabled);
+        self
+    }
+
+    /// Whether to search the crate's `build.rs` (default `true`)
+    ///
+    /// Build script matches land in `other_matches` tagged `MatchSource::Build`, for digging into
+    /// how codegen-heavy crates generate their sources. Pass `false` to leave the script out.
+    pub fn include_build_scripts(mut self, enabled: bool) -> Self {
+        self.scope = self.scope.set_build_scripts(enabled);
+        self
+    }
+
+    /// Choose which parts of the crate to search (default: examples, sources, tests, and the build script)
+    ///
+    /// This replaces any earlier `include_markdown` or `include_build_scripts` setting, e.g.
+    /// `SearchScope::examples().with_tests()` searches only examples and integration tests.
+    pub fn scope(mut self, scope: SearchScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Search files with these extensions instead of only Rust sources (default `["rs"]`)
+    ///
+    /// For config or data files that accompany examples, e.g. `&["rs", "toml"]`. Files under
+    /// `examples/` still count as examples whatever their extension. Identifier, macro, and
+    /// `code_only` matching only understand `.rs` files; others are matched line by line.
+    pub fn include_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|ext| ext.trim_start_matches('.').to_string()).collect();
+        self
+    }
+
+    /// Also search hidden files and directories, such as `examples/.config`, skipped by default
+    ///
+    /// Dotfiles with a searched extension are included, as are dotfiles with no extension at all.
+    pub fn include_hidden(mut self, enabled: bool) -> Self {
+        self.include_hidden = enabled;
+        self
+    }
+
+    /// Attach the doc comment of the item each match falls in, or just follows, as `Match::doc_comment`
+    ///
+    /// Rust files are parsed with `syn`. A match just below an item, such as in a trailing
+    /// comment, gets that item's doc comment; otherwise the innermost documented item, impl item,
+    /// or trait item whose span (doc comment included) covers the match's first line is used.
+    /// Files that don't parse, and non-Rust files, get no doc comments.
+    pub fn attach_doc_comments(mut self, enabled: bool) -> Self {
+        self.attach_doc_comments = enabled;
+        self
+    }
+
+    /// Attach the `#[test]` function each match falls in as `Match::test_context`
+    ///
+    /// Meant for matches in `tests/` and `#[cfg(test)]` modules, where the assertions near a call
+    /// show what it is expected to do. The whole function comes back with the matched lines
+    /// marked and the lines of `assert!`-style macros listed in `assertion_lines`. Matches
+    /// outside a test function, and files that don't parse, get none.
+    pub fn include_test_context(mut self, enabled: bool) -> Self {
+        self.include_test_context = enabled;
+        self
+    }
+
+    /// Record every file of the searched package in `SearchResult::file_list`, with its size
+    ///
+    /// For auditing what a published crate ships. The list covers the whole `.crate` archive,
+    /// not just the files that were searched, sorted by path. Only packaged sources are listed:
+    /// for local sources (`rust_dir`, path dependencies) the list stays empty.
+    pub fn record_file_list(mut self, enabled: bool) -> Self {
+        self.record_file_list = enabled;
+        self
+    }
+
+    /// Ignore matches that fall entirely within comments, string literals, or whitespace
+    ///
+    /// Useful when searching for common identifiers that are mostly mentioned in docs. Rust files
+    /// are scanned with a lightweight lexer, not a full parser, so this is a heuristic: unusual
+    /// block comments or literals produced by macros may be misjudged. Markdown is unaffected.
+    pub fn code_only(mut self, enabled: bool) -> Self {
+        self.code_only = enabled;
+        self
+    }
+
+    /// Leave out code blocks of these kinds from `readme_examples`
+    ///
+    /// E.g. `&[DocBlockKind::CompileFail]` drops blocks that show what *not* to write, so they
+    /// aren't mistaken for usage; adding `Ignore` also drops pseudocode.
+    pub fn exclude_doc_blocks(mut self, kinds: &[DocBlockKind]) -> Self {
+        self.excluded_doc_blocks = kinds.to_vec();
+        self
+    }
+
+    /// Remove `#[cfg(...)]`-gated code that doesn't apply to the host from match context
+    ///
+    /// Example files for multi-platform crates often carry a branch per platform; with this, a
+    /// snippet on Linux keeps the `unix` branch and drops the `windows` one. Predicates like
+    /// `target_os`, `target_family`, `unix`, and `windows` are evaluated for the host; ones that
+    /// depend on the build, like `feature = "..."`, are kept. `ContextLine::line_number` still
+    /// refers to the original file. Matched lines are never removed.
+    pub fn simplify_snippets(mut self, enabled: bool) -> Self {
+        self.simplify_snippets = enabled;
+        self
+    }
+
+    /// Report match and truncated file paths as absolute paths instead of crate-relative ones
+    ///
+    /// Handy when the paths are opened directly rather than displayed. Dependency matches are
+    /// joined with their own dependency's checkout path.
+    pub fn absolute_paths(mut self, enabled: bool) -> Self {
+        self.absolute_paths = enabled;
+        self
+    }
+
+    /// Record how long each phase of the search took in `SearchResult::timings`
+    ///
+    /// For diagnosing slow searches: the phases are resolve, locate, download, extract, and
+    /// search. A result-cache hit reports only its resolve time.
+    pub fn collect_timings(mut self, enabled: bool) -> Self {
+        self.collect_timings = enabled;
+        self
+    }
+
+    /// Stop the search with `EgError::Cancelled` once `token` is cancelled
+    ///
+    /// Resolution, download, extraction, and the dependency fallback all stop waiting as soon as
+    /// the token fires. Unpacking an archive and matching are synchronous, so once started they
+    /// run to completion and the token is honored at the next step.
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    /// Check whether each example match's snippet compiles against the searched crate
+    ///
+    /// Runs `cargo check` per snippet, so this is slow and capped at 8 snippets; the outcome is
+    /// recorded in `Match::compiles`. Snippets without a `fn main` are checked as its body.
+    #[cfg(feature = "check")]
+    pub fn verify_compiles(mut self, enabled: bool) -> Self {
+        self.verify_compiles = enabled;
+        self
+    }
+
+    /// Resolve to the newest stable release rather than a newer prerelease (the default)
+    ///
+    /// Only affects resolution to the latest version; explicit constraints and the current
+    /// project's pinned version are used as-is.
+    pub fn stable_only(mut self, enabled: bool) -> Self {
+        self.stable_only = enabled;
+        self
+    }
+
+    /// Reuse the result of an identical earlier search in this process
+    ///
+    /// Results are kept in `ResultCache::global()`, keyed by crate, resolved version, patterns,
+    /// options, and where the sources come from (`cache_dirs`, mirrors, user-agent). The version
+    /// is still resolved on every search, so a new release is picked up.
+    /// Searches of a directory (`Eg::rust_dir`) are never cached, since its contents may change.
+    pub fn result_cache(mut self, enabled: bool) -> Self {
+        self.result_cache = enabled;
+        self
+    }
+
+    /// Require the resolved version to match the current project's Cargo.lock pin
+    ///
+    /// Analogous to `cargo --locked`: if the version constraint resolves to anything other than
+    /// the pinned version, the search fails with a "version drift" error instead.
+    pub fn locked(mut self, enabled: bool) -> Self {
+        self.locked = enabled;
+        self
+    }
+
+    /// Only take the project's version from dependencies of this kind, e.g. `DependencyKind::Dev`
+    ///
+    /// Useful for searching the exact version of a test-only crate a workspace member declares
+    /// in `[dev-dependencies]`. If no member declares the crate with that kind, the version is
+    /// resolved as if there were no project.
+    pub fn dep_kind(mut self, kind: DependencyKind) -> Self {
+        self.dep_kind = Some(kind);
+        self
+    }
+
+    /// Resolve versions against the project at this `Cargo.toml` instead of the current directory's
+    ///
+    /// Dependencies that project takes from a path, a git repository, or a `[patch]` section are
+    /// searched in the sources cargo builds rather than the crates.io release.
+    pub fn manifest_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest_path = Some(path.into());
+        self
+    }
+
+    /// Download `.crate` archives from this endpoint instead of `https://static.crates.io/crates`
+    ///
+    /// For registry mirrors and local test servers: archives are fetched from
+    /// `{base_url}/{crate}/{crate}-{version}.crate`. The `EG_CRATES_DL_URL` environment variable
+    /// sets the same for every search that doesn't call this. Version resolution still queries
+    /// crates.io unless `resolved_version` is used.
+    pub fn download_base_url(mut self, base_url: &str) -> Self {
+        self.download_base_url = Some(base_url.to_string());
+        self
+    }
+
+    /// Send crates.io API requests, such as version lookups, to this root instead of `https://crates.io/api/v1`
+    ///
+    /// For mirrors and tests; pairs with `download_base_url`, which moves the downloads.
+    pub fn api_base_url(mut self, base_url: &str) -> Self {
+        self.api_base_url = Some(base_url.to_string());
+        self
+    }
+
+    /// Look for cargo's registry cache under `cargo_home` and keep extractions in `extraction_cache_dir`
+    ///
+    /// Defaults to `$CARGO_HOME` and the platform cache directory, e.g. `~/.cache/eg/extractions`.
+    /// For sandboxed tools and tests that must not touch the user's caches.
+    pub fn cache_dirs(mut self, cargo_home: &Path, extraction_cache_dir: &Path) -> Self {
+        self.cache_dirs = Some((cargo_home.to_path_buf(), extraction_cache_dir.to_path_buf()));
+        self
+    }
+
+    /// Identify crates.io requests made by this search with the given user-agent
+    ///
+    /// Overrides the process-wide default set with `Eg::set_user_agent`.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
     /// Set number of context lines before/after each match
+    ///
+    /// With `0`, `context_before` and `context_after` are empty without allocating and `context`
+    /// holds only the matched lines, for lean location-only queries. Markdown fences still widen it.
     pub fn context_lines(mut self, lines: usize) -> Self {
         self.context_lines = lines;
         self
     }
 
+    /// Match the pattern against example file paths instead of file contents
+    ///
+    /// Each matching example file is reported once as a whole-file match.
+    pub fn match_filenames(mut self, enabled: bool) -> Self {
+        self.match_filenames = enabled;
+        self
+    }
+
+    /// Resolve the version that `search` would use, without downloading or extracting anything
+    pub async fn resolve_only(self) -> Result<String> {
+        Ok(self.resolve_registry().await?.version)
+    }
+
+    /// Check out each (name, version constraint) pair concurrently with this search's options
+    ///
+    /// Like `Eg::warm_cache`, but every crate shares this search's configuration, such as its
+    /// `cache_dirs`, mirrors, or user-agent. The search's own crate name is ignored.
+    pub async fn warm_cache(self, names_and_versions: &[(String, String)]) -> Vec<WarmedCrate> {
+        warm_cache(&self, names_and_versions).await
+    }
+
+    /// Resolve the version and make sure the crate is extracted, returning `(version, path)`
+    ///
+    /// Reuses the extraction cache like `search`, but skips all pattern matching and example
+    /// scanning, for callers that only need the source directory.
+    pub async fn checkout_only(self) -> Result<(String, PathBuf)> {
+        let cache_manager = self.cache_manager()?;
+        let extractor = self.extractor();
+        let resolved = self.resolve().await?;
+        self.checkout(resolved, &cache_manager, &extractor, &mut SearchTimings::default()).await
+    }
+
+    /// Where the crate's source repository lives, from its `repository` metadata
+    ///
+    /// Registry crates are looked up on crates.io without downloading anything; local archives
+    /// and directories are read from their manifest. `None` if the crate doesn't declare one.
+    pub async fn repository_url(self) -> Result<Option<String>> {
+        if self.crate_file.is_none() && self.crate_dir.is_none() && !self.current_crate {
+            return self.resolver().get_repository_url(&self.crate_name).await;
+        }
+
+        let resolver = self.resolver();
+        let (_, checkout_path) = self.checkout_only().await?;
+        let details = resolver.manifest_details(&checkout_path.join("Cargo.toml"))?;
+        Ok(details.repository.filter(|url| !url.trim().is_empty()))
+    }
+
+    /// Make sure the crate is extracted and return its `src/` directory, e.g. to feed another analyzer
+    ///
+    /// Fails with `ExtractionError` if the crate has no `src/` directory, as with a crate whose
+    /// library lives elsewhere through a custom `[lib] path`.
+    pub async fn docs_source(self) -> Result<PathBuf> {
+        let crate_name = self.crate_name.clone();
+        let (_, checkout_path) = self.checkout_only().await?;
+
+        let src = checkout_path.join("src");
+        if !src.is_dir() {
+            return Err(EgError::ExtractionError(format!("Crate '{}' has no src/ directory", crate_name)));
+        }
+        Ok(src)
+    }
+
+    /// Search, writing each match to `writer` as one JSON object per line (JSON Lines)
+    ///
+    /// Matches are written once the search completes, examples first, and the writer is
+    /// flushed. The full result is returned for its version, paths, and counts.
+    pub async fn search_ndjson(self, mut writer: impl std::io::Write) -> Result<SearchResult> {
+        let result = self.search().await?;
+        result.write_json_lines(&mut writer)?;
+        writer.flush()?;
+        Ok(result)
+    }
+
+    /// Compare the example files of two exact versions of the crate
+    ///
+    /// Both versions are checked out like `checkout_only`. Files under `examples/` are reported
+    /// as added, removed, or modified; for modified files, the lines only one side has are
+    /// listed, narrowed to lines matching the search's patterns if any were set.
+    pub async fn diff_examples(self, from: &str, to: &str) -> Result<ExampleDiff> {
+        let (from_version, from_path) = self.clone().resolved_version(from)?.checkout_only().await?;
+        let (to_version, to_path) = self.clone().resolved_version(to)?.checkout_only().await?;
+
+        let searcher = CrateSearcher::new();
+        let from_files: BTreeSet<PathBuf> = searcher.example_files(&from_path)?.into_iter().collect();
+        let to_files: BTreeSet<PathBuf> = searcher.example_files(&to_path)?.into_iter().collect();
+
+        let mut diff = ExampleDiff {
+            from_version,
+            to_version,
+            added: to_files.difference(&from_files).cloned().collect(),
+            removed: from_files.difference(&to_files).cloned().collect(),
+            modified: Vec::new(),
+        };
+
+        for file_path in from_files.intersection(&to_files) {
+            let old = read_example(&from_path.join(file_path))?;
+            let new = read_example(&to_path.join(file_path))?;
+            if old == new {
+                continue;
+            }
+
+            let keep = |line: &&str| self.patterns.is_empty() || self.patterns.iter().any(|p| p.is_match(line));
+            diff.modified.push(ModifiedExample {
+                file_path: file_path.clone(),
+                removed_lines: lines_missing_from(&old, &new).into_iter().filter(keep).map(str::to_string).collect(),
+                added_lines: lines_missing_from(&new, &old).into_iter().filter(keep).map(str::to_string).collect(),
+            });
+        }
+
+        Ok(diff)
+    }
+
+    /// List the published versions of the crate, sorted from oldest to newest
+    ///
+    /// If a version constraint was given with `.version()`, only matching versions are returned.
+    pub async fn available_versions(self) -> Result<Vec<AvailableVersion>> {
+        let resolver = self.resolver();
+        let mut versions = resolver.get_available_versions(&self.crate_name).await?;
+
+        if let Some(spec) = self.version_spec.as_deref().filter(|spec| !version::is_latest_spec(spec)) {
+            let req = semver::VersionReq::parse(spec)?;
+            versions.retain(|v| req.matches(&v.version));
+        }
+
+        Ok(versions)
+    }
+
+    /// Size in bytes of the resolved version's `.crate` download, without downloading it
+    ///
+    /// Issues a HEAD request for the archive; `None` if the server doesn't report a length.
+    pub async fn download_size(self) -> Result<Option<u64>> {
+        let ResolvedCrate { name, version, .. } = self.resolve_registry().await?;
+        self.extractor().download_size(&name, &version).await
+    }
+
+    /// Fetch the crate's `.crate` archive into `dest` without searching it
+    ///
+    /// The archive is copied from cargo's cache when available and downloaded otherwise, and
+    /// written as `dest/{crate}-{version}.crate`. With `extract`, it is also unpacked into
+    /// `dest/{crate}-{version}/`.
+    pub async fn download_crate(self, dest: &Path, extract: bool) -> Result<DownloadedCrate> {
+        let ResolvedCrate { name, version, .. } = self.resolve_registry().await?;
+
+        let cache_manager = self.cache_manager()?;
+        let extractor = self.extractor();
+
+        std::fs::create_dir_all(dest)?;
+        let crate_path = dest.join(format!("{}-{}.crate", name, version));
+
+        if let Some(cached_crate_path) = cache_manager.find_cached_crate(&name, &version)? {
+            std::fs::copy(&cached_crate_path, &crate_path)?;
+        } else {
+            let bytes = extractor.download_crate(&name, &version).await?;
+            std::fs::write(&crate_path, bytes)?;
+        }
+
+        let extracted_path = if extract {
+            let extraction_path = dest.join(format!("{}-{}", name, version));
+            Some(extractor.extract_crate_to_dir(&crate_path, &extraction_path).await?)
+        } else {
+            None
+        };
+
+        Ok(DownloadedCrate {
+            version,
+            crate_path,
+            extracted_path,
+        })
+    }
+
+    /// Search once per matching version, returning the full results from oldest to newest
+    ///
+    /// Without `all_matching_versions(true)`, or for a local archive or directory, this is just
+    /// `search` in a `Vec`. Otherwise each version is checked out and searched like
+    /// `resolved_version(v).search()`, a few at a time; the first failure fails the call.
+    pub async fn search_versions(self) -> Result<Vec<SearchResult>> {
+        if !self.all_matching_versions || self.crate_file.is_some() || self.crate_dir.is_some() || self.current_crate {
+            return Ok(vec![self.search().await?]);
+        }
+
+        let mut versions = self.clone().available_versions().await?;
+        versions.retain(|v| !v.yanked);
+        if versions.is_empty() {
+            return Err(EgError::NoMatchingVersions {
+                crate_name: self.crate_name.clone(),
+                constraint: self.version_spec.clone().unwrap_or_else(|| "*".to_string()),
+            });
+        }
+        let newest = versions.split_off(versions.len().saturating_sub(MAX_MATCHING_VERSIONS));
+
+        let semaphore = Arc::new(Semaphore::new(VERSION_SEARCH_CONCURRENCY));
+        let tasks: Vec<_> = newest
+            .iter()
+            .map(|v| {
+                let search = self.clone().resolved_version(&v.version.to_string());
+                let semaphore = semaphore.clone();
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await;
+                    search?.search().await
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            results.push(task.await??);
+        }
+        Ok(results)
+    }
+
     /// Execute the search
     pub async fn search(self) -> Result<SearchResult> {
+        let mut timings = SearchTimings::default();
+
         // 1. Resolve version
-        let resolver = VersionResolver::new();
-        let version = resolver.resolve_version(&self.crate_name, self.version_spec.as_deref()).await?;
+        let started = Instant::now();
+        let resolved = self.cancellable(self.resolve()).await?;
+        timings.resolve = started.elapsed();
+
+        // Local directories and path/patched dependencies can change under the same version
+        let local_sources = self.crate_dir.is_some()
+            || resolved.as_ref().is_some_and(|resolved| resolved.source_path.is_some());
+        if !self.result_cache || local_sources || self.matcher.is_some() {
+            let result = self.search_resolved(resolved, &mut timings).await?;
+            return Ok(self.finish(result, timings));
+        }
+
+        let key = self.result_key(resolved.as_ref())?;
+        if let Some(result) = ResultCache::global().get(&key) {
+            crate::metrics::record(crate::metrics::Event::ResultCacheHit);
+            return Ok(self.finish(result, timings));
+        }
+        let result = self.search_resolved(resolved, &mut timings).await?;
+        ResultCache::global().insert(key, result.clone());
+        Ok(self.finish(result, timings))
+    }
+
+    /// Run one phase of the search, giving up with `EgError::Cancelled` if the token fires first
+    async fn cancellable<T>(&self, phase: impl Future<Output = Result<T>>) -> Result<T> {
+        let Some(token) = &self.cancel_token else {
+            return phase.await;
+        };
+        tokio::select! {
+            biased;
+            _ = token.cancelled() => Err(EgError::Cancelled),
+            result = phase => result,
+        }
+    }
+
+    /// Fail with `EgError::Cancelled` if the token has already fired
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.cancel_token {
+            Some(token) if token.is_cancelled() => Err(EgError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply presentation options to a (possibly cached) result
+    fn finish(&self, mut result: SearchResult, timings: SearchTimings) -> SearchResult {
+        result.timings = self.collect_timings.then_some(timings);
+        result.alias = self.alias.clone();
+        if !self.absolute_paths {
+            return result;
+        }
+
+        let checkout_path = result.checkout_path.clone();
+        for m in result.example_matches.iter_mut().chain(&mut result.other_matches) {
+            m.file_path = checkout_path.join(&m.file_path);
+        }
+        for file in &mut result.truncated_files {
+            *file = checkout_path.join(&*file);
+        }
+        for dependency in result.dependency_matches.iter_mut().chain(&mut result.companion_matches) {
+            for m in dependency.example_matches.iter_mut().chain(&mut dependency.other_matches) {
+                m.file_path = dependency.checkout_path.join(&m.file_path);
+            }
+        }
+        result
+    }
+
+    /// Run the search for an already-resolved crate version
+    async fn search_resolved(&self, resolved: Option<ResolvedCrate>, timings: &mut SearchTimings) -> Result<SearchResult> {
+        let cache_manager = self.cache_manager()?;
+        let extractor = self.extractor();
+
+        let local_sources = self.crate_dir.is_some()
+            || resolved.as_ref().is_some_and(|resolved| resolved.source_path.is_some());
+        let source = if local_sources { CheckoutSource::Local } else { CheckoutSource::Packaged };
+        let crate_name = resolved.as_ref().map_or_else(|| self.crate_name.clone(), |resolved| resolved.name.clone());
 
         // 2. Get or extract crate source
-        let cache_manager = CacheManager::new()?;
-        let extractor = CrateExtractor::new();
-        
-        let checkout_path = cache_manager.get_or_extract_crate(&self.crate_name, &version, &extractor).await?;
+        let (version, checkout_path) = self.cancellable(self.checkout(resolved, &cache_manager, &extractor, timings)).await?;
 
         // 3. Search the extracted crate
-        let searcher = CrateSearcher::new();
-        let (example_matches, other_matches) = if let Some(pattern) = &self.pattern {
-            searcher.search_crate(&checkout_path, pattern, self.context_lines)?
+        self.check_cancelled()?;
+        let started = Instant::now();
+        let matches = self.search_checkout(&checkout_path)?;
+
+        #[cfg(feature = "check")]
+        let matches = self.cancellable(self.check_example_matches(&crate_name, &version, matches)).await?;
+
+        let stats = matches.stats;
+        let file_list = if self.record_file_list && source == CheckoutSource::Packaged {
+            CrateSearcher::new().file_list(&checkout_path)?
         } else {
-            // No pattern - just return empty matches but still provide checkout_path
-            (Vec::new(), Vec::new())
+            Vec::new()
         };
+        // The search itself doesn't need the manifest; one cargo can't read (e.g. a missing path
+        // dependency, or no registry access) only costs the manifest-derived extras
+        let manifest = self.checkout_manifest(&checkout_path).unwrap_or_else(|e| {
+            tracing::debug!("Ignoring manifest of {}: {}", checkout_path.display(), e);
+            ManifestDetails::default()
+        });
+        let examples_excluded_from_package = source == CheckoutSource::Packaged
+            && stats.example_files == 0
+            && manifest.excludes_examples;
+        let unsupported_repo_host = manifest.repository.as_deref()
+            .filter(|_| stats.example_files == 0)
+            .and_then(unsupported_repo_host);
+
+        // 4. Search companion crates, then fall back to the other dependencies if nothing matched
+        let companions: Vec<(String, String)> = if self.include_companions {
+            manifest.dependencies.iter()
+                .filter(|(dep_name, _)| is_companion(&crate_name, dep_name))
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let companion_matches = self.cancellable(self.search_companions(&companions, &manifest, &cache_manager, &extractor)).await?;
+        let dependency_matches = if matches.example_matches.is_empty() && matches.other_matches.is_empty() {
+            let searched: Vec<&str> = companions.iter().map(|(dep_name, _)| dep_name.as_str()).collect();
+            self.cancellable(self.search_dependencies(&manifest, &searched, &cache_manager, &extractor)).await?
+        } else {
+            Vec::new()
+        };
+        timings.search += started.elapsed();
 
         Ok(SearchResult {
+            crate_name,
             version,
             checkout_path,
-            example_matches,
-            other_matches,
+            example_matches: matches.example_matches,
+            other_matches: matches.other_matches,
+            truncated_files: matches.truncated_files,
+            truncated: matches.truncated,
+            dependency_matches,
+            companion_matches,
+            stats,
+            file_list,
+            examples_excluded_from_package,
+            documentation: manifest.documentation,
+            homepage: manifest.homepage,
+            unsupported_repo_host,
+            dependencies: manifest.dependencies,
+            source,
+            timings: None,
+            alias: None,
         })
     }
+
+    /// Pick the single most representative example file from the crate, with its full contents
+    ///
+    /// Returns `None` if the crate ships no examples.
+    pub async fn best_example(self) -> Result<Option<Example>> {
+        let cache_manager = self.cache_manager()?;
+        let extractor = self.extractor();
+        let resolved = self.resolve().await?;
+        let resolved_name = resolved.as_ref().map(|resolved| resolved.name.clone());
+        let (_, checkout_path) = self.checkout(resolved, &cache_manager, &extractor, &mut SearchTimings::default()).await?;
+
+        // Local archives and directories are named by their manifest, not their file name
+        let manifest_path = checkout_path.join("Cargo.toml");
+        let crate_name = match resolved_name {
+            Some(name) => name,
+            None if manifest_path.exists() => VersionResolver::new().manifest_package(&manifest_path)?.name,
+            None => self.crate_name.clone(),
+        };
+        CrateSearcher::new().best_example(&checkout_path, &crate_name)
+    }
+
+    /// Find the example file closest to `snippet`, e.g. code a user pasted, with its full contents
+    ///
+    /// Examples are ranked by how many words they share with the snippet, see
+    /// `CrateSearcher::most_similar`. Returns `None` if no example shares any.
+    pub async fn most_similar(self, snippet: &str) -> Result<Option<Example>> {
+        let cache_manager = self.cache_manager()?;
+        let extractor = self.extractor();
+        let resolved = self.resolve().await?;
+        let (_, checkout_path) = self.checkout(resolved, &cache_manager, &extractor, &mut SearchTimings::default()).await?;
+
+        CrateSearcher::new().most_similar(&checkout_path, snippet)
+    }
+
+    /// The fenced Rust code blocks of the crate's packaged README, the quickest "how do I start"
+    ///
+    /// The README is the manifest's `readme` file, or else `README.md` at the crate root. Blocks
+    /// tagged `rust` or with only rustdoc attributes (`no_run`, `ignore`, ...) are returned, each
+    /// with `start_line` pointing back into the README and its `doc_block` kind. If a pattern was
+    /// set, only blocks matching it are kept; kinds given to `exclude_doc_blocks` are dropped.
+    /// Empty if the crate packages no README.
+    pub async fn readme_examples(self) -> Result<Vec<Example>> {
+        let cache_manager = self.cache_manager()?;
+        let extractor = self.extractor();
+        let resolved = self.resolve().await?;
+        let (_, checkout_path) = self.checkout(resolved, &cache_manager, &extractor, &mut SearchTimings::default()).await?;
+
+        let declared = self.checkout_manifest(&checkout_path)?.readme;
+        let readme = declared
+            .into_iter()
+            .chain(["README.md", "Readme.md", "readme.md"].map(PathBuf::from))
+            .find(|candidate| checkout_path.join(candidate).is_file());
+        let Some(readme) = readme else {
+            return Ok(Vec::new());
+        };
+
+        let mut examples = CrateSearcher::new().fenced_examples(&checkout_path, &readme)?;
+        examples.retain(|example| example.doc_block.is_none_or(|kind| !self.excluded_doc_blocks.contains(&kind)));
+        if !self.patterns.is_empty() {
+            examples.retain(|example| self.patterns.iter().any(|p| p.is_match(&example.content)));
+        }
+        Ok(examples)
+    }
+
+    /// A version resolver configured with this search's options
+    fn resolver(&self) -> VersionResolver {
+        let mut resolver = VersionResolver::new()
+            .stable_only(self.stable_only)
+            .locked(self.locked);
+        if let Some(manifest_path) = &self.manifest_path {
+            resolver = resolver.manifest_path(manifest_path);
+        }
+        if let Some(kind) = self.dep_kind {
+            resolver = resolver.dep_kind(kind);
+        }
+        if let Some(base_url) = &self.api_base_url {
+            resolver = resolver.api_base_url(base_url);
+        }
+        match &self.user_agent {
+            Some(user_agent) => resolver.user_agent(user_agent),
+            None => resolver,
+        }
+    }
+
+    /// A crate extractor configured with this search's options
+    fn extractor(&self) -> CrateExtractor {
+        let mut extractor = CrateExtractor::new();
+        if let Some(user_agent) = &self.user_agent {
+            extractor = extractor.user_agent(user_agent);
+        }
+        if let Some(base_url) = &self.download_base_url {
+            extractor = extractor.base_url(base_url);
+        }
+        extractor
+    }
+
+    /// The cache manager for this search, using `cache_dirs` if set
+    fn cache_manager(&self) -> Result<CacheManager> {
+        match &self.cache_dirs {
+            Some((cargo_home, extraction_cache_dir)) => Ok(CacheManager::with_dirs(cargo_home, extraction_cache_dir)),
+            None => CacheManager::new(),
+        }
+    }
+
+    /// A crate discovery client configured with this search's options
+    pub(crate) fn discovery(&self) -> CrateDiscovery {
+        let mut discovery = CrateDiscovery::new();
+        if let Some(base_url) = &self.api_base_url {
+            discovery = discovery.api_base_url(base_url);
+        }
+        match &self.user_agent {
+            Some(user_agent) => discovery.user_agent(user_agent),
+            None => discovery,
+        }
+    }
+
+    /// Resolve the package name and exact version to search, or `None` for a local archive or directory
+    async fn resolve(&self) -> Result<Option<ResolvedCrate>> {
+        if self.crate_file.is_some() || self.crate_dir.is_some() {
+            // Local sources skip resolution entirely
+            return Ok(None);
+        }
+
+        Ok(Some(self.resolve_registry().await?))
+    }
+
+    /// Resolve the crate's package name and version, unless `resolved_version` already pinned them
+    async fn resolve_registry(&self) -> Result<ResolvedCrate> {
+        if self.current_crate {
+            return self.resolver().current_package();
+        }
+
+        if let Some(version) = &self.resolved_version {
+            return Ok(ResolvedCrate {
+                name: self.crate_name.clone(),
+                version: version.clone(),
+                source_path: None,
+            });
+        }
+
+        self.resolver().resolve_crate(&self.crate_name, self.version_spec.as_deref()).await
+    }
+
+    /// Get or extract the crate source, returning its version and path
+    async fn checkout(
+        &self,
+        resolved: Option<ResolvedCrate>,
+        cache_manager: &CacheManager,
+        extractor: &CrateExtractor,
+        timings: &mut SearchTimings,
+    ) -> Result<(String, PathBuf)> {
+        if let Some(crate_dir) = &self.crate_dir {
+            // A directory is searched where it is, with no version to report
+            return Ok((String::new(), crate_dir.clone()));
+        }
+
+        if let Some(crate_file) = &self.crate_file {
+            // A local archive is cached purely by its contents
+            let started = Instant::now();
+            // Read asynchronously, so a slow source such as a network mount can be cancelled
+            let bytes = tokio::fs::read(crate_file).await?;
+            let checkout_path = extractor.extract_bytes_to_content(&bytes, cache_manager.extraction_cache_dir()).await?;
+            let version = self.crate_file_version(crate_file, &checkout_path)?;
+            timings.extract += started.elapsed();
+            return Ok((version, checkout_path));
+        }
+
+        let ResolvedCrate { name, version, source_path } = resolved
+            .ok_or_else(|| crate::EgError::CrateNotFound(self.crate_name.clone()))?;
+
+        // Path, git, and patched dependencies are searched where cargo keeps them
+        if let Some(source_path) = source_path {
+            return Ok((version, source_path));
+        }
+
+        let checkout_path = cache_manager.get_or_extract_crate_timed(&name, &version, extractor, timings).await?;
+        Ok((version, checkout_path))
+    }
+
+    /// Key identifying this search in the result cache
+    ///
+    /// Local archives are identified by path and modification time, since they have no
+    /// resolved version until extracted.
+    fn result_key(&self, resolved: Option<&ResolvedCrate>) -> Result<String> {
+        let source = match (resolved, &self.crate_file) {
+            (_, Some(crate_file)) => {
+                let modified = std::fs::metadata(crate_file)?.modified()?;
+                format!("{}@{:?}", crate_file.display(), modified)
+            }
+            (Some(resolved), None) => format!("{}@{}", resolved.name, resolved.version),
+            (None, None) => self.crate_name.clone(),
+        };
+
+        let options = ResultKeyOptions {
+            patterns: self.patterns.iter().map(|p| p.as_str()).collect(),
+            match_mode: self.match_mode,
+            context_lines: self.context_lines,
+            match_filenames: self.match_filenames,
+            dot_matches_newline: self.dot_matches_newline,
+            max_matches_per_file: self.max_matches_per_file,
+            max_total_bytes: self.max_total_bytes,
+            first_match_only: self.first_match_only,
+            follow_deps: self.follow_deps,
+            include_companions: self.include_companions,
+            scope: self.scope,
+            extensions: &self.extensions,
+            include_hidden: self.include_hidden,
+            attach_doc_comments: self.attach_doc_comments,
+            include_test_context: self.include_test_context,
+            record_file_list: self.record_file_list,
+            code_only: self.code_only,
+            token_query: self.token_query.as_ref(),
+            simplify_snippets: self.simplify_snippets,
+            #[cfg(feature = "check")]
+            verify_compiles: self.verify_compiles,
+            cache_dirs: self.cache_dirs.as_ref(),
+            download_base_url: self.download_base_url.as_deref(),
+            api_base_url: self.api_base_url.as_deref(),
+            user_agent: self.user_agent.as_deref(),
+        };
+        let mut hasher = std::hash::DefaultHasher::new();
+        options.hash(&mut hasher);
+
+        Ok(format!("{} {:016x}", source, hasher.finish()))
+    }
+
+    /// The checkout's manifest details, read once per search
+    ///
+    /// A local directory without a `Cargo.toml` has none; a manifest that can't be read is an error,
+    /// which `search_resolved` logs and treats as no details.
+    fn checkout_manifest(&self, checkout_path: &Path) -> Result<ManifestDetails> {
+        let manifest_path = checkout_path.join("Cargo.toml");
+        if !manifest_path.is_file() {
+            return Ok(ManifestDetails::default());
+        }
+        self.resolver().manifest_details(&manifest_path)
+    }
+
+    /// Determine a local archive's version from its file name or its extracted manifest
+    fn crate_file_version(&self, crate_file: &Path, checkout_path: &Path) -> Result<String> {
+        let stem = crate_file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        if let Some((_, version)) = parse_crate_file_stem(&stem) {
+            return Ok(version);
+        }
+
+        let package = VersionResolver::new().manifest_package(&checkout_path.join("Cargo.toml"))?;
+        Ok(package.version)
+    }
+
+    /// Compile-check the snippets of the example matches if requested, tagging each match with the outcome
+    ///
+    /// A snippet is the match's context, or its line when there is none. Identical snippets are
+    /// checked once.
+    #[cfg(feature = "check")]
+    async fn check_example_matches(&self, crate_name: &str, version: &str, mut matches: CrateMatches) -> Result<CrateMatches> {
+        if !self.verify_compiles {
+            return Ok(matches);
+        }
+
+        let checker = SnippetChecker::new(crate_name, version);
+        let mut outcomes: std::collections::HashMap<String, bool> = std::collections::HashMap::new();
+
+        for m in matches.example_matches.iter_mut() {
+            let snippet = if m.context.is_empty() {
+                m.line_content.clone()
+            } else {
+                m.context.iter()
+                    .filter(|line| !line.is_truncation_marker())
+                    .map(|line| line.content.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            if let Some(&compiles) = outcomes.get(&snippet) {
+                m.compiles = Some(compiles);
+                continue;
+            }
+            if outcomes.len() >= MAX_CHECKED_SNIPPETS {
+                continue;
+            }
+
+            let compiles = checker.check(&check::snippet_program(&snippet)).await?;
+            outcomes.insert(snippet, compiles);
+            m.compiles = Some(compiles);
+        }
+
+        Ok(matches)
+    }
+
+    /// Search an extracted crate with this builder's pattern and options
+    fn search_checkout(&self, checkout_path: &Path) -> Result<CrateMatches> {
+        let searcher = CrateSearcher::new()
+            .multiline_matches(self.dot_matches_newline)
+            .max_matches_per_file(self.max_matches_per_file)
+            .max_total_bytes(self.max_total_bytes)
+            .first_match_only(self.first_match_only)
+            .scope(self.scope)
+            .include_extensions(&self.extensions.iter().map(String::as_str).collect::<Vec<_>>())
+            .include_hidden(self.include_hidden)
+            .doc_comments(self.attach_doc_comments)
+            .test_context(self.include_test_context)
+            .code_only(self.code_only)
+            .token_query(self.token_query.clone())
+            .matcher(self.matcher.clone())
+            .simplify_snippets(self.simplify_snippets);
+
+        if self.matcher.is_some() {
+            return searcher.search_crate_patterns(checkout_path, &[], MatchMode::default(), self.context_lines);
+        }
+
+        if self.patterns.is_empty() {
+            // No pattern - walk without reading anything, for the stats and checkout_path
+            return searcher.search_crate_patterns(checkout_path, &[], MatchMode::default(), self.context_lines);
+        }
+
+        let patterns: Vec<&Regex> = self.patterns.iter().map(|p| p.as_ref()).collect();
+        if self.match_filenames {
+            Ok(CrateMatches {
+                example_matches: searcher.search_filenames_patterns(checkout_path, &patterns, self.match_mode)?,
+                ..searcher.search_crate_patterns(checkout_path, &[], MatchMode::default(), self.context_lines)?
+            })
+        } else if let [pattern] = patterns.as_slice() {
+            searcher.search_crate(checkout_path, pattern, self.context_lines)
+        } else {
+            searcher.search_crate_patterns(checkout_path, &patterns, self.match_mode, self.context_lines)
+        }
+    }
+
+    /// Search each companion crate, keeping those with matches
+    async fn search_companions(
+        &self,
+        companions: &[(String, String)],
+        manifest: &ManifestDetails,
+        cache_manager: &CacheManager,
+        extractor: &CrateExtractor,
+    ) -> Result<Vec<DependencyMatches>> {
+        let mut results = Vec::new();
+        if self.patterns.is_empty() {
+            return Ok(results);
+        }
+
+        let resolver = self.resolver();
+        for (dep_name, dep_req) in companions {
+            let local_path = manifest.dependency_path(dep_name);
+            if let Some((matches, _)) = self.search_dependency(&resolver, dep_name, dep_req, local_path, cache_manager, extractor).await?
+                && (!matches.example_matches.is_empty() || !matches.other_matches.is_empty())
+            {
+                results.push(matches);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Breadth-first search of the crate's dependencies, bounded by `follow_deps` and `MAX_FOLLOWED_CRATES`
+    ///
+    /// `manifest` is the searched crate's, already read by the search. Crates named in `skip`,
+    /// e.g. companions already searched, are not searched again.
+    async fn search_dependencies(
+        &self,
+        manifest: &ManifestDetails,
+        skip: &[&str],
+        cache_manager: &CacheManager,
+        extractor: &CrateExtractor,
+    ) -> Result<Vec<DependencyMatches>> {
+        let mut results = Vec::new();
+        if self.follow_deps == 0 || self.patterns.is_empty() {
+            return Ok(results);
+        }
+
+        let resolver = self.resolver();
+        let mut seen: HashSet<String> = skip.iter().map(|name| name.to_string()).collect();
+        let skipped = seen.len();
+        let mut queue: VecDeque<(ManifestDetails, u8)> = VecDeque::from([(manifest.clone(), self.follow_deps)]);
+
+        while let Some((manifest, depth)) = queue.pop_front() {
+            for (dep_name, dep_req) in &manifest.dependencies {
+                if seen.len() - skipped >= MAX_FOLLOWED_CRATES {
+                    return Ok(results);
+                }
+                if !seen.insert(dep_name.clone()) {
+                    continue;
+                }
+
+                let local_path = manifest.dependency_path(dep_name);
+                let Some((matches, dep_path)) = self.search_dependency(&resolver, dep_name, dep_req, local_path, cache_manager, extractor).await? else {
+                    continue;
+                };
+                if !matches.example_matches.is_empty() || !matches.other_matches.is_empty() {
+                    results.push(matches);
+                }
+
+                if depth > 1 {
+                    match resolver.manifest_details(&dep_path.join("Cargo.toml")) {
+                        Ok(dep_manifest) => queue.push_back((dep_manifest, depth - 1)),
+                        Err(e) => tracing::debug!("Skipping dependencies of {}: {}", dep_path.display(), e),
+                    }
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Resolve, extract, and search one dependency, or `None` if it can't be fetched
+    ///
+    /// A path dependency, at `local_path`, is searched in place without contacting the registry.
+    async fn search_dependency(
+        &self,
+        resolver: &VersionResolver,
+        dep_name: &str,
+        dep_req: &str,
+        local_path: Option<&Path>,
+        cache_manager: &CacheManager,
+        extractor: &CrateExtractor,
+    ) -> Result<Option<(DependencyMatches, PathBuf)>> {
+        // A dependency we can't fetch shouldn't fail the whole search
+        let resolved = match local_path {
+            Some(local_path) => resolver.manifest_package(&local_path.join("Cargo.toml")).map(|package| ResolvedCrate {
+                source_path: Some(local_path.to_path_buf()),
+                ..package
+            }),
+            None => resolver.resolve_crate(dep_name, Some(dep_req)).await,
+        };
+        let resolved = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                tracing::debug!("Skipping dependency {}: {}", dep_name, e);
+                return Ok(None);
+            }
+        };
+        let dep_path = match resolved.source_path {
+            Some(source_path) => source_path,
+            None => match cache_manager.get_or_extract_crate(&resolved.name, &resolved.version, extractor).await {
+                Ok(dep_path) => dep_path,
+                Err(e) => {
+                    tracing::debug!("Skipping dependency {}: {}", dep_name, e);
+                    return Ok(None);
+                }
+            },
+        };
+
+        let matches = self.search_checkout(&dep_path)?;
+        let dependency = DependencyMatches {
+            crate_name: resolved.name,
+            version: resolved.version,
+            checkout_path: dep_path.clone(),
+            example_matches: matches.example_matches,
+            other_matches: matches.other_matches,
+        };
+        Ok(Some((dependency, dep_path)))
+    }
+}
+
+/// Every option that changes a search's result, hashed into its result cache key
+#[derive(Hash)]
+struct ResultKeyOptions<'a> {
+    patterns: Vec<&'a str>,
+    match_mode: MatchMode,
+    context_lines: usize,
+    match_filenames: bool,
+    dot_matches_newline: bool,
+    max_matches_per_file: Option<usize>,
+    max_total_bytes: Option<u64>,
+    first_match_only: bool,
+    follow_deps: u8,
+    include_companions: bool,
+    scope: SearchScope,
+    extensions: &'a [String],
+    include_hidden: bool,
+    attach_doc_comments: bool,
+    include_test_context: bool,
+    record_file_list: bool,
+    code_only: bool,
+    token_query: Option<&'a TokenQuery>,
+    simplify_snippets: bool,
+    #[cfg(feature = "check")]
+    verify_compiles: bool,
+    // Where the sources come from: another mirror or cargo home may hold different contents
+    cache_dirs: Option<&'a (PathBuf, PathBuf)>,
+    download_base_url: Option<&'a str>,
+    api_base_url: Option<&'a str>,
+    user_agent: Option<&'a str>,
+}
+
+/// Read an example file for `diff_examples`
+fn read_example(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| EgError::ReadError { path: path.to_path_buf(), source: e })
+}
+
+/// Lines of `content` that `other` lacks, counting repeated lines separately
+fn lines_missing_from<'a>(content: &'a str, other: &str) -> Vec<&'a str> {
+    let mut available: HashMap<&str, usize> = HashMap::new();
+    for line in other.lines() {
+        *available.entry(line).or_default() += 1;
+    }
+
+    content.lines()
+        .filter(|line| match available.get_mut(line) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+/// Whether `dep_name` is a companion of `crate_name`: the same name plus one of `COMPANION_SUFFIXES`
+fn is_companion(crate_name: &str, dep_name: &str) -> bool {
+    let crate_name = crate_name.replace('_', "-");
+    let dep_name = dep_name.replace('_', "-");
+    dep_name.strip_prefix(crate_name.as_str())
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|suffix| COMPANION_SUFFIXES.contains(&suffix))
+}
+
+/// The host of a repository URL, if it isn't one of `SUPPORTED_REPO_HOSTS`
+fn unsupported_repo_host(repository: &str) -> Option<String> {
+    let url = reqwest::Url::parse(repository.trim()).ok()?;
+    let host = url.host_str()?.trim_start_matches("www.").to_ascii_lowercase();
+    (!SUPPORTED_REPO_HOSTS.contains(&host.as_str())).then_some(host)
+}
+
+/// Split a `.crate` file stem like `foo-bar-1.2.3-beta.1` into name and version
+fn parse_crate_file_stem(stem: &str) -> Option<(String, String)> {
+    stem.match_indices('-')
+        .map(|(idx, _)| (&stem[..idx], &stem[idx + 1..]))
+        .find(|(_, version)| semver::Version::parse(version).is_ok())
+        .map(|(name, version)| (name.to_string(), version.to_string()))
 }
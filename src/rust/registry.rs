@@ -0,0 +1,99 @@
+//! Shared crates.io API clients and retries for transient registry failures
+
+use crate::{EgError, Result};
+use crates_io_api::AsyncClient;
+use std::future::Future;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Minimum spacing between crates.io API requests, per crates.io's crawler policy
+const API_RATE_LIMIT: Duration = Duration::from_millis(1000);
+
+/// Attempts made for a request before its last error is returned
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled for each one after it
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// One API client per user-agent, so every request in the process shares its rate limiter
+static API_CLIENTS: Mutex<Vec<(String, Arc<AsyncClient>)>> = Mutex::new(Vec::new());
+
+/// HTTP client for requests `crates_io_api` can't make, sharing one connection pool
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// When the last direct API request was sent, to space them out like the API clients do
+static LAST_API_REQUEST: tokio::sync::Mutex<Option<Instant>> = tokio::sync::Mutex::const_new(None);
+
+/// The process-wide crates.io API client identifying itself with `user_agent`
+pub(crate) fn api_client(user_agent: &str) -> Result<Arc<AsyncClient>> {
+    // A panic while holding the lock leaves at worst a missing client, created again below
+    let mut clients = API_CLIENTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some((_, client)) = clients.iter().find(|(agent, _)| agent == user_agent) {
+        return Ok(client.clone());
+    }
+
+    let client = Arc::new(AsyncClient::new(user_agent, API_RATE_LIMIT)?);
+    clients.push((user_agent.to_string(), client.clone()));
+    Ok(client)
+}
+
+/// The process-wide HTTP client for registry requests made without `crates_io_api`
+pub(crate) fn http_client() -> reqwest::Client {
+    HTTP_CLIENT.clone()
+}
+
+/// Wait until a direct crates.io API request may be sent under the crawler policy's rate limit
+///
+/// For queries `crates_io_api` lacks; its clients enforce the same spacing themselves.
+pub(crate) async fn api_rate_limit() {
+    let mut last = LAST_API_REQUEST.lock().await;
+    if let Some(last) = *last {
+        tokio::time::sleep_until(last + API_RATE_LIMIT).await;
+    }
+    *last = Some(Instant::now());
+}
+
+/// Run `request`, retrying with exponential backoff while it fails transiently
+///
+/// Timeouts, connection failures, 429s, and 5xx statuses are retried; anything else, such as
+/// a 404, is returned at once.
+pub(crate) async fn with_backoff<T, F, Fut>(mut request: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut delay = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                tracing::debug!(attempt, error = %e, "retrying after transient failure");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether an error is worth retrying
+fn is_transient(error: &EgError) -> bool {
+    match error {
+        EgError::DownloadHttpStatus { status, .. } => is_transient_status(*status),
+        EgError::DownloadError(e) => is_transient_http(e),
+        EgError::RegistryError(crates_io_api::Error::Http(e)) => is_transient_http(e),
+        _ => false,
+    }
+}
+
+fn is_transient_http(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error.status().is_some_and(|status| is_transient_status(status.as_u16()))
+}
+
+fn is_transient_status(status: u16) -> bool {
+    status == 429 || status >= 500
+}
@@ -0,0 +1,107 @@
+//! In-memory cache of search results, for repeated identical searches
+
+use crate::SearchResult;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+
+/// Number of results kept by the process-wide cache unless changed with `set_capacity`
+const DEFAULT_CAPACITY: usize = 32;
+
+/// Least-recently-used cache of search results, shared by every search in the process
+///
+/// Keys combine the crate, its exact resolved version, the patterns, and every search option,
+/// so a newly published version or a changed option is simply a miss.
+pub struct ResultCache {
+    inner: Mutex<CacheState>,
+}
+
+struct CacheState {
+    capacity: usize,
+    entries: HashMap<String, SearchResult>,
+    /// Keys from least to most recently used
+    recency: VecDeque<String>,
+    hits: usize,
+    misses: usize,
+}
+
+impl ResultCache {
+    /// The process-wide cache used by `RustCrateSearch::result_cache`
+    pub fn global() -> &'static ResultCache {
+        static CACHE: OnceLock<ResultCache> = OnceLock::new();
+        CACHE.get_or_init(|| ResultCache {
+            inner: Mutex::new(CacheState {
+                capacity: DEFAULT_CAPACITY,
+                entries: HashMap::new(),
+                recency: VecDeque::new(),
+                hits: 0,
+                misses: 0,
+            }),
+        })
+    }
+
+    /// Change how many results are kept, evicting the least recently used if over the new limit
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.state();
+        state.capacity = capacity;
+        state.evict();
+    }
+
+    /// Drop every cached result
+    pub fn clear(&self) {
+        let mut state = self.state();
+        state.entries.clear();
+        state.recency.clear();
+    }
+
+    /// Number of lookups answered from the cache
+    pub fn hits(&self) -> usize {
+        self.state().hits
+    }
+
+    /// Number of lookups that had to run the search
+    pub fn misses(&self) -> usize {
+        self.state().misses
+    }
+
+    /// Look up a result, marking it as recently used
+    pub(crate) fn get(&self, key: &str) -> Option<SearchResult> {
+        let mut state = self.state();
+        let Some(result) = state.entries.get(key).cloned() else {
+            state.misses += 1;
+            return None;
+        };
+
+        state.hits += 1;
+        state.touch(key);
+        Some(result)
+    }
+
+    /// Store a result, evicting the least recently used one if the cache is full
+    pub(crate) fn insert(&self, key: String, result: SearchResult) {
+        let mut state = self.state();
+        state.touch(&key);
+        state.entries.insert(key, result);
+        state.evict();
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        // A panic while holding the lock can't leave the maps inconsistent enough to matter
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl CacheState {
+    /// Move `key` to the most recently used position
+    fn touch(&mut self, key: &str) {
+        self.recency.retain(|k| k != key);
+        self.recency.push_back(key.to_string());
+    }
+
+    fn evict(&mut self) {
+        while self.recency.len() > self.capacity {
+            if let Some(oldest) = self.recency.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
@@ -1,31 +1,644 @@
 //! Text searching within extracted crates
 
-use crate::{Result, EgError, Match};
+use crate::{ContextLine, DocBlockKind, Example, ExtractionStats, Result, EgError, Match, MatchSource, TestContext};
+use super::VersionResolver;
+use super::{docs, entry, macros, test_fns};
+use super::simplify::inapplicable_lines;
 use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+/// Matches found while searching an extracted crate
+#[derive(Debug, Default)]
+pub struct CrateMatches {
+    /// Matches found in examples/ directory
+    pub example_matches: Vec<Match>,
+    /// Matches found elsewhere in the crate
+    pub other_matches: Vec<Match>,
+    /// Files whose matches were cut short by the per-file limit
+    pub truncated_files: Vec<PathBuf>,
+    /// What the walk came across, including files that didn't match
+    pub stats: ExtractionStats,
+    /// Whether files were left unread because the total byte budget ran out
+    pub truncated: bool,
+}
+
+/// How multiple patterns combine when deciding what matches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MatchMode {
+    /// Report lines matching any pattern
+    #[default]
+    AnyInFile,
+    /// Only search files where every pattern matches somewhere, reporting each pattern's matches
+    AllInFile,
+    /// Report lines on which every pattern matches
+    AllOnLine,
+}
+
+/// How many levels of `mod`/`include!` a resolved snippet follows
+const MAX_SNIPPET_DEPTH: usize = 4;
+
+/// Marker file cargo writes at the root of each crate it unpacks into its registry cache
+const CARGO_OK_MARKER: &str = ".cargo-ok";
+
+/// Files larger than this are left as a `mod`/`include!` reference instead of being inlined
+const MAX_INLINED_FILE_BYTES: u64 = 64 * 1024;
+
+/// A matched span of a file, as byte offsets into its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchRange {
+    /// Offset of the first matched byte
+    pub byte_start: usize,
+    /// Offset just past the last matched byte
+    pub byte_end: usize,
+}
+
+/// Custom match logic that can replace the built-in regex search, see `RustCrateSearch::matcher`
+///
+/// `find` is called once per searched file with its crate-relative path and contents, and
+/// returns the spans it considers matches. Each span becomes a `Match` at the line it starts
+/// on, with context and categorization handled as for regex matches.
+pub trait Matcher: Send + Sync {
+    /// The matched spans of `content`, in any order
+    fn find(&self, path: &Path, content: &str) -> Vec<SearchRange>;
+}
+
+/// The built-in regex search across lines, as a `Matcher`
+impl Matcher for Regex {
+    fn find(&self, path: &Path, content: &str) -> Vec<SearchRange> {
+        RegexMatcher { pattern: self, multiline: true, code: None }.find(path, content)
+    }
+}
+
+/// Where a match sits: its file, the matched text's byte range, and the file's fenced blocks
+///
+/// `fences` are inclusive 0-based line ranges that a match's context is widened to cover whole.
+struct MatchLocation<'a> {
+    relative_path: &'a Path,
+    bytes: Range<usize>,
+    fences: &'a [(usize, usize)],
+}
+
+/// The built-in regex search, which every regex query runs through
+///
+/// Line by line, each line's first match is a span; with `multiline`, every match in the file
+/// is. With a `code` mask from `code_mask`, matches entirely in comments or strings are skipped.
+struct RegexMatcher<'a> {
+    pattern: &'a Regex,
+    multiline: bool,
+    code: Option<&'a [bool]>,
+}
+
+impl Matcher for RegexMatcher<'_> {
+    fn find(&self, _path: &Path, content: &str) -> Vec<SearchRange> {
+        let to_range = |range: Range<usize>| SearchRange { byte_start: range.start, byte_end: range.end };
+        let lines: Vec<&str> = without_bom(content).lines().collect();
+        let line_starts = line_offsets(content, &lines);
+
+        if !self.multiline {
+            return lines.iter()
+                .zip(line_starts)
+                .filter_map(|(line, line_start)| code_match(self.pattern, line, line_start, self.code))
+                .map(to_range)
+                .collect();
+        }
+
+        // Match after any byte order mark so `^` anchors the first line, keeping file offsets
+        let text = without_bom(content);
+        let bom = content.len() - text.len();
+        self.pattern
+            .find_iter(text)
+            .map(|found| bom + found.start()..bom + found.end())
+            .filter(|range| self.code.is_none_or(|code| code[range.clone()].contains(&true)))
+            .map(to_range)
+            .collect()
+    }
+}
+
+/// Which parts of a crate a search covers
+///
+/// The default is what a plain search has always covered: examples, library sources,
+/// integration tests, and the build script. Build one fluently, e.g. `SearchScope::examples().with_tests()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SearchScope {
+    examples: bool,
+    source: bool,
+    tests: bool,
+    markdown: bool,
+    build_scripts: bool,
+}
+
+impl Default for SearchScope {
+    fn default() -> Self {
+        SearchScope::examples().with_source().with_tests().with_build_scripts()
+    }
+}
+
+impl SearchScope {
+    /// Only the files under the crate's top-level `examples/` directory
+    pub fn examples() -> Self {
+        SearchScope { examples: true, source: false, tests: false, markdown: false, build_scripts: false }
+    }
+
+    /// Everything: examples, sources, tests, root markdown, and the build script
+    pub fn all() -> Self {
+        SearchScope { examples: true, source: true, tests: true, markdown: true, build_scripts: true }
+    }
+
+    /// Also cover source files outside `examples/` and `tests/`, such as `src/` and `benches/`
+    pub fn with_source(mut self) -> Self {
+        self.source = true;
+        self
+    }
+
+    /// Also cover integration tests under the top-level `tests/` directory
+    pub fn with_tests(mut self) -> Self {
+        self.tests = true;
+        self
+    }
+
+    /// Also cover `*.md` files at the crate root, such as README.md
+    pub fn with_markdown(mut self) -> Self {
+        self.markdown = true;
+        self
+    }
+
+    /// Also cover the `build.rs` at the crate root
+    pub fn with_build_scripts(mut self) -> Self {
+        self.build_scripts = true;
+        self
+    }
+
+    /// Set whether root markdown is covered
+    pub(crate) fn set_markdown(mut self, enabled: bool) -> Self {
+        self.markdown = enabled;
+        self
+    }
+
+    /// Set whether the build script is covered
+    pub(crate) fn set_build_scripts(mut self, enabled: bool) -> Self {
+        self.build_scripts = enabled;
+        self
+    }
+}
+
+/// A query answered from a Rust file's tokens rather than its text
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum TokenQuery {
+    /// Identifier tokens equal to the name
+    Ident(String),
+    /// Uses of the macro: `name!(...)`, `#[derive(name)]`, or `#[name]`
+    Macro(String),
+}
 
 /// Handles text searching within extracted crate sources
-pub struct CrateSearcher;
+pub struct CrateSearcher {
+    multiline_matches: bool,
+    max_matches_per_file: Option<usize>,
+    scope: SearchScope,
+    extensions: Vec<String>,
+    include_hidden: bool,
+    doc_comments: bool,
+    test_context: bool,
+    code_only: bool,
+    simplify_snippets: bool,
+    max_total_bytes: Option<u64>,
+    first_match_only: bool,
+    token_query: Option<TokenQuery>,
+    matcher: Option<Arc<dyn Matcher>>,
+}
+
+impl Default for CrateSearcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl CrateSearcher {
     pub fn new() -> Self {
-        Self
+        Self {
+            multiline_matches: false,
+            max_matches_per_file: None,
+            scope: SearchScope::default(),
+            extensions: vec!["rs".to_string()],
+            include_hidden: false,
+            doc_comments: false,
+            test_context: false,
+            code_only: false,
+            simplify_snippets: false,
+            max_total_bytes: None,
+            first_match_only: false,
+            token_query: None,
+            matcher: None,
+        }
+    }
+
+    /// In Rust files, match only identifier tokens equal to `name` instead of running the pattern
+    ///
+    /// Files are tokenized with `proc-macro2`, so comments, literals, and longer identifiers
+    /// never match. Files that don't tokenize, and markdown, fall back to the pattern.
+    pub fn ident(mut self, name: Option<String>) -> Self {
+        self.token_query = name.map(TokenQuery::Ident);
+        self
+    }
+
+    /// In Rust files, match only uses of the macro `name` instead of running the pattern
+    ///
+    /// Files are parsed with `syn`, so each match spans the whole invocation even across lines.
+    /// Files that don't parse, and markdown, fall back to the pattern.
+    pub fn macro_invocations(mut self, name: Option<String>) -> Self {
+        self.token_query = name.map(TokenQuery::Macro);
+        self
+    }
+
+    /// Find matches with custom logic instead of the patterns, in every searched file
+    ///
+    /// Token queries and `code_only` don't apply; context, budgets, and per-file limits do.
+    pub fn matcher(mut self, matcher: Option<Arc<dyn Matcher>>) -> Self {
+        self.matcher = matcher;
+        self
+    }
+
+    /// Set or clear the token query directly
+    pub(crate) fn token_query(mut self, query: Option<TokenQuery>) -> Self {
+        self.token_query = query;
+        self
+    }
+
+    /// Keep only the first match in each example file
+    pub fn first_match_only(mut self, enabled: bool) -> Self {
+        self.first_match_only = enabled;
+        self
+    }
+
+    /// Stop reading files before the total read would exceed `max` bytes
+    pub fn max_total_bytes(mut self, max: Option<u64>) -> Self {
+        self.max_total_bytes = max;
+        self
+    }
+
+    /// Drop context lines in Rust files that belong to code `#[cfg]`-gated out on the host
+    pub fn simplify_snippets(mut self, enabled: bool) -> Self {
+        self.simplify_snippets = enabled;
+        self
+    }
+
+    /// Drop matches in Rust files that fall entirely within comments, string literals, or whitespace
+    pub fn code_only(mut self, enabled: bool) -> Self {
+        self.code_only = enabled;
+        self
+    }
+
+    /// Also search `*.md` files at the crate root, such as README.md and CHANGELOG.md
+    pub fn include_markdown(mut self, enabled: bool) -> Self {
+        self.scope = self.scope.set_markdown(enabled);
+        self
+    }
+
+    /// Whether to search the `build.rs` at the crate root (the default), tagging its matches `MatchSource::Build`
+    pub fn include_build_scripts(mut self, enabled: bool) -> Self {
+        self.scope = self.scope.set_build_scripts(enabled);
+        self
+    }
+
+    /// Cover these parts of the crate, replacing the markdown and build script settings
+    pub fn scope(mut self, scope: SearchScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Search files with these extensions (without the dot) instead of only `rs`
+    ///
+    /// Token queries, `code_only`, and `simplify_snippets` only apply to `.rs` files; other files
+    /// are matched line by line like markdown.
+    pub fn include_extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = extensions.iter().map(|ext| ext.trim_start_matches('.').to_string()).collect();
+        self
+    }
+
+    /// Also search hidden files and directories, whose names start with `.`
+    ///
+    /// A dotfile with no extension of its own, such as `examples/.config`, is searched whenever
+    /// this is enabled.
+    pub fn include_hidden(mut self, enabled: bool) -> Self {
+        self.include_hidden = enabled;
+        self
+    }
+
+    /// In Rust files, attach the doc comment of the innermost documented item around each match
+    pub fn doc_comments(mut self, enabled: bool) -> Self {
+        self.doc_comments = enabled;
+        self
+    }
+
+    /// In Rust files, attach the `#[test]` function around each match with its assertions marked
+    pub fn test_context(mut self, enabled: bool) -> Self {
+        self.test_context = enabled;
+        self
+    }
+
+    /// Keep at most `max` matches from any single file
+    pub fn max_matches_per_file(mut self, max: Option<usize>) -> Self {
+        self.max_matches_per_file = max;
+        self
+    }
+
+    /// Apply the pattern to whole file contents so matches may span several lines
+    pub fn multiline_matches(mut self, enabled: bool) -> Self {
+        self.multiline_matches = enabled;
+        self
     }
 
     /// Search for pattern in the extracted crate, returning categorized matches
+    #[tracing::instrument(
+        level = "debug",
+        skip(self, pattern),
+        fields(crate_path = %crate_path.display(), pattern = %pattern, matches = tracing::field::Empty),
+    )]
     pub fn search_crate(
         &self,
         crate_path: &Path,
         pattern: &Regex,
         context_lines: usize,
-    ) -> Result<(Vec<Match>, Vec<Match>)> {
-        let mut example_matches = Vec::new();
-        let mut other_matches = Vec::new();
+    ) -> Result<CrateMatches> {
+        let matches = self.search_with(crate_path, &[pattern], MatchMode::AnyInFile, context_lines)?;
+        tracing::Span::current().record("matches", matches.example_matches.len() + matches.other_matches.len());
+        Ok(matches)
+    }
+
+    /// Search for several patterns in the extracted crate, combined according to `mode`
+    #[tracing::instrument(
+        level = "debug",
+        skip(self, patterns),
+        fields(crate_path = %crate_path.display(), patterns = patterns.len(), matches = tracing::field::Empty),
+    )]
+    pub fn search_crate_patterns(
+        &self,
+        crate_path: &Path,
+        patterns: &[&Regex],
+        mode: MatchMode,
+        context_lines: usize,
+    ) -> Result<CrateMatches> {
+        let matches = self.search_with(crate_path, patterns, mode, context_lines)?;
+        tracing::Span::current().record("matches", matches.example_matches.len() + matches.other_matches.len());
+        Ok(matches)
+    }
+
+    /// Shared implementation of `search_crate` and `search_crate_patterns`
+    fn search_with(
+        &self,
+        crate_path: &Path,
+        patterns: &[&Regex],
+        mode: MatchMode,
+        context_lines: usize,
+    ) -> Result<CrateMatches> {
+        let mut matches = CrateMatches::default();
+        self.search_directory(crate_path, crate_path, patterns, mode, context_lines, &mut matches)?;
+        Ok(matches)
+    }
+
+    /// Match pattern against the relative paths of example files, without reading their contents
+    pub fn search_filenames(&self, crate_path: &Path, pattern: &Regex) -> Result<Vec<Match>> {
+        self.search_filenames_patterns(crate_path, &[pattern], MatchMode::AnyInFile)
+    }
+
+    /// Match several patterns against example file paths; any `All*` mode requires every pattern to match
+    pub fn search_filenames_patterns(&self, crate_path: &Path, patterns: &[&Regex], mode: MatchMode) -> Result<Vec<Match>> {
+        let mut matches = Vec::new();
 
-        self.search_directory(crate_path, crate_path, pattern, context_lines, &mut example_matches, &mut other_matches)?;
+        for relative_path in self.example_files(crate_path)? {
+            let relative_str = relative_path.to_string_lossy().to_string();
+            let is_match = match mode {
+                MatchMode::AnyInFile => patterns.iter().any(|p| p.is_match(&relative_str)),
+                MatchMode::AllInFile | MatchMode::AllOnLine => patterns.iter().all(|p| p.is_match(&relative_str)),
+            };
 
-        Ok((example_matches, other_matches))
+            if is_match {
+                #[allow(deprecated)]
+                matches.push(Match {
+                    file_path: relative_path,
+                    line_number: 0,
+                    line_content: relative_str,
+                    byte_start: 0,
+                    byte_end: 0,
+                    context_before: Vec::new(),
+                    context_after: Vec::new(),
+                    context: Vec::new(),
+                    source: MatchSource::Example,
+                    compiles: None,
+                    doc_comment: None,
+                    test_context: None,
+                });
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// List the crate-relative paths of all Rust files under the top-level examples/ directory
+    pub fn example_files(&self, crate_path: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        self.collect_example_files(crate_path, crate_path, &mut files)?;
+        Ok(files)
+    }
+
+    /// List every Rust file under the top-level examples/ directory with its size in bytes, sorted by path
+    pub fn example_listing(&self, crate_path: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let mut listing = self.example_files(crate_path)?
+            .into_iter()
+            .map(|relative_path| {
+                let size = fs::metadata(crate_path.join(&relative_path))?.len();
+                Ok((relative_path, size))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        listing.sort();
+        Ok(listing)
+    }
+
+    /// List every file in an extracted crate with its size in bytes, sorted by path
+    ///
+    /// Unlike the search itself, nothing is skipped: hidden files, `target/`, and non-Rust files
+    /// are all listed, so a packaged crate's listing matches the contents of its `.crate` archive.
+    /// The exception is the `.cargo-ok` marker cargo writes into its own extractions, which the
+    /// archive never contains.
+    pub fn file_list(&self, crate_path: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let mut files = Vec::new();
+        collect_all_files(crate_path, crate_path, &mut files)?;
+        files.retain(|(path, _)| path != Path::new(CARGO_OK_MARKER));
+        files.sort();
+        Ok(files)
+    }
+
+    /// The `cargo run --example` invocation for each example the crate packages
+    ///
+    /// Examples and their `required-features` come from the crate's manifest; without one,
+    /// cargo's auto-discovery rules are applied to the files under `examples/` instead. A
+    /// manifest that can't be read is an error.
+    pub fn run_commands(&self, crate_path: &Path) -> Result<Vec<(PathBuf, String)>> {
+        let manifest_path = crate_path.join("Cargo.toml");
+        let examples = if manifest_path.is_file() {
+            VersionResolver::new().manifest_examples(&manifest_path)?
+        } else {
+            self.discover_examples(crate_path)?
+        };
+
+        let mut commands: Vec<_> = examples
+            .into_iter()
+            .map(|(name, path, required_features)| {
+                let mut command = format!("cargo run --example {}", name);
+                if !required_features.is_empty() {
+                    command.push_str(&format!(" --features {}", required_features.join(",")));
+                }
+                (normalize_path_separators(&path), command)
+            })
+            .collect();
+        commands.sort();
+
+        Ok(commands)
+    }
+
+    /// Find examples the way cargo does without a manifest: `examples/*.rs` and `examples/*/main.rs`
+    fn discover_examples(&self, crate_path: &Path) -> Result<Vec<(String, PathBuf, Vec<String>)>> {
+        let examples = self.example_files(crate_path)?
+            .into_iter()
+            .filter_map(|path| {
+                let components: Vec<_> = path.iter().map(|c| c.to_string_lossy().to_string()).collect();
+                let name = match components.as_slice() {
+                    [dir, file] if dir == "examples" => file.strip_suffix(".rs")?.to_string(),
+                    [dir, name, file] if dir == "examples" && file == "main.rs" => name.clone(),
+                    _ => return None,
+                };
+                Some((name, path, Vec::new()))
+            })
+            .collect();
+
+        Ok(examples)
+    }
+
+    /// Cut the fenced Rust code blocks out of a markdown file, such as the crate's README
+    ///
+    /// A block counts as Rust if its info string says `rust`, or consists only of rustdoc
+    /// attributes like `no_run` or `ignore`. Unlabeled blocks are skipped, since READMEs use them
+    /// for shell commands and output too. Each `Example` records the line its code starts on
+    /// and its `DocBlockKind`.
+    pub fn fenced_examples(&self, crate_path: &Path, relative_path: &Path) -> Result<Vec<Example>> {
+        let path = crate_path.join(relative_path);
+        let content = fs::read_to_string(&path).map_err(|e| EgError::ReadError { path: path.clone(), source: e })?;
+        let lines: Vec<&str> = without_bom(&content).lines().collect();
+
+        let examples = fenced_blocks(&lines)
+            .into_iter()
+            .filter_map(|(open_idx, close_idx)| {
+                let kind = doc_block_kind(fence_info(lines[open_idx]))?;
+                // An unterminated block's last line is code, not a closing fence
+                let is_closed = close_idx > open_idx && is_fence(lines[close_idx]);
+                let end_idx = if is_closed { close_idx } else { close_idx + 1 };
+                let code = lines[open_idx + 1..end_idx].join("\n");
+                Some(Example {
+                    file_path: normalize_path_separators(relative_path),
+                    entry: entry::detect(&code),
+                    content: code,
+                    start_line: Some((open_idx + 2) as u32),
+                    doc_block: Some(kind),
+                })
+            })
+            .collect();
+
+        Ok(examples)
+    }
+
+    /// Pick the single most representative example file, if the crate has any
+    ///
+    /// Files are scored on having a `fn main`, a moderate length, a beginner-friendly name
+    /// (`basic`, `simple`, `hello`, ...), and actually using the crate; ties go to the
+    /// shortest path.
+    pub fn best_example(&self, crate_path: &Path, crate_name: &str) -> Result<Option<Example>> {
+        let lib_name = crate_name.replace('-', "_");
+        let mut best: Option<(i32, Example)> = None;
+
+        let mut files = self.example_files(crate_path)?;
+        files.sort_by_key(|path| (path.as_os_str().len(), path.clone()));
+
+        for relative_path in files {
+            let Ok(content) = fs::read_to_string(crate_path.join(&relative_path)) else {
+                continue;
+            };
+
+            let score = score_example(&relative_path, &content, &lib_name);
+            if best.as_ref().is_none_or(|(best_score, _)| score > *best_score) {
+                best = Some((score, Example { file_path: relative_path, entry: entry::detect(&content), content, start_line: None, doc_block: None }));
+            }
+        }
+
+        Ok(best.map(|(_, example)| example))
+    }
+
+    /// Find the example file most similar to `snippet`, with its full contents
+    ///
+    /// Similarity is the overlap of the words (identifiers, keywords, and numbers) the two
+    /// share, counting repeats, so formatting and comments barely matter. Ties go to the
+    /// shorter path. `None` if the crate has no examples or none shares a word with `snippet`.
+    pub fn most_similar(&self, crate_path: &Path, snippet: &str) -> Result<Option<Example>> {
+        let wanted = word_counts(snippet);
+        let mut best: Option<(f64, Example)> = None;
+
+        let mut files = self.example_files(crate_path)?;
+        files.sort_by_key(|path| (path.as_os_str().len(), path.clone()));
+
+        for relative_path in files {
+            let Ok(content) = fs::read_to_string(crate_path.join(&relative_path)) else {
+                continue;
+            };
+
+            let score = word_overlap(&wanted, &word_counts(&content));
+            if score > 0.0 && best.as_ref().is_none_or(|(best_score, _)| score > *best_score) {
+                best = Some((score, Example { file_path: relative_path, entry: entry::detect(&content), content, start_line: None, doc_block: None }));
+            }
+        }
+
+        Ok(best.map(|(_, example)| example))
+    }
+
+    /// Read a crate file with its local `mod foo;` declarations and `include!`s inlined
+    ///
+    /// Produces a self-contained snippet for examples split across several files. Inlining
+    /// follows at most a few levels and skips large files; references that can't be resolved
+    /// are left as written.
+    pub fn resolved_snippet(&self, crate_path: &Path, relative_path: &Path) -> Result<String> {
+        let path = crate_path.join(relative_path);
+        let content = fs::read_to_string(&path).map_err(|e| EgError::ReadError { path: path.clone(), source: e })?;
+        let root = fs::canonicalize(crate_path).map_err(|e| EgError::ReadError { path: crate_path.to_path_buf(), source: e })?;
+        Ok(inline_modules(&root, &path, &content, true, MAX_SNIPPET_DEPTH))
+    }
+
+    /// Recursively collect Rust files under examples/ directories
+    fn collect_example_files(
+        &self,
+        base_path: &Path,
+        current_path: &Path,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        for entry in fs::read_dir(current_path)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if path.is_dir() {
+                // Skip hidden directories and target directories
+                if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                    && (name.starts_with('.') || name == "target")
+                {
+                    continue;
+                }
+                self.collect_example_files(base_path, &path, files)?;
+            } else if path.extension().is_some_and(|ext| ext == "rs") && self.is_example_file(base_path, &path) {
+                files.push(self.relative_path(base_path, &path));
+            }
+        }
+
+        Ok(())
     }
 
     /// Recursively search a directory
@@ -33,31 +646,66 @@ impl CrateSearcher {
         &self,
         base_path: &Path,
         current_path: &Path,
-        pattern: &Regex,
+        patterns: &[&Regex],
+        mode: MatchMode,
         context_lines: usize,
-        example_matches: &mut Vec<Match>,
-        other_matches: &mut Vec<Match>,
+        results: &mut CrateMatches,
     ) -> Result<()> {
         for entry in fs::read_dir(current_path)? {
             let entry = entry?;
             let path = entry.path();
 
             if path.is_dir() {
-                // Skip hidden directories and target directories
-                if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                    if name.starts_with('.') || name == "target" {
-                        continue;
-                    }
+                // Skip hidden directories (unless asked) and target directories
+                if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                    && ((name.starts_with('.') && !self.include_hidden) || name == "target")
+                {
+                    continue;
+                }
+                self.search_directory(base_path, &path, patterns, mode, context_lines, results)?;
+            } else {
+                results.stats.files_seen += 1;
+                let is_example = self.is_example_file(base_path, &path);
+                if is_example && path.extension().is_some_and(|ext| ext == "rs") {
+                    results.stats.example_files += 1;
+                }
+
+                let has_query = !patterns.is_empty() || self.matcher.is_some();
+                if !has_query || !(self.is_searched_file(&path) || self.is_root_markdown(base_path, &path)) {
+                    continue;
+                }
+                let is_build_script = self.is_build_script(base_path, &path);
+                if !self.in_scope(base_path, &path, is_build_script) {
+                    continue;
                 }
-                self.search_directory(base_path, &path, pattern, context_lines, example_matches, other_matches)?;
-            } else if path.extension().map_or(false, |ext| ext == "rs") {
-                // Search Rust files
-                if let Ok(matches) = self.search_file(base_path, &path, pattern, context_lines) {
-                    let is_example = self.is_example_file(base_path, &path);
+                let len = entry.metadata().map_or(0, |metadata| metadata.len());
+                // Once the budget runs out, keep walking only to finish the stats
+                if results.truncated || self.max_total_bytes.is_some_and(|max| results.stats.bytes_read + len > max) {
+                    results.truncated = true;
+                    continue;
+                }
+
+                // Search Rust files, plus top-level markdown docs if requested
+                if let Ok(mut matches) = self.search_file(base_path, &path, patterns, mode, context_lines) {
+                    results.stats.bytes_read += len;
+                    if let Some(max) = self.max_matches_per_file
+                        && matches.len() > max
+                    {
+                        matches.truncate(max);
+                        results.truncated_files.push(self.relative_path(base_path, &path));
+                    }
+
                     if is_example {
-                        example_matches.extend(matches);
+                        if self.first_match_only {
+                            matches.truncate(1);
+                        }
+                        matches.iter_mut().for_each(|m| m.source = MatchSource::Example);
+                        results.example_matches.extend(matches);
                     } else {
-                        other_matches.extend(matches);
+                        if is_build_script {
+                            matches.iter_mut().for_each(|m| m.source = MatchSource::Build);
+                        }
+                        results.other_matches.extend(matches);
                     }
                 }
             }
@@ -71,57 +719,674 @@ impl CrateSearcher {
         &self,
         base_path: &Path,
         file_path: &Path,
-        pattern: &Regex,
+        patterns: &[&Regex],
+        mode: MatchMode,
         context_lines: usize,
     ) -> Result<Vec<Match>> {
         let content = fs::read_to_string(file_path)
-            .map_err(|e| EgError::Other(format!("Failed to read file {}: {}", file_path.display(), e)))?;
+            .map_err(|e| EgError::ReadError { path: file_path.to_path_buf(), source: e })?;
 
-        let lines: Vec<&str> = content.lines().collect();
-        let mut matches = Vec::new();
+        let lines: Vec<&str> = without_bom(&content).lines().collect();
+
+        // Get relative path from base
+        let relative_path = self.relative_path(base_path, file_path);
+
+        // In markdown, context grows to cover any fenced code block the match falls in
+        let is_markdown = file_path.extension().is_some_and(|ext| ext == "md");
+        let is_rust = file_path.extension().is_some_and(|ext| ext == "rs");
+        let fences = if is_markdown { fenced_blocks(&lines) } else { Vec::new() };
+
+        let code = (self.code_only && is_rust).then(|| code_mask(&content));
+        let code = code.as_deref();
+
+        // Every query becomes line spans, which turn into matches the same way
+        let spans_of = |matcher: &dyn Matcher| matcher_spans(matcher, &relative_path, &content, &lines);
+        let custom = match &self.matcher {
+            Some(matcher) => Some(spans_of(matcher.as_ref())),
+            None => self.token_query.as_ref()
+                .filter(|_| is_rust)
+                .and_then(|query| token_spans(&content, &lines, query)),
+        };
 
-        for (line_idx, line) in lines.iter().enumerate() {
-            if pattern.is_match(line) {
-                let line_number = (line_idx + 1) as u32; // 1-based line numbers
-                
-                // Get context lines
-                let context_start = line_idx.saturating_sub(context_lines);
-                let context_end = std::cmp::min(line_idx + context_lines + 1, lines.len());
-                
-                let context_before = lines[context_start..line_idx]
-                    .iter()
-                    .map(|s| s.to_string())
+        let spans = match (custom, mode) {
+            (Some(spans), _) => spans,
+            (None, MatchMode::AllOnLine) => {
+                // Lines every pattern hits, spanning from the first pattern's hit to the last's
+                let per_pattern: Vec<HashMap<usize, Range<usize>>> = patterns.iter()
+                    .map(|pattern| {
+                        spans_of(&RegexMatcher { pattern, multiline: false, code })
+                            .into_iter()
+                            .map(|(start_idx, _, range)| (start_idx, range))
+                            .collect()
+                    })
                     .collect();
-                
-                let context_after = lines[line_idx + 1..context_end]
-                    .iter()
-                    .map(|s| s.to_string())
+                let Some((first, rest)) = per_pattern.split_first() else {
+                    return Ok(Vec::new());
+                };
+                let mut spans: Vec<_> = first.iter()
+                    .filter_map(|(&line_idx, range)| {
+                        let mut span = range.clone();
+                        for hits in rest {
+                            let hit = hits.get(&line_idx)?;
+                            span = span.start.min(hit.start)..span.end.max(hit.end);
+                        }
+                        Some((line_idx, line_idx, span))
+                    })
                     .collect();
+                spans.sort_by_key(|(line_idx, _, _)| *line_idx);
+                spans
+            }
+            (None, MatchMode::AnyInFile | MatchMode::AllInFile) => {
+                let mut spans = Vec::new();
+                for pattern in patterns {
+                    let found = spans_of(&RegexMatcher { pattern, multiline: self.multiline_matches, code });
+                    if found.is_empty() && mode == MatchMode::AllInFile {
+                        return Ok(Vec::new());
+                    }
+                    spans.extend(found);
+                }
+
+                // Lines hit by more than one pattern are reported once
+                if patterns.len() > 1 {
+                    spans.sort_by_key(|(start_idx, _, _)| *start_idx);
+                    spans.dedup_by_key(|(start_idx, _, _)| *start_idx);
+                }
+                spans
+            }
+        };
 
-                // Get relative path from base
-                let relative_path = file_path.strip_prefix(base_path)
-                    .unwrap_or(file_path)
-                    .to_path_buf();
+        let mut matches: Vec<Match> = spans.into_iter()
+            .map(|(start_idx, end_idx, bytes)| {
+                let location = MatchLocation { relative_path: &relative_path, bytes, fences: &fences };
+                self.build_match(location, &lines, start_idx, end_idx, context_lines)
+            })
+            .collect();
 
-                matches.push(Match {
-                    file_path: relative_path,
-                    line_number,
-                    line_content: line.to_string(),
-                    context_before,
-                    context_after,
-                });
+        if is_markdown {
+            matches.iter_mut().for_each(|m| m.source = MatchSource::Docs);
+        }
+
+        if self.doc_comments
+            && is_rust
+            && let Some(items) = docs::documented_items(&content)
+        {
+            for m in &mut matches {
+                m.doc_comment = docs::doc_for_line(&items, m.line_number as usize).map(str::to_string);
+            }
+        }
+
+        if self.test_context
+            && is_rust
+            && let Some(fns) = test_fns::test_functions(&content)
+        {
+            for m in &mut matches {
+                let context = test_fns::test_for_line(&fns, m.line_number as usize)
+                    .map(|(name, start, end)| test_context(name, &lines, start, end, m));
+                m.test_context = context;
+            }
+        }
+
+        // Lines keep their original numbers, so gaps show where gated code was dropped
+        if self.simplify_snippets && is_rust {
+            let gated = inapplicable_lines(&content);
+            let applicable = |line: &ContextLine| {
+                let idx = (line.line_number as usize).saturating_sub(1);
+                line.is_match || !gated.get(idx).copied().unwrap_or(false)
+            };
+            for m in &mut matches {
+                m.context.retain(applicable);
+                if let Some(test) = &mut m.test_context {
+                    test.lines.retain(applicable);
+                    let kept: HashSet<u32> = test.lines.iter().map(|line| line.line_number).collect();
+                    test.assertion_lines.retain(|line_number| kept.contains(line_number));
+                }
             }
         }
 
         Ok(matches)
     }
 
-    /// Check if a file is in the examples directory
+    /// Build a match covering lines `start_idx..=end_idx` (0-based) with surrounding context
+    ///
+    /// Context is widened so it never splits one of the location's `fences`.
+    #[allow(deprecated)]
+    fn build_match(
+        &self,
+        location: MatchLocation<'_>,
+        lines: &[&str],
+        start_idx: usize,
+        end_idx: usize,
+        context_lines: usize,
+    ) -> Match {
+        let MatchLocation { relative_path, bytes, fences } = location;
+        // Location-only searches: the context is just the matched lines, so skip the window bookkeeping
+        let (context_start, context_end) = if context_lines == 0 && fences.is_empty() {
+            (start_idx, end_idx + 1)
+        } else {
+            let mut context_start = start_idx.saturating_sub(context_lines);
+            let mut context_end = std::cmp::min(end_idx + context_lines + 1, lines.len());
+
+            for &(open_idx, close_idx) in fences {
+                if open_idx <= end_idx && start_idx <= close_idx {
+                    context_start = context_start.min(open_idx);
+                    context_end = context_end.max(close_idx + 1).min(lines.len());
+                }
+            }
+            (context_start, context_end)
+        };
+
+        let context = (context_start..context_end)
+            .map(|idx| ContextLine {
+                line_number: (idx + 1) as u32,
+                content: lines[idx].to_string(),
+                is_match: (start_idx..=end_idx).contains(&idx),
+            })
+            .collect();
+
+        Match {
+            file_path: relative_path.to_path_buf(),
+            line_number: (start_idx + 1) as u32, // 1-based line numbers
+            line_content: lines[start_idx..=end_idx].join("\n"),
+            byte_start: bytes.start,
+            byte_end: bytes.end,
+            // Empty ranges collect without allocating, so zero-context matches stay lean
+            context_before: lines[context_start..start_idx].iter().map(|s| s.to_string()).collect(),
+            context_after: lines[end_idx + 1..context_end].iter().map(|s| s.to_string()).collect(),
+            context,
+            source: MatchSource::Code,
+            compiles: None,
+            doc_comment: None,
+            test_context: None,
+        }
+    }
+
+    /// Get a file's path relative to the crate root, with `/` separators
+    fn relative_path(&self, base_path: &Path, file_path: &Path) -> PathBuf {
+        normalize_path_separators(file_path.strip_prefix(base_path).unwrap_or(file_path))
+    }
+
+    /// Check if a file is a markdown file at the crate root that should be searched
+    fn is_root_markdown(&self, base_path: &Path, file_path: &Path) -> bool {
+        self.scope.markdown
+            && file_path.parent() == Some(base_path)
+            && file_path.extension().is_some_and(|ext| ext == "md")
+    }
+
+    /// Check if a file has one of the searched extensions, skipping dotfiles unless `include_hidden`
+    fn is_searched_file(&self, file_path: &Path) -> bool {
+        let has_extension = file_path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.iter().any(|searched| searched == ext));
+        let is_hidden = file_path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+
+        match (is_hidden, self.include_hidden) {
+            (false, _) => has_extension,
+            (true, false) => false,
+            (true, true) => has_extension || file_path.extension().is_none(),
+        }
+    }
+
+    /// Check if a searchable file belongs to a part of the crate the scope covers
+    fn in_scope(&self, base_path: &Path, file_path: &Path, is_build_script: bool) -> bool {
+        let relative_path = file_path.strip_prefix(base_path).unwrap_or(file_path);
+        if is_build_script {
+            self.scope.build_scripts
+        } else if is_example_path(relative_path) {
+            self.scope.examples
+        } else if self.is_root_markdown(base_path, file_path) {
+            true
+        } else if relative_path.components().next().is_some_and(|c| c.as_os_str() == "tests") {
+            self.scope.tests
+        } else {
+            self.scope.source
+        }
+    }
+
+    /// Check if a file is the build script at the crate root
+    fn is_build_script(&self, base_path: &Path, file_path: &Path) -> bool {
+        file_path.parent() == Some(base_path) && file_path.file_name().is_some_and(|name| name == "build.rs")
+    }
+
+    /// Check if a file is in the crate's top-level examples directory
     fn is_example_file(&self, base_path: &Path, file_path: &Path) -> bool {
-        if let Ok(relative_path) = file_path.strip_prefix(base_path) {
-            relative_path.components().any(|c| c.as_os_str() == "examples")
+        file_path.strip_prefix(base_path).is_ok_and(is_example_path)
+    }
+}
+
+/// Whether a crate-relative path is under the crate's top-level `examples/` directory
+///
+/// Only the first component counts, so a module like `src/examples/mod.rs` is source code.
+pub(crate) fn is_example_path(relative_path: &Path) -> bool {
+    relative_path.components().next().is_some_and(|c| c.as_os_str() == "examples")
+}
+
+/// Recursively collect every file under `current_path` as a crate-relative path and size
+fn collect_all_files(base_path: &Path, current_path: &Path, files: &mut Vec<(PathBuf, u64)>) -> Result<()> {
+    for entry in fs::read_dir(current_path)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            collect_all_files(base_path, &path, files)?;
+        } else {
+            let relative_path = normalize_path_separators(path.strip_prefix(base_path).unwrap_or(&path));
+            files.push((relative_path, metadata.len()));
+        }
+    }
+
+    Ok(())
+}
+
+/// The test function on 1-based lines `start..=end` around `m`, for `Match::test_context`
+fn test_context(name: &str, lines: &[&str], start: usize, end: usize, m: &Match) -> TestContext {
+    let matched: HashSet<u32> = m.context.iter().filter(|line| line.is_match).map(|line| line.line_number).collect();
+    let fn_lines: Vec<ContextLine> = (start.max(1)..=end.min(lines.len()))
+        .map(|line_number| ContextLine {
+            line_number: line_number as u32,
+            content: lines[line_number - 1].to_string(),
+            is_match: matched.contains(&(line_number as u32)),
+        })
+        .collect();
+    let assertion_lines = fn_lines.iter()
+        .filter(|line| test_fns::is_assertion(&line.content))
+        .map(|line| line.line_number)
+        .collect();
+
+    TestContext { name: name.to_string(), lines: fn_lines, assertion_lines }
+}
+
+/// How often each word (a run of alphanumerics and `_`) occurs in `text`
+fn word_counts(text: &str) -> HashMap<&str, usize> {
+    let mut counts = HashMap::new();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|word| !word.is_empty()) {
+        *counts.entry(word).or_default() += 1;
+    }
+    counts
+}
+
+/// Weighted Jaccard similarity of two word counts, from 0 (nothing shared) to 1 (same words)
+fn word_overlap(a: &HashMap<&str, usize>, b: &HashMap<&str, usize>) -> f64 {
+    let shared: usize = a.iter().map(|(word, count)| (*count).min(b.get(word).copied().unwrap_or(0))).sum();
+    let total: usize = a.values().sum::<usize>() + b.values().sum::<usize>() - shared;
+    if total == 0 { 0.0 } else { shared as f64 / total as f64 }
+}
+
+/// Score how representative an example file is; higher is better
+fn score_example(relative_path: &Path, content: &str, lib_name: &str) -> i32 {
+    let mut score = 0;
+
+    if content.contains("fn main") {
+        score += 3;
+    }
+
+    // Long enough to show real usage, short enough to read in one go
+    match content.lines().count() {
+        20..=150 => score += 2,
+        0..=4 | 400.. => score -= 2,
+        _ => {}
+    }
+
+    let name = relative_path.to_string_lossy().to_lowercase();
+    if ["basic", "simple", "hello", "minimal", "quickstart", "getting_started", "intro"]
+        .iter()
+        .any(|hint| name.contains(hint))
+    {
+        score += 3;
+    }
+
+    if content.contains(&format!("{}::", lib_name)) || content.contains(&format!("use {}", lib_name)) {
+        score += 2;
+    }
+
+    score
+}
+
+/// A `mod foo;` declaration on a line of its own
+static MOD_DECL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\s*)((?:pub(?:\([^)]*\))?\s+)?mod\s+(\w+))\s*;\s*$").unwrap());
+
+/// An `include!("...")` of a literal path on a line of its own
+static INCLUDE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^(\s*)include!\(\s*"([^"]+)"\s*\);?\s*$"#).unwrap());
+
+/// Replace `mod foo;` and `include!("...")` lines in `content` (read from `path`) with the referenced files
+///
+/// `is_root` marks a crate root such as an example's own file, whose modules live next to it.
+/// Only files inside `root`, the canonicalized crate root, are inlined, so a path such as
+/// `include!("../../secret")` or a symlink can't pull in files from outside the crate.
+fn inline_modules(root: &Path, path: &Path, content: &str, is_root: bool, depth: usize) -> String {
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    // Children of a root or `mod.rs` live next to it; otherwise under a directory named after the file
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let module_dir = if is_root || stem == "mod" { dir.to_path_buf() } else { dir.join(&stem) };
+
+    let read_small = |candidate: &Path| {
+        if !fs::canonicalize(candidate).ok()?.starts_with(root) {
+            return None;
+        }
+        let size = fs::metadata(candidate).ok()?.len();
+        if size > MAX_INLINED_FILE_BYTES { None } else { fs::read_to_string(candidate).ok() }
+    };
+
+    let inline_line = |line: &str| -> Option<String> {
+        if let Some(caps) = MOD_DECL.captures(line) {
+            let name = &caps[3];
+            let file = module_dir.join(format!("{}.rs", name));
+            let file = if file.exists() { file } else { module_dir.join(name).join("mod.rs") };
+            let body = inline_modules(root, &file, &read_small(&file)?, false, depth - 1);
+            Some(format!("{}{} {{\n{}{}}}", &caps[1], &caps[2], body, &caps[1]))
+        } else if let Some(caps) = INCLUDE.captures(line) {
+            let file = dir.join(&caps[2]);
+            let body = inline_modules(root, &file, &read_small(&file)?, false, depth - 1);
+            Some(format!("{}// include!(\"{}\")\n{}", &caps[1], &caps[2], body.trim_end()))
+        } else {
+            None
+        }
+    };
+
+    let mut resolved = String::new();
+    for line in content.lines() {
+        let inlined = if depth > 0 { inline_line(line) } else { None };
+        resolved.push_str(inlined.as_deref().unwrap_or(line));
+        resolved.push('\n');
+    }
+
+    resolved
+}
+
+/// Find fenced code blocks in markdown lines, as inclusive (opening, closing) line indices
+fn fenced_blocks(lines: &[&str]) -> Vec<(usize, usize)> {
+    let mut fences = Vec::new();
+    let mut open: Option<(usize, &str)> = None;
+
+    for (idx, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let marker = if trimmed.starts_with("```") {
+            "```"
+        } else if trimmed.starts_with("~~~") {
+            "~~~"
         } else {
-            false
+            continue;
+        };
+
+        match open {
+            Some((open_idx, open_marker)) if open_marker == marker => {
+                fences.push((open_idx, idx));
+                open = None;
+            }
+            Some(_) => {}
+            None => open = Some((idx, marker)),
         }
     }
+
+    // An unterminated fence runs to the end of the file
+    if let Some((open_idx, _)) = open {
+        fences.push((open_idx, lines.len().saturating_sub(1)));
+    }
+
+    fences
+}
+
+/// Whether a line opens or closes a fenced code block
+fn is_fence(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("```") || trimmed.starts_with("~~~")
+}
+
+/// The info string of a fence's opening line, e.g. `rust,no_run`
+fn fence_info(line: &str) -> &str {
+    line.trim_start().trim_start_matches(['`', '~']).trim()
+}
+
+/// Whether a fence token is a compiler error code like `E0382`, as `compile_fail` blocks may name
+fn is_error_code(token: &str) -> bool {
+    token.len() == 5 && token.starts_with('E') && token[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// How rustdoc treats a block with this fence info string, or `None` if it isn't Rust code
+fn doc_block_kind(info: &str) -> Option<DocBlockKind> {
+    const OTHER_ATTRIBUTES: &[&str] = &["rust", "test_harness", "standalone_crate"];
+
+    let tokens: Vec<&str> = info.split([',', ' ', '\t']).filter(|token| !token.is_empty()).collect();
+    let is_rust = !tokens.is_empty() && tokens.iter().all(|token| {
+        OTHER_ATTRIBUTES.contains(token)
+            || token.starts_with("edition")
+            || is_error_code(token)
+            || matches!(*token, "ignore" | "no_run" | "should_panic" | "compile_fail")
+    });
+    if !is_rust {
+        return None;
+    }
+
+    let has = |attribute: &str| tokens.contains(&attribute);
+    let kind = if has("compile_fail") {
+        DocBlockKind::CompileFail
+    } else if has("ignore") {
+        DocBlockKind::Ignore
+    } else if has("no_run") {
+        DocBlockKind::NoRun
+    } else if has("should_panic") {
+        DocBlockKind::ShouldPanic
+    } else {
+        DocBlockKind::Run
+    };
+    Some(kind)
+}
+
+/// Render a crate-relative path with `/` separators on every platform
+pub fn normalize_path_separators(path: &Path) -> PathBuf {
+    let components: Vec<_> = path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    PathBuf::from(components.join("/"))
+}
+
+/// The first match of `pattern` in `text`, which starts at byte `offset` of the file, covering code
+///
+/// The range is a file offset. `code` is a per-byte mask of the file from `code_mask`; without
+/// one, any match counts.
+fn code_match(pattern: &Regex, text: &str, offset: usize, code: Option<&[bool]>) -> Option<Range<usize>> {
+    pattern
+        .find_iter(text)
+        .map(|found| offset + found.start()..offset + found.end())
+        .find(|range| code.is_none_or(|code| code[range.clone()].contains(&true)))
+}
+
+/// Where a token query matches, as (0-based first line, last line, byte range in `content`)
+///
+/// `lines` are `content`'s lines. Only the first match starting on each line is kept. `None`
+/// if the file doesn't tokenize or parse.
+fn token_spans(content: &str, lines: &[&str], query: &TokenQuery) -> Option<Vec<(usize, usize, Range<usize>)>> {
+    let line_starts = line_offsets(content, lines);
+    let mut spans = match query {
+        TokenQuery::Ident(name) => ident_locations(without_bom(content), name)?
+            .into_iter()
+            .filter_map(|start| {
+                let offset = byte_offset(lines, &line_starts, start)?;
+                Some((start.line - 1, start.line - 1, offset..offset + name.len()))
+            })
+            .collect::<Vec<_>>(),
+        TokenQuery::Macro(name) => macros::invocation_spans(without_bom(content), name)?
+            .into_iter()
+            .filter_map(|(start, end)| {
+                let range = byte_offset(lines, &line_starts, start)?..byte_offset(lines, &line_starts, end)?;
+                Some((start.line - 1, (end.line - 1).min(lines.len() - 1), range))
+            })
+            .collect(),
+    };
+    spans.dedup_by_key(|(start_idx, _, _)| *start_idx);
+    Some(spans)
+}
+
+/// A custom matcher's spans, as (0-based first line, last line, byte range in `content`)
+///
+/// Spans outside `content` are dropped; only the first span starting on each line is kept.
+fn matcher_spans(matcher: &dyn Matcher, relative_path: &Path, content: &str, lines: &[&str]) -> Vec<(usize, usize, Range<usize>)> {
+    // Byte offset at which each line starts
+    let mut line_starts = vec![0];
+    line_starts.extend(content.match_indices('\n').map(|(idx, _)| idx + 1));
+    let line_of = |offset: usize| (line_starts.partition_point(|&start| start <= offset) - 1).min(lines.len().saturating_sub(1));
+
+    let mut ranges = matcher.find(relative_path, content);
+    ranges.retain(|range| range.byte_start <= range.byte_end && range.byte_end <= content.len());
+    ranges.sort_by_key(|range| (range.byte_start, range.byte_end));
+
+    let mut spans: Vec<_> = ranges.into_iter()
+        .filter(|_| !lines.is_empty())
+        .map(|range| {
+            let start_idx = line_of(range.byte_start);
+            // A span ending with a newline belongs to the line it terminates
+            let end_idx = line_of(range.byte_end.saturating_sub(1).max(range.byte_start)).max(start_idx);
+            (start_idx, end_idx, range.byte_start..range.byte_end)
+        })
+        .collect();
+    spans.dedup_by_key(|(start_idx, _, _)| *start_idx);
+    spans
+}
+
+/// `content` without a leading UTF-8 byte order mark, as some Windows editors write
+fn without_bom(content: &str) -> &str {
+    content.strip_prefix('\u{feff}').unwrap_or(content)
+}
+
+/// Byte offset in the file of each of `lines`, the `lines()` of `content` after any byte order mark
+///
+/// Tracks each line's length and terminator, `\n` or `\r\n`, so a BOM and CRLF endings are counted.
+fn line_offsets(content: &str, lines: &[&str]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(lines.len());
+    let mut offset = content.len() - without_bom(content).len();
+    for line in lines {
+        offsets.push(offset);
+        offset += line.len();
+        if content[offset..].starts_with("\r\n") {
+            offset += 2;
+        } else if content[offset..].starts_with('\n') {
+            offset += 1;
+        }
+    }
+    offsets
+}
+
+/// Byte offset in the file of a 1-based line and character column from a token span
+///
+/// `line_starts` are the offsets of `lines` from `line_offsets`.
+fn byte_offset(lines: &[&str], line_starts: &[usize], location: proc_macro2::LineColumn) -> Option<usize> {
+    let idx = location.line.checked_sub(1)?;
+    let line = lines.get(idx)?;
+    // Columns count characters, not bytes
+    let column = line.char_indices().nth(location.column).map_or(line.len(), |(idx, _)| idx);
+    Some(line_starts[idx] + column)
+}
+
+/// Start locations of the identifier tokens equal to `name`, sorted, or `None` if `content` doesn't tokenize
+fn ident_locations(content: &str, name: &str) -> Option<Vec<proc_macro2::LineColumn>> {
+    fn collect(tokens: proc_macro2::TokenStream, name: &str, found: &mut Vec<proc_macro2::LineColumn>) {
+        for token in tokens {
+            match token {
+                proc_macro2::TokenTree::Ident(ident) if ident == name => found.push(ident.span().start()),
+                proc_macro2::TokenTree::Group(group) => collect(group.stream(), name, found),
+                _ => {}
+            }
+        }
+    }
+
+    let tokens: proc_macro2::TokenStream = content.parse().ok()?;
+    let mut found = Vec::new();
+    collect(tokens, name, &mut found);
+    found.sort_by_key(|location| (location.line, location.column));
+    Some(found)
+}
+
+/// Mark each byte of Rust source that is code, as opposed to a comment, literal, or whitespace
+///
+/// A lightweight lexer rather than a parser: it understands line, doc, and nested block
+/// comments, string, byte string, raw string, and char literals, but is only a heuristic
+/// around unusual constructs such as a `/*` inside a macro's token soup.
+fn code_mask(content: &str) -> Vec<bool> {
+    let bytes = content.as_bytes();
+    let mut code = vec![false; bytes.len()];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        let after_ident = i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+        let raw_len = if after_ident { None } else { raw_string_len(rest) };
+
+        if rest.starts_with(b"//") {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else if rest.starts_with(b"/*") {
+            let mut depth = 0;
+            while i < bytes.len() {
+                if bytes[i..].starts_with(b"/*") {
+                    depth += 1;
+                    i += 2;
+                } else if bytes[i..].starts_with(b"*/") {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+        } else if rest[0] == b'"' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i += 1;
+        } else if let Some(len) = raw_len {
+            i += len;
+        } else if rest[0] == b'\'' {
+            i += char_literal_len(&content[i..]).unwrap_or_else(|| {
+                // A lifetime or label, which is code
+                code[i] = true;
+                1
+            });
+        } else {
+            code[i] = !rest[0].is_ascii_whitespace();
+            i += 1;
+        }
+    }
+
+    code
+}
+
+/// Length of a raw string literal such as `r#"..."#` or `br"..."` at the start of `bytes`
+fn raw_string_len(bytes: &[u8]) -> Option<usize> {
+    let prefix = if bytes.starts_with(b"br") { 2 } else if bytes.starts_with(b"r") { 1 } else { return None };
+    let hashes = bytes[prefix..].iter().take_while(|&&b| b == b'#').count();
+    if bytes.get(prefix + hashes) != Some(&b'"') {
+        return None;
+    }
+
+    let body = prefix + hashes + 1;
+    let closing: Vec<u8> = std::iter::once(b'"').chain(std::iter::repeat_n(b'#', hashes)).collect();
+    let end = bytes[body..]
+        .windows(closing.len())
+        .position(|window| window == closing.as_slice())
+        .map_or(bytes.len(), |pos| body + pos + closing.len());
+    Some(end)
+}
+
+/// Length of a char literal such as `'a'` or `'\n'` at the start of `text`, or `None` for a lifetime
+fn char_literal_len(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1);
+    let (_, first) = chars.next()?;
+    if first == '\\' {
+        // Escapes like '\n', '\'', or '\u{1F600}' run to the next quote after the escaped character
+        return text.get(3..)?.find('\'').map(|pos| pos + 4);
+    }
+
+    match chars.next() {
+        Some((idx, '\'')) => Some(idx + 1),
+        _ => None,
+    }
 }
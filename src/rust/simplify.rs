@@ -0,0 +1,183 @@
+//! Dropping `#[cfg(...)]`-gated code that doesn't apply to the host from snippets
+
+use proc_macro2::Span;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{Attribute, Expr, Lit, Meta, Token};
+
+/// Flag every line of `content` that belongs to code gated out by a `cfg` false on the host
+///
+/// Items, impl and trait items, statements, fields, variants, match arms, and block expressions
+/// are considered. Predicates that can't be decided without a build, such as `feature = "..."`
+/// or `test`, are treated as applicable. Files that don't parse yield no flagged lines.
+pub(crate) fn inapplicable_lines(content: &str) -> Vec<bool> {
+    let mut collector = GatedLines { lines: vec![false; content.lines().count()] };
+    if let Ok(file) = syn::parse_file(content) {
+        collector.visit_file(&file);
+    }
+    collector.lines
+}
+
+struct GatedLines {
+    lines: Vec<bool>,
+}
+
+impl GatedLines {
+    /// Flag the node's lines if its attributes gate it out, returning whether it was
+    fn gated(&mut self, attrs: &[Attribute], span: Span) -> bool {
+        if !attrs.iter().any(cfg_is_false) {
+            return false;
+        }
+
+        // Line numbers are 1-based; a span's end line is inclusive
+        let start = span.start().line.saturating_sub(1);
+        let end = span.end().line.min(self.lines.len());
+        for line in self.lines.iter_mut().take(end).skip(start) {
+            *line = true;
+        }
+        true
+    }
+}
+
+impl<'ast> Visit<'ast> for GatedLines {
+    fn visit_item(&mut self, node: &'ast syn::Item) {
+        let attrs = match node {
+            syn::Item::Const(item) => &item.attrs,
+            syn::Item::Enum(item) => &item.attrs,
+            syn::Item::ExternCrate(item) => &item.attrs,
+            syn::Item::Fn(item) => &item.attrs,
+            syn::Item::ForeignMod(item) => &item.attrs,
+            syn::Item::Impl(item) => &item.attrs,
+            syn::Item::Macro(item) => &item.attrs,
+            syn::Item::Mod(item) => &item.attrs,
+            syn::Item::Static(item) => &item.attrs,
+            syn::Item::Struct(item) => &item.attrs,
+            syn::Item::Trait(item) => &item.attrs,
+            syn::Item::TraitAlias(item) => &item.attrs,
+            syn::Item::Type(item) => &item.attrs,
+            syn::Item::Union(item) => &item.attrs,
+            syn::Item::Use(item) => &item.attrs,
+            _ => return visit::visit_item(self, node),
+        };
+        if !self.gated(attrs, node.span()) {
+            visit::visit_item(self, node);
+        }
+    }
+
+    fn visit_impl_item(&mut self, node: &'ast syn::ImplItem) {
+        let attrs = match node {
+            syn::ImplItem::Const(item) => &item.attrs,
+            syn::ImplItem::Fn(item) => &item.attrs,
+            syn::ImplItem::Type(item) => &item.attrs,
+            syn::ImplItem::Macro(item) => &item.attrs,
+            _ => return visit::visit_impl_item(self, node),
+        };
+        if !self.gated(attrs, node.span()) {
+            visit::visit_impl_item(self, node);
+        }
+    }
+
+    fn visit_trait_item(&mut self, node: &'ast syn::TraitItem) {
+        let attrs = match node {
+            syn::TraitItem::Const(item) => &item.attrs,
+            syn::TraitItem::Fn(item) => &item.attrs,
+            syn::TraitItem::Type(item) => &item.attrs,
+            syn::TraitItem::Macro(item) => &item.attrs,
+            _ => return visit::visit_trait_item(self, node),
+        };
+        if !self.gated(attrs, node.span()) {
+            visit::visit_trait_item(self, node);
+        }
+    }
+
+    fn visit_local(&mut self, node: &'ast syn::Local) {
+        if !self.gated(&node.attrs, node.span()) {
+            visit::visit_local(self, node);
+        }
+    }
+
+    fn visit_stmt_macro(&mut self, node: &'ast syn::StmtMacro) {
+        if !self.gated(&node.attrs, node.span()) {
+            visit::visit_stmt_macro(self, node);
+        }
+    }
+
+    fn visit_expr_block(&mut self, node: &'ast syn::ExprBlock) {
+        if !self.gated(&node.attrs, node.span()) {
+            visit::visit_expr_block(self, node);
+        }
+    }
+
+    fn visit_field(&mut self, node: &'ast syn::Field) {
+        if !self.gated(&node.attrs, node.span()) {
+            visit::visit_field(self, node);
+        }
+    }
+
+    fn visit_variant(&mut self, node: &'ast syn::Variant) {
+        if !self.gated(&node.attrs, node.span()) {
+            visit::visit_variant(self, node);
+        }
+    }
+
+    fn visit_arm(&mut self, node: &'ast syn::Arm) {
+        if !self.gated(&node.attrs, node.span()) {
+            visit::visit_arm(self, node);
+        }
+    }
+}
+
+/// Whether an attribute is a `#[cfg(...)]` whose predicate is definitely false on the host
+fn cfg_is_false(attr: &Attribute) -> bool {
+    attr.path().is_ident("cfg")
+        && attr.parse_args::<Meta>().is_ok_and(|predicate| evaluate(&predicate) == Some(false))
+}
+
+/// Evaluate a cfg predicate for the host, or `None` if it depends on the build configuration
+fn evaluate(predicate: &Meta) -> Option<bool> {
+    match predicate {
+        Meta::Path(path) => {
+            let name = path.get_ident()?.to_string();
+            match name.as_str() {
+                "unix" | "windows" => Some(std::env::consts::FAMILY == name),
+                _ => None,
+            }
+        }
+        Meta::NameValue(name_value) => {
+            let key = name_value.path.get_ident()?.to_string();
+            let Expr::Lit(syn::ExprLit { lit: Lit::Str(value), .. }) = &name_value.value else {
+                return None;
+            };
+            let host = host_value(&key)?;
+            Some(value.value() == host)
+        }
+        Meta::List(list) => {
+            let operator = list.path.get_ident()?.to_string();
+            let operands = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated).ok()?;
+            let values: Vec<Option<bool>> = operands.iter().map(evaluate).collect();
+            match operator.as_str() {
+                "all" if values.contains(&Some(false)) => Some(false),
+                "all" => values.iter().all(|v| *v == Some(true)).then_some(true),
+                "any" if values.contains(&Some(true)) => Some(true),
+                "any" => values.iter().all(|v| *v == Some(false)).then_some(false),
+                "not" if values.len() == 1 => values[0].map(|v| !v),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// The host's value for a target cfg key
+fn host_value(key: &str) -> Option<String> {
+    let value = match key {
+        "target_os" => std::env::consts::OS.to_string(),
+        "target_family" => std::env::consts::FAMILY.to_string(),
+        "target_arch" => std::env::consts::ARCH.to_string(),
+        "target_pointer_width" => usize::BITS.to_string(),
+        "target_endian" if cfg!(target_endian = "little") => "little".to_string(),
+        "target_endian" => "big".to_string(),
+        _ => return None,
+    };
+    Some(value)
+}
@@ -0,0 +1,59 @@
+//! Finding the `#[test]` function a match falls in, for `include_test_context`
+
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::Attribute;
+
+/// A test function spanning 1-based lines `start..=end`, its attributes included
+pub(crate) struct TestFn {
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+/// Every test function in `content`, or `None` if it doesn't parse
+pub(crate) fn test_functions(content: &str) -> Option<Vec<TestFn>> {
+    let file = syn::parse_file(content).ok()?;
+    let mut collector = TestFns { fns: Vec::new() };
+    collector.visit_file(&file);
+    Some(collector.fns)
+}
+
+/// The test function spanning 1-based `line`, as its name with the lines it covers
+///
+/// Test functions don't nest, but a `mod tests` can sit inside another, so the innermost wins.
+pub(crate) fn test_for_line(fns: &[TestFn], line: usize) -> Option<(&str, usize, usize)> {
+    fns.iter()
+        .filter(|test| test.start <= line && line <= test.end)
+        .min_by_key(|test| test.end - test.start)
+        .map(|test| (test.name.as_str(), test.start, test.end))
+}
+
+/// Whether a line invokes an assertion macro, such as `assert!`, `assert_eq!`, or `debug_assert_ne!`
+pub(crate) fn is_assertion(line: &str) -> bool {
+    let line = line.trim_start();
+    let name_len = line.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(line.len());
+    let name = &line[..name_len];
+    (name.starts_with("assert") || name.starts_with("debug_assert"))
+        && line[name_len..].trim_start().starts_with('!')
+}
+
+struct TestFns {
+    fns: Vec<TestFn>,
+}
+
+impl<'ast> Visit<'ast> for TestFns {
+    fn visit_item_fn(&mut self, node: &'ast syn::ItemFn) {
+        if node.attrs.iter().any(is_test_attr) {
+            // An item's span starts at its first outer attribute, so `#[test]` is covered too
+            let span = node.span();
+            self.fns.push(TestFn { name: node.sig.ident.to_string(), start: span.start().line, end: span.end().line });
+        }
+        visit::visit_item_fn(self, node);
+    }
+}
+
+/// `#[test]` or a runtime's test attribute, such as `#[tokio::test]`
+fn is_test_attr(attr: &Attribute) -> bool {
+    attr.path().segments.last().is_some_and(|segment| segment.ident == "test")
+}
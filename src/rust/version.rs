@@ -1,57 +1,361 @@
 //! Version resolution for Rust crates
 
 use crate::{Result, EgError};
-use cargo_metadata::{MetadataCommand, CargoOpt};
+use super::registry;
+use cargo_metadata::{DependencyKind as CargoDependencyKind, Metadata, MetadataCommand, CargoOpt, Package};
 use semver::{Version, VersionReq};
+use std::path::{Path, PathBuf};
+
+/// A published version of a crate
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AvailableVersion {
+    /// The semver version number
+    pub version: Version,
+    /// Whether this version has been yanked from the registry
+    pub yanked: bool,
+}
+
+/// What a crate's own manifest says about it, see `VersionResolver::manifest_details`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDetails {
+    /// The `documentation` URL, if set
+    pub documentation: Option<String>,
+    /// The `homepage` URL, if set
+    pub homepage: Option<String>,
+    /// The `repository` URL, if set
+    pub repository: Option<String>,
+    /// `[dependencies]` as (package name, version requirement)
+    pub dependencies: Vec<(String, String)>,
+    /// The `[dependencies]` taken from a local `path`, as (package name, directory)
+    pub dependency_paths: Vec<(String, PathBuf)>,
+    /// The `readme` file, relative to the manifest's directory, if set
+    pub readme: Option<PathBuf>,
+    /// Example targets as (name, crate-relative source path, required features)
+    pub examples: Vec<(String, PathBuf, Vec<String>)>,
+    /// Whether `include`/`exclude` keep the top-level `examples/` out of the package
+    pub excludes_examples: bool,
+}
+
+impl ManifestDetails {
+    /// The directory of a path dependency, or `None` if it comes from a registry
+    pub fn dependency_path(&self, dep_name: &str) -> Option<&Path> {
+        self.dependency_paths.iter()
+            .find(|(name, _)| name == dep_name)
+            .map(|(_, path)| path.as_path())
+    }
+}
+
+/// Which section of the project's manifests a dependency is declared in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    /// `[dependencies]`
+    Normal,
+    /// `[dev-dependencies]`
+    Dev,
+    /// `[build-dependencies]`
+    Build,
+}
+
+impl DependencyKind {
+    fn matches(self, kind: CargoDependencyKind) -> bool {
+        matches!(
+            (self, kind),
+            (DependencyKind::Normal, CargoDependencyKind::Normal)
+                | (DependencyKind::Dev, CargoDependencyKind::Development)
+                | (DependencyKind::Build, CargoDependencyKind::Build)
+        )
+    }
+}
+
+/// A crate resolved to its registry package name and an exact version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedCrate {
+    /// Package name as published on the registry
+    pub name: String,
+    /// The exact version
+    pub version: String,
+    /// Local sources to search instead of the registry's, for path, git, and `[patch]`ed dependencies
+    pub source_path: Option<PathBuf>,
+}
 
 /// Handles version resolution using the three-tier strategy
-pub struct VersionResolver;
+pub struct VersionResolver {
+    manifest_path: Option<PathBuf>,
+    stable_only: bool,
+    user_agent: Option<String>,
+    api_base_url: Option<String>,
+    locked: bool,
+    dep_kind: Option<DependencyKind>,
+}
+
+impl Default for VersionResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl VersionResolver {
     pub fn new() -> Self {
-        Self
+        Self {
+            manifest_path: None,
+            stable_only: true,
+            user_agent: None,
+            api_base_url: None,
+            locked: false,
+            dep_kind: None,
+        }
+    }
+
+    /// Only use the project's version if a workspace member declares the crate as this kind of dependency
+    ///
+    /// E.g. `DependencyKind::Dev` resolves the version a crate is tested against. Without this,
+    /// any package in the project's dependency graph counts.
+    pub fn dep_kind(mut self, kind: DependencyKind) -> Self {
+        self.dep_kind = Some(kind);
+        self
+    }
+
+    /// Use the project at this `Cargo.toml` instead of the one in the current directory
+    pub fn manifest_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest_path = Some(path.into());
+        self
+    }
+
+    /// Fail with a "version drift" error if resolution picks a different version than the project's Cargo.lock pins
+    ///
+    /// Like `cargo --locked`. Crates the current project doesn't depend on have no pin and are unaffected.
+    pub fn locked(mut self, enabled: bool) -> Self {
+        self.locked = enabled;
+        self
+    }
+
+    /// Identify registry requests with this user-agent instead of the process default
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Send crate lookups to this API root instead of `https://crates.io/api/v1`
+    ///
+    /// For mirrors and tests, like `CrateDiscovery::api_base_url`.
+    pub fn api_base_url(mut self, base_url: &str) -> Self {
+        self.api_base_url = Some(base_url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Prefer the newest stable release over a newer prerelease when resolving the latest version
+    ///
+    /// Enabled by default. A prerelease is still chosen if the crate has no stable release.
+    pub fn stable_only(mut self, enabled: bool) -> Self {
+        self.stable_only = enabled;
+        self
     }
 
     /// Resolve version using: explicit → current project → latest
+    #[tracing::instrument(level = "debug", skip(self), fields(version = tracing::field::Empty))]
     pub async fn resolve_version(&self, crate_name: &str, version_spec: Option<&str>) -> Result<String> {
-        if let Some(spec) = version_spec {
-            // Explicit version specified - find latest matching version
-            self.resolve_version_constraint(crate_name, spec).await
+        let version = self.resolve_crate(crate_name, version_spec).await?.version;
+        tracing::Span::current().record("version", version.as_str());
+        Ok(version)
+    }
+
+    /// Resolve the package name and version using: explicit → current project → latest
+    ///
+    /// Within the current project, `crate_name` may be either a package name or a library
+    /// target name (e.g. `foo_bar` for package `foo-bar`); the package name is returned.
+    ///
+    /// A `version_spec` of `"latest"` or `"*"` skips the current project and resolves the
+    /// newest published version.
+    #[tracing::instrument(level = "debug", skip(self), fields(package = tracing::field::Empty, version = tracing::field::Empty))]
+    pub async fn resolve_crate(&self, crate_name: &str, version_spec: Option<&str>) -> Result<ResolvedCrate> {
+        // The project's metadata is read at most once, for both the fallback and the `locked` check
+        let pinned = if version_spec.is_none() || self.locked {
+            self.find_in_current_project(crate_name)?
         } else {
-            // Try current project first
-            if let Ok(version) = self.find_in_current_project(crate_name) {
-                Ok(version)
-            } else {
-                // Fallback to latest
-                self.get_latest_version(crate_name).await
+            None
+        };
+
+        let resolved = match version_spec {
+            // Explicitly asked for the newest release, wherever the project is pinned
+            Some(spec) if is_latest_spec(spec) => self.get_latest_version(crate_name).await?,
+            // Explicit version specified - find latest matching version
+            Some(spec) => self.resolve_version_constraint(crate_name, spec).await?,
+            None => {
+                // Try current project first
+                if let Some(resolved) = pinned.clone() {
+                    resolved
+                } else {
+                    // Fallback to latest
+                    self.get_latest_version(crate_name).await?
+                }
             }
+        };
+
+        if self.locked
+            && let Some(pinned) = pinned
+            && pinned.version != resolved.version
+        {
+            return Err(EgError::Other(format!(
+                "version drift: {} resolves to {} but Cargo.lock pins {}",
+                crate_name, resolved.version, pinned.version
+            )));
         }
+
+        let span = tracing::Span::current();
+        span.record("package", resolved.name.as_str());
+        span.record("version", resolved.version.as_str());
+        Ok(resolved)
+    }
+
+    /// Find crate in current project's dependencies, by package name or library target name
+    ///
+    /// `None` when there is no project (no `Cargo.toml` in the current directory or its
+    /// ancestors) or the project doesn't depend on the crate. A project whose metadata can't
+    /// be read is an error rather than silently ignored.
+    fn find_in_current_project(&self, crate_name: &str) -> Result<Option<ResolvedCrate>> {
+        let mut command = MetadataCommand::new();
+        command.features(CargoOpt::AllFeatures);
+        if let Some(manifest_path) = &self.manifest_path {
+            command.manifest_path(manifest_path);
+        } else if !in_cargo_project()? {
+            return Ok(None);
+        }
+        let metadata = command.exec()?;
+
+        let found = match self.dep_kind {
+            Some(kind) => declared_dependency(&metadata, crate_name, kind),
+            None => {
+                // Look through all packages in the resolved dependency graph, preferring exact package names
+                let by_package_name = metadata.packages.iter()
+                    .find(|package| package.name.as_str() == crate_name);
+                by_package_name.or_else(|| metadata.packages.iter().find(|package| provides_lib(package, crate_name)))
+            }
+        };
+
+        if let Some(package) = found {
+            // Anything not from crates.io (a path, git, or `[patch]` source) differs from the
+            // published crate, so point at the sources cargo actually builds
+            let from_crates_io = package.source.as_ref().is_some_and(|source| source.is_crates_io());
+            let source_path = if from_crates_io {
+                None
+            } else {
+                package.manifest_path.parent().map(|dir| dir.as_std_path().to_path_buf())
+            };
+
+            return Ok(Some(ResolvedCrate {
+                name: package.name.as_str().to_string(),
+                version: package.version.to_string(),
+                source_path,
+            }));
+        }
+
+        Ok(None)
     }
 
-    /// Find crate version in current project's dependencies
-    fn find_in_current_project(&self, crate_name: &str) -> Result<String> {
+    /// Read the package name and version declared in a manifest
+    pub fn manifest_package(&self, manifest_path: &Path) -> Result<ResolvedCrate> {
         let metadata = MetadataCommand::new()
-            .features(CargoOpt::AllFeatures)
+            .manifest_path(manifest_path)
+            .no_deps()
             .exec()?;
 
-        // Look through all packages in the resolved dependency graph
-        for package in metadata.packages {
-            if package.name.as_str() == crate_name {
-                return Ok(package.version.to_string());
-            }
+        let package = metadata.root_package()
+            .ok_or_else(|| EgError::Other(format!("No package in {}", manifest_path.display())))?;
+
+        Ok(ResolvedCrate {
+            name: package.name.as_str().to_string(),
+            version: package.version.to_string(),
+            source_path: None,
+        })
+    }
+
+    /// The package of the project being developed, with its directory as the source path
+    ///
+    /// Uses the project at `manifest_path` if set, otherwise the one containing the current
+    /// directory. Fails for a virtual workspace manifest, which has no package of its own.
+    pub fn current_package(&self) -> Result<ResolvedCrate> {
+        let mut command = MetadataCommand::new();
+        command.no_deps();
+        if let Some(manifest_path) = &self.manifest_path {
+            command.manifest_path(manifest_path);
         }
+        let metadata = command.exec()?;
 
-        Err(EgError::CrateNotFound(crate_name.to_string()))
+        let package = metadata.root_package()
+            .ok_or_else(|| EgError::Other(format!("No package in {}", metadata.workspace_root)))?;
+
+        Ok(ResolvedCrate {
+            name: package.name.as_str().to_string(),
+            version: package.version.to_string(),
+            source_path: package.manifest_path.parent().map(|dir| dir.as_std_path().to_path_buf()),
+        })
+    }
+
+    /// Read the `documentation` and `homepage` links declared in a manifest
+    pub fn manifest_links(&self, manifest_path: &Path) -> Result<(Option<String>, Option<String>)> {
+        let details = self.manifest_details(manifest_path)?;
+        Ok((details.documentation, details.homepage))
+    }
+
+    /// List the normal dependencies declared in a manifest as (package name, version requirement)
+    pub fn manifest_dependencies(&self, manifest_path: &Path) -> Result<Vec<(String, String)>> {
+        Ok(self.manifest_details(manifest_path)?.dependencies)
+    }
+
+    /// Read everything eg uses from a manifest in one `cargo metadata` call
+    ///
+    /// A manifest without a `[package]`, such as a virtual workspace's, has no details to report.
+    pub fn manifest_details(&self, manifest_path: &Path) -> Result<ManifestDetails> {
+        let metadata = MetadataCommand::new()
+            .manifest_path(manifest_path)
+            .no_deps()
+            .exec()?;
+
+        let Some(package) = metadata.root_package() else {
+            return Ok(ManifestDetails::default());
+        };
+
+        let crate_dir = package.manifest_path.parent().map_or(Path::new(""), |dir| dir.as_std_path());
+        Ok(ManifestDetails {
+            documentation: package.documentation.clone(),
+            homepage: package.homepage.clone(),
+            repository: package.repository.clone(),
+            dependencies: package.dependencies.iter()
+                .filter(|dep| dep.kind == CargoDependencyKind::Normal)
+                .map(|dep| (dep.name.clone(), dep.req.to_string()))
+                .collect(),
+            dependency_paths: package.dependencies.iter()
+                .filter(|dep| dep.kind == CargoDependencyKind::Normal)
+                .filter_map(|dep| Some((dep.name.clone(), dep.path.as_ref()?.as_std_path().to_path_buf())))
+                .collect(),
+            readme: package.readme.as_ref().map(PathBuf::from),
+            examples: package.targets.iter()
+                .filter(|target| target.is_example())
+                .map(|target| {
+                    let src_path = target.src_path.as_std_path();
+                    let relative = src_path.strip_prefix(crate_dir).unwrap_or(src_path);
+                    (target.name.clone(), relative.to_path_buf(), target.required_features.clone())
+                })
+                .collect(),
+            excludes_examples: manifest_excludes_examples(manifest_path)?,
+        })
+    }
+
+    /// Read the example targets declared in a manifest, as crate-relative source paths with their required features
+    pub fn manifest_examples(&self, manifest_path: &Path) -> Result<Vec<(String, PathBuf, Vec<String>)>> {
+        Ok(self.manifest_details(manifest_path)?.examples)
     }
 
     /// Resolve version constraint to latest matching version
-    async fn resolve_version_constraint(&self, crate_name: &str, constraint: &str) -> Result<String> {
+    async fn resolve_version_constraint(&self, crate_name: &str, constraint: &str) -> Result<ResolvedCrate> {
         let req = VersionReq::parse(constraint)?;
-        let available_versions = self.get_available_versions(crate_name).await?;
+        let crate_info = self.fetch_crate(crate_name).await?;
+        let available_versions = self.parse_versions(&crate_info);
         
         // Find the latest version that matches the constraint
         let mut matching_versions: Vec<_> = available_versions
             .into_iter()
+            .map(|v| v.version)
             .filter(|v| req.matches(v))
             .collect();
         
@@ -59,7 +363,11 @@ impl VersionResolver {
         
         matching_versions
             .last()
-            .map(|v| v.to_string())
+            .map(|v| ResolvedCrate {
+                name: crate_info.crate_data.name.clone(),
+                version: v.to_string(),
+                source_path: None,
+            })
             .ok_or_else(|| EgError::NoMatchingVersions {
                 crate_name: crate_name.to_string(),
                 constraint: constraint.to_string(),
@@ -67,36 +375,172 @@ impl VersionResolver {
     }
 
     /// Get latest version from crates.io
-    async fn get_latest_version(&self, crate_name: &str) -> Result<String> {
-        let client = crates_io_api::AsyncClient::new(
-            "eg-library (https://github.com/socratic-shell/eg)",
-            std::time::Duration::from_millis(1000),
-        ).map_err(|e| EgError::Other(e.to_string()))?;
+    async fn get_latest_version(&self, crate_name: &str) -> Result<ResolvedCrate> {
+        let crate_info = self.fetch_crate(crate_name).await?;
+        let crate_data = crate_info.crate_data;
 
-        let crate_info = client.get_crate(crate_name).await
-            .map_err(|_| EgError::CrateNotFound(crate_name.to_string()))?;
+        let version = match crate_data.max_stable_version {
+            Some(stable) if self.stable_only => stable,
+            _ => crate_data.max_version,
+        };
 
-        Ok(crate_info.crate_data.max_version)
+        Ok(ResolvedCrate {
+            name: crate_data.name,
+            version,
+            source_path: None,
+        })
     }
 
-    /// Get all available versions from crates.io
-    async fn get_available_versions(&self, crate_name: &str) -> Result<Vec<Version>> {
-        let client = crates_io_api::AsyncClient::new(
-            "eg-library (https://github.com/socratic-shell/eg)",
-            std::time::Duration::from_millis(1000),
-        ).map_err(|e| EgError::Other(e.to_string()))?;
-
+    /// Get all available versions from crates.io, sorted from oldest to newest
+    pub async fn get_available_versions(&self, crate_name: &str) -> Result<Vec<AvailableVersion>> {
         // Get crate info which includes versions
-        let crate_info = client.get_crate(crate_name).await
-            .map_err(|_| EgError::CrateNotFound(crate_name.to_string()))?;
+        let crate_info = self.fetch_crate(crate_name).await?;
+        Ok(self.parse_versions(&crate_info))
+    }
+
+    /// The crate's `repository` URL as published on crates.io, or `None` if it has none
+    pub async fn get_repository_url(&self, crate_name: &str) -> Result<Option<String>> {
+        let crate_info = self.fetch_crate(crate_name).await?;
+        Ok(crate_info.crate_data.repository.filter(|url| !url.trim().is_empty()))
+    }
+
+    /// Fetch crate info from crates.io, trying the name variants cargo treats as equivalent
+    ///
+    /// The returned info carries the registry's canonical name; errors report `crate_name` as given.
+    async fn fetch_crate(&self, crate_name: &str) -> Result<crates_io_api::CrateResponse> {
+        let user_agent = self.user_agent.clone().unwrap_or_else(crate::default_user_agent);
+        let client = registry::api_client(&user_agent)?;
+
+        for candidate in name_variants(crate_name) {
+            let response = match &self.api_base_url {
+                Some(base_url) => fetch_crate_from(base_url, &candidate, &user_agent).await,
+                None => match registry::with_backoff(|| async { Ok(client.get_crate(&candidate).await?) }).await {
+                    Ok(crate_info) => Ok(Some(crate_info)),
+                    Err(EgError::RegistryError(crates_io_api::Error::NotFound(_))) => Ok(None),
+                    Err(e) => Err(e),
+                },
+            };
+
+            match response? {
+                Some(crate_info) => return Ok(crate_info),
+                // Not under this spelling; try the next one
+                None => continue,
+            }
+        }
 
+        Err(EgError::CrateNotFound(crate_name.to_string()))
+    }
+
+    /// Parse the published versions in a crate response, sorted from oldest to newest
+    fn parse_versions(&self, crate_info: &crates_io_api::CrateResponse) -> Vec<AvailableVersion> {
         let mut parsed_versions = Vec::new();
-        for version in crate_info.versions {
+        for version in &crate_info.versions {
             if let Ok(v) = Version::parse(&version.num) {
-                parsed_versions.push(v);
+                parsed_versions.push(AvailableVersion {
+                    version: v,
+                    yanked: version.yanked,
+                });
             }
         }
 
-        Ok(parsed_versions)
+        parsed_versions.sort();
+        parsed_versions
     }
 }
+
+/// Whether a manifest's `include`/`exclude` lists keep the top-level `examples/` out of the package
+///
+/// `exclude` drops it when a pattern names the directory itself or everything in it. An `include`
+/// list drops it unless one of its patterns could match under `examples/`.
+fn manifest_excludes_examples(manifest_path: &Path) -> Result<bool> {
+    let manifest = std::fs::read_to_string(manifest_path)
+        .map_err(|e| EgError::ReadError { path: manifest_path.to_path_buf(), source: e })?;
+    let manifest = manifest.parse::<toml::Table>()
+        .map_err(|e| EgError::Other(format!("Invalid manifest {}: {}", manifest_path.display(), e)))?;
+    let Some(package) = manifest.get("package").and_then(|package| package.as_table()) else {
+        return Ok(false);
+    };
+
+    let patterns = |key: &str| -> Option<Vec<String>> {
+        let list = package.get(key)?.as_array()?;
+        Some(list.iter().filter_map(|pattern| pattern.as_str()).map(|pattern| pattern.trim_start_matches('/').to_string()).collect())
+    };
+
+    if let Some(include) = patterns("include") {
+        return Ok(!include.iter().any(|pattern| pattern.starts_with("examples") || pattern.starts_with('*')));
+    }
+    Ok(patterns("exclude").is_some_and(|exclude| {
+        exclude.iter().any(|pattern| {
+            matches!(pattern.trim_end_matches(['/', '*']), "examples")
+        })
+    }))
+}
+
+/// Fetch crate info from `{base_url}/crates/{crate_name}`, or `None` if the registry doesn't have it
+///
+/// For API roots other than crates.io, which `crates_io_api` can't be pointed at.
+async fn fetch_crate_from(base_url: &str, crate_name: &str, user_agent: &str) -> Result<Option<crates_io_api::CrateResponse>> {
+    let url = format!("{}/crates/{}", base_url, crate_name);
+    registry::with_backoff(|| async {
+        registry::api_rate_limit().await;
+        let response = registry::http_client()
+            .get(&url)
+            .header(reqwest::header::USER_AGENT, user_agent)
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let status = response.status().as_u16();
+        let response = response
+            .error_for_status()
+            .map_err(|source| EgError::DownloadHttpStatus { status, source })?;
+        Ok(Some(response.json().await?))
+    }).await
+}
+
+/// Name spellings that refer to the same crate: registry names are case-insensitive and
+/// treat `-` and `_` as equivalent. The name as given is tried first.
+fn name_variants(crate_name: &str) -> Vec<String> {
+    let lowercase = crate_name.to_lowercase();
+    let mut variants = vec![
+        crate_name.to_string(),
+        lowercase.clone(),
+        lowercase.replace('_', "-"),
+        lowercase.replace('-', "_"),
+    ];
+
+    let mut seen = std::collections::HashSet::new();
+    variants.retain(|variant| seen.insert(variant.clone()));
+    variants
+}
+
+/// Whether a package is named `crate_name` or has a library target by that name
+fn provides_lib(package: &Package, crate_name: &str) -> bool {
+    package.targets.iter().any(|target| {
+        (target.is_lib() || target.is_proc_macro()) && target.name == crate_name
+    })
+}
+
+/// The package a workspace member depends on as `crate_name` with the given kind of dependency
+fn declared_dependency<'a>(metadata: &'a Metadata, crate_name: &str, kind: DependencyKind) -> Option<&'a Package> {
+    let resolve = metadata.resolve.as_ref()?;
+    resolve.nodes.iter()
+        .filter(|node| metadata.workspace_members.contains(&node.id))
+        .flat_map(|node| &node.deps)
+        .filter(|dep| dep.dep_kinds.iter().any(|info| kind.matches(info.kind)))
+        .filter_map(|dep| metadata.packages.iter().find(|package| package.id == dep.pkg))
+        .find(|package| package.name.as_str() == crate_name || provides_lib(package, crate_name))
+}
+
+/// Whether the current directory is inside a Cargo project, as `cargo metadata` would find it
+fn in_cargo_project() -> Result<bool> {
+    let cwd = std::env::current_dir()?;
+    Ok(cwd.ancestors().any(|dir| dir.join("Cargo.toml").is_file()))
+}
+
+/// Whether a version spec asks for the newest release (`"latest"` or `"*"`) rather than a constraint
+pub(crate) fn is_latest_spec(spec: &str) -> bool {
+    matches!(spec.trim(), "latest" | "*")
+}
@@ -0,0 +1,23 @@
+//! Tests for compile-checking snippets (requires the `check` feature)
+
+#![cfg(feature = "check")]
+
+use eg::rust::SnippetChecker;
+
+/// Test that a correct snippet compiles and a broken one does not
+#[tokio::test(flavor = "current_thread")]
+async fn test_snippet_checker() {
+    let checker = SnippetChecker::new("semver", "1.0.26");
+
+    let good = checker
+        .check("fn main() { let _ = semver::Version::parse(\"1.2.3\"); }")
+        .await
+        .expect("Should run cargo check");
+    assert!(good, "Correct snippet should compile");
+
+    let broken = checker
+        .check("fn main() { let _: u32 = semver::Version::parse(\"1.2.3\"); }")
+        .await
+        .expect("Should run cargo check");
+    assert!(!broken, "Broken snippet should not compile");
+}
@@ -0,0 +1,168 @@
+//! Shared fixtures for tests that run without network access
+
+#![allow(dead_code)]
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs;
+use std::path::PathBuf;
+
+/// Create a fresh fixture directory containing the given (relative path, contents) files
+pub fn fixture_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join("eg-tests")
+        .join(format!("{}-{}", name, std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).expect("Should clear old fixture");
+    }
+    fs::create_dir_all(&dir).expect("Should create fixture dir");
+    for (relative_path, contents) in files {
+        let path = dir.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).expect("Should create fixture dirs");
+        fs::write(&path, contents).expect("Should write fixture file");
+    }
+    dir
+}
+
+/// Build the bytes of a `.crate` archive, nesting files under `{crate_name}-{version}/` like crates.io does
+pub fn crate_archive(crate_name: &str, version: &str, files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (relative_path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let path = format!("{}-{}/{}", crate_name, version, relative_path);
+        builder.append_data(&mut header, path, contents.as_bytes()).expect("Should append archive entry");
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .expect("Should finish archive")
+}
+
+/// Serve one HTTP request on localhost with a canned `status` and `body`
+///
+/// Returns the server's base URL and a handle that yields the request's lowercased header lines.
+pub fn mock_http_server(status: &'static str, body: &'static str) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    mock_http_server_bytes(status, body.as_bytes().to_vec())
+}
+
+/// Like `mock_http_server`, with a binary body such as a `.crate` archive
+pub fn mock_http_server_bytes(status: &'static str, body: Vec<u8>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    let (base_url, handle) = mock_http_server_sequence(vec![(status, body)]);
+    (base_url, std::thread::spawn(move || handle.join().unwrap().remove(0)))
+}
+
+/// Serve one request per (status, body) response, in order
+///
+/// The handle yields each request's lowercased header lines, in the order they were served.
+pub fn mock_http_server_sequence(responses: Vec<(&'static str, Vec<u8>)>) -> (String, std::thread::JoinHandle<Vec<Vec<String>>>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Should bind mock server");
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = std::thread::spawn(move || responses.into_iter().map(|(status, body)| {
+        let (stream, _) = listener.accept().expect("Should accept a request");
+        let mut headers = Vec::new();
+        let mut reader = BufReader::new(&stream);
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).expect("Should read request");
+            if line.trim().is_empty() {
+                break;
+            }
+            headers.push(line.trim().to_lowercase());
+        }
+
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        );
+        (&stream).write_all(head.as_bytes()).expect("Should write response");
+        (&stream).write_all(&body).expect("Should write response body");
+        headers
+    }).collect());
+
+    (base_url, handle)
+}
+
+/// Serve requests on localhost until the process exits, answering each by its path from `routes`
+///
+/// Unknown paths get a 404. Requests may arrive in any order, e.g. from concurrent searches;
+/// the returned list collects the requested paths as they are served.
+pub fn mock_http_server_routes(routes: Vec<(String, Vec<u8>)>) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Should bind mock server");
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let requested = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let served = requested.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.expect("Should accept a request");
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).expect("Should read request");
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).expect("Should read request");
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+
+            // GET /path?query HTTP/1.1
+            let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+            let path = target.split('?').next().unwrap_or_default().to_string();
+            let (status, body) = match routes.iter().find(|(route, _)| *route == path) {
+                Some((_, body)) => ("200 OK", body.clone()),
+                None => ("404 Not Found", Vec::new()),
+            };
+            served.lock().unwrap().push(path);
+
+            let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+            (&stream).write_all(head.as_bytes()).expect("Should write response");
+            (&stream).write_all(&body).expect("Should write response body");
+        }
+    });
+
+    (base_url, requested)
+}
+
+/// The crates.io API's `/crates/{name}` response for a crate with these versions, oldest first
+pub fn crate_response(crate_name: &str, versions: &[&str]) -> Vec<u8> {
+    let timestamp = "2024-01-01T00:00:00Z";
+    let newest = versions.last().copied().unwrap_or_default();
+    let newest_stable = versions.iter().rev().find(|version| !version.contains('-')).copied();
+    let response = serde_json::json!({
+        "categories": [],
+        "keywords": [],
+        "crate": {
+            "id": crate_name,
+            "name": crate_name,
+            "downloads": 1000,
+            "max_version": newest,
+            "max_stable_version": newest_stable,
+            "links": {"owner_team": "", "owner_user": "", "owners": "", "reverse_dependencies": "", "version_downloads": ""},
+            "created_at": timestamp,
+            "updated_at": timestamp,
+        },
+        "versions": versions.iter().enumerate().map(|(id, version)| serde_json::json!({
+            "crate": crate_name,
+            "created_at": timestamp,
+            "updated_at": timestamp,
+            "dl_path": format!("/api/v1/crates/{}/{}/download", crate_name, version),
+            "downloads": 100,
+            "features": {},
+            "id": id,
+            "num": version,
+            "yanked": false,
+            "links": {"dependencies": "", "version_downloads": ""},
+        })).collect::<Vec<_>>(),
+    });
+    response.to_string().into_bytes()
+}
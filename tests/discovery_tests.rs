@@ -0,0 +1,70 @@
+//! Tests for finding crates on the registry, against a mock crates.io API
+
+mod common;
+
+use common::{mock_http_server_bytes, mock_http_server_sequence};
+
+/// Test that keyword discovery drops crates below the download threshold
+#[tokio::test(flavor = "current_thread")]
+async fn test_min_downloads() {
+    let listing = |name: &str, downloads: u64| format!(
+        r#"{{"id":"{name}","name":"{name}","description":null,"downloads":{downloads},"max_version":"1.0.0","links":{{"owner_team":"","owner_user":"","owners":"","reverse_dependencies":"","version_downloads":""}},"created_at":"2020-01-01T00:00:00Z","updated_at":"2024-01-01T00:00:00Z"}}"#
+    );
+    let body = format!(
+        r#"{{"crates":[{},{},{}],"meta":{{"total":3}}}}"#,
+        listing("demo-popular", 2_500_000),
+        listing("demo-abandoned", 40),
+        listing("demo-established", 90_000),
+    );
+    let (base_url, server) = mock_http_server_bytes("200 OK", body.into_bytes());
+
+    let crates = eg::rust::CrateDiscovery::new()
+        .api_base_url(&base_url)
+        .min_downloads(10_000)
+        .find_by_keyword("demo")
+        .await
+        .expect("Should parse the listing");
+
+    let names: Vec<&str> = crates.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["demo-popular", "demo-established"]);
+    let request = server.join().unwrap();
+    assert!(request[0].contains("keyword=demo"), "Should query by keyword: {:?}", request[0]);
+}
+
+/// Test that keyword discovery retries a transient registry failure
+#[tokio::test(flavor = "current_thread")]
+async fn test_keyword_discovery_retry() {
+    let body = r#"{"crates":[{"id":"demo-retried","name":"demo-retried","description":null,"downloads":1000,"max_version":"1.0.0","links":{"owner_team":"","owner_user":"","owners":"","reverse_dependencies":"","version_downloads":""},"created_at":"2020-01-01T00:00:00Z","updated_at":"2024-01-01T00:00:00Z"}],"meta":{"total":1}}"#;
+    let (base_url, server) = mock_http_server_sequence(vec![
+        ("503 Service Unavailable", Vec::new()),
+        ("200 OK", body.as_bytes().to_vec()),
+    ]);
+
+    let crates = eg::rust::CrateDiscovery::new()
+        .api_base_url(&base_url)
+        .find_by_keyword("demo")
+        .await
+        .expect("Should succeed after retrying");
+
+    assert_eq!(crates[0].name, "demo-retried");
+    assert_eq!(server.join().unwrap().len(), 2, "Should have retried once");
+}
+
+/// Test that discovery results carry recent downloads and the number of versions
+#[tokio::test(flavor = "current_thread")]
+async fn test_discovery_momentum() {
+    let body = r#"{"crates":[{"id":"demo-active","name":"demo-active","description":null,"downloads":900000,"recent_downloads":120000,"versions":[31,30,29,28],"max_version":"2.3.0","links":{"owner_team":"","owner_user":"","owners":"","reverse_dependencies":"","version_downloads":""},"created_at":"2020-01-01T00:00:00Z","updated_at":"2024-01-01T00:00:00Z"},{"id":"demo-legacy","name":"demo-legacy","description":null,"downloads":50000,"max_version":"0.1.0","links":{"owner_team":"","owner_user":"","owners":"","reverse_dependencies":"","version_downloads":""},"created_at":"2016-01-01T00:00:00Z","updated_at":"2016-01-01T00:00:00Z"}],"meta":{"total":2}}"#;
+    let (base_url, _server) = mock_http_server_bytes("200 OK", body.as_bytes().to_vec());
+
+    let crates = eg::rust::CrateDiscovery::new()
+        .api_base_url(&base_url)
+        .find_by_keyword("demo")
+        .await
+        .expect("Should parse the listing");
+
+    assert_eq!(crates[0].name, "demo-active");
+    assert_eq!(crates[0].recent_downloads, Some(120_000));
+    assert_eq!(crates[0].version_count, Some(4));
+    assert_eq!(crates[1].recent_downloads, None, "Listings may leave the fields out");
+    assert_eq!(crates[1].version_count, None);
+}
@@ -0,0 +1,1002 @@
+//! Tests for extracting `.crate` archives, without touching the network
+
+mod common;
+
+use common::{crate_archive, crate_response, fixture_dir, mock_http_server, mock_http_server_bytes, mock_http_server_routes, mock_http_server_sequence};
+use eg::rust::CrateExtractor;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Test that identical archives extracted under two names share one content directory
+#[tokio::test(flavor = "current_thread")]
+async fn test_content_addressed_dedup() {
+    let cache_dir = fixture_dir("content-dedup", &[]);
+    let bytes = crate_archive("demo", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ]);
+
+    let extractor = CrateExtractor::new();
+    let first = extractor
+        .extract_bytes_to_cache(&bytes, &cache_dir.join("demo-0.1.0"))
+        .await
+        .expect("First extraction should succeed");
+    let second = extractor
+        .extract_bytes_to_cache(&bytes, &cache_dir.join("demo-mirror-0.1.0"))
+        .await
+        .expect("Second extraction should succeed");
+
+    assert!(first.join("src/lib.rs").exists(), "Should flatten the archive's top-level directory");
+    assert_eq!(
+        fs::canonicalize(&first).unwrap(),
+        fs::canonicalize(&second).unwrap(),
+        "Both names should resolve to the same content directory"
+    );
+
+    let content_dirs: Vec<_> = fs::read_dir(cache_dir.join("content")).unwrap().collect();
+    assert_eq!(content_dirs.len(), 1, "Should extract the content only once");
+}
+
+/// Test that concurrent extractions of one archive to one path all succeed and leave one copy
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_extraction() {
+    let cache_dir = fixture_dir("concurrent-extraction", &[]);
+    let bytes = crate_archive("demo", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ]);
+    let extraction_path = cache_dir.join("demo-0.1.0");
+
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            let (bytes, extraction_path) = (bytes.clone(), extraction_path.clone());
+            tokio::spawn(async move {
+                CrateExtractor::new().extract_bytes_to_cache(&bytes, &extraction_path).await
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap().expect("Every concurrent extraction should succeed");
+    }
+
+    assert_eq!(fs::read_to_string(extraction_path.join("src/lib.rs")).unwrap(), "pub fn demo() {}");
+    let content_dirs: Vec<_> = fs::read_dir(cache_dir.join("content")).unwrap().collect();
+    assert_eq!(content_dirs.len(), 1, "Should leave one content directory and no partial ones");
+    let entries: Vec<_> = fs::read_dir(&cache_dir).unwrap().collect();
+    assert_eq!(entries.len(), 2, "Should leave only the link and the content directory");
+}
+
+/// Test lazily iterating example files straight from a `.crate` archive
+#[test]
+fn test_iter_example_files() {
+    let bytes = crate_archive("demo", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+        ("examples/hello.rs", "fn main() { demo::demo(); }"),
+        ("examples/server/main.rs", "fn main() {}"),
+        ("examples/README.md", "Not Rust"),
+    ]);
+
+    let files: Vec<_> = CrateExtractor::new()
+        .iter_example_files(std::io::Cursor::new(bytes))
+        .collect::<Result<_, _>>()
+        .expect("Should read example files");
+
+    let paths: Vec<_> = files.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(paths, vec![
+        PathBuf::from("examples/hello.rs"),
+        PathBuf::from("examples/server/main.rs"),
+    ]);
+    assert_eq!(files[0].1, "fn main() { demo::demo(); }");
+}
+
+/// Test searching a local `.crate` archive without any registry interaction
+#[tokio::test(flavor = "current_thread")]
+async fn test_search_crate_file() {
+    let dir = fixture_dir("search-crate-file", &[]);
+    let crate_file = dir.join("demo-widgets-0.4.2.crate");
+    fs::write(&crate_file, crate_archive("demo-widgets", "0.4.2", &[
+        ("Cargo.toml", "[package]\nname = \"demo-widgets\"\nversion = \"0.4.2\"\n"),
+        ("src/lib.rs", "pub fn render_widget() {}"),
+        ("examples/gallery.rs", "fn main() { demo_widgets::render_widget(); }"),
+    ])).unwrap();
+
+    let result = eg::Eg::rust_crate_file(&crate_file)
+        .pattern(r"render_widget")
+        .expect("Should compile regex")
+        .search()
+        .await
+        .expect("Should search the archive");
+
+    assert_eq!(result.version, "0.4.2", "Should infer the version from the file name");
+    assert_eq!(result.example_matches.len(), 1);
+    assert_eq!(result.example_matches[0].file_path, PathBuf::from("examples/gallery.rs"));
+    assert_eq!(result.other_matches.len(), 1);
+}
+
+/// Test that the manifest's documentation and homepage links are reported with the result
+#[tokio::test(flavor = "current_thread")]
+async fn test_manifest_links() {
+    let dir = fixture_dir("manifest-links", &[]);
+    let crate_file = dir.join("demo-links-0.1.0.crate");
+    fs::write(&crate_file, crate_archive("demo-links", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-links\"\nversion = \"0.1.0\"\ndocumentation = \"https://docs.example.com/demo\"\nhomepage = \"https://example.com\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ])).unwrap();
+
+    let result = eg::Eg::rust_crate_file(&crate_file)
+        .search()
+        .await
+        .expect("Should search the archive");
+
+    assert_eq!(result.documentation.as_deref(), Some("https://docs.example.com/demo"));
+    assert_eq!(result.homepage.as_deref(), Some("https://example.com"));
+}
+
+/// Test that the crate's own normal dependencies are reported with the result
+#[tokio::test(flavor = "current_thread")]
+async fn test_manifest_dependencies() {
+    let dir = fixture_dir("manifest-dependencies", &[]);
+    let crate_file = dir.join("demo-deps-0.1.0.crate");
+    fs::write(&crate_file, crate_archive("demo-deps", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-deps\"\nversion = \"0.1.0\"\n\n[dependencies]\nserde = \"1.0\"\ntokio = { version = \"1.2\", features = [\"rt\"] }\n\n[dev-dependencies]\nproptest = \"1\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ])).unwrap();
+
+    let result = eg::Eg::rust_crate_file(&crate_file)
+        .search()
+        .await
+        .expect("Should search the archive");
+
+    let mut dependencies = result.dependencies.clone();
+    dependencies.sort();
+    assert_eq!(dependencies, vec![
+        ("serde".to_string(), "^1.0".to_string()),
+        ("tokio".to_string(), "^1.2".to_string()),
+    ], "Dev-dependencies are not listed");
+}
+
+/// Test pulling the fenced Rust blocks out of a packaged README
+#[tokio::test(flavor = "current_thread")]
+async fn test_readme_examples() {
+    let readme = "# Demo\n\n```sh\ncargo add demo-readme\n```\n\n```rust\nlet client = demo::Client::new();\nclient.connect();\n```\n\n```\nplain output\n```\n\n```rust,no_run\ndemo::serve();\n```\n";
+    let dir = fixture_dir("readme-examples", &[]);
+    let crate_file = dir.join("demo-readme-0.1.0.crate");
+    fs::write(&crate_file, crate_archive("demo-readme", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-readme\"\nversion = \"0.1.0\"\n"),
+        ("README.md", readme),
+        ("src/lib.rs", "pub struct Client;"),
+    ])).unwrap();
+
+    let examples = eg::Eg::rust_crate_file(&crate_file)
+        .readme_examples()
+        .await
+        .expect("Should read the README");
+
+    assert_eq!(examples.len(), 2, "Only rust-tagged blocks are examples: {:?}", examples);
+    assert_eq!(examples[0].file_path, PathBuf::from("README.md"));
+    assert_eq!(examples[0].content, "let client = demo::Client::new();\nclient.connect();");
+    assert_eq!(examples[0].start_line, Some(8));
+    assert_eq!(readme.lines().nth(7), Some("let client = demo::Client::new();"));
+    assert_eq!(examples[1].content, "demo::serve();");
+
+    let filtered = eg::Eg::rust_crate_file(&crate_file)
+        .pattern(r"serve")
+        .expect("Should compile regex")
+        .readme_examples()
+        .await
+        .expect("Should read the README");
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].content, "demo::serve();");
+}
+
+/// Test that a manifest excluding examples/ explains why the package has none
+#[tokio::test(flavor = "current_thread")]
+async fn test_examples_excluded_from_package() {
+    let dir = fixture_dir("examples-excluded", &[]);
+    let excluded = dir.join("demo-excluded-0.1.0.crate");
+    fs::write(&excluded, crate_archive("demo-excluded", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-excluded\"\nversion = \"0.1.0\"\nexclude = [\"examples/\", \"benches\"]\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ])).unwrap();
+    let plain = dir.join("demo-plain-0.1.0.crate");
+    fs::write(&plain, crate_archive("demo-plain", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-plain\"\nversion = \"0.1.0\"\nexclude = [\"benches\"]\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ])).unwrap();
+
+    let result = eg::Eg::rust_crate_file(&excluded)
+        .search()
+        .await
+        .expect("Should search the archive");
+    assert!(result.examples_excluded_from_package);
+
+    let result = eg::Eg::rust_crate_file(&plain)
+        .search()
+        .await
+        .expect("Should search the archive");
+    assert!(!result.examples_excluded_from_package, "The crate just has no examples");
+}
+
+/// Test that an example-less crate hosted outside GitHub says so instead of coming back empty
+#[tokio::test(flavor = "current_thread")]
+async fn test_unsupported_repo_host() {
+    let dir = fixture_dir("unsupported-repo-host", &[]);
+    let bitbucket = dir.join("demo-bitbucket-0.1.0.crate");
+    fs::write(&bitbucket, crate_archive("demo-bitbucket", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-bitbucket\"\nversion = \"0.1.0\"\nrepository = \"https://bitbucket.org/demo/demo-bitbucket\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ])).unwrap();
+    let github = dir.join("demo-github-0.1.0.crate");
+    fs::write(&github, crate_archive("demo-github", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-github\"\nversion = \"0.1.0\"\nrepository = \"https://github.com/demo/demo-github\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ])).unwrap();
+
+    let result = eg::Eg::rust_crate_file(&bitbucket)
+        .search()
+        .await
+        .expect("An unsupported host isn't an error");
+    assert_eq!(result.unsupported_repo_host.as_deref(), Some("bitbucket.org"));
+
+    let result = eg::Eg::rust_crate_file(&github)
+        .search()
+        .await
+        .expect("Should search the archive");
+    assert_eq!(result.unsupported_repo_host, None);
+}
+
+/// Test that a search cancelled before resolving never reaches the registry
+#[tokio::test(flavor = "current_thread")]
+async fn test_cancelled_during_resolution() {
+    let token = eg::rust::CancellationToken::new();
+    token.cancel();
+
+    let err = eg::Eg::rust_crate("serde")
+        .cancel_token(token)
+        .search()
+        .await
+        .expect_err("A cancelled search should fail");
+    assert!(matches!(err, eg::EgError::Cancelled), "Unexpected error: {:?}", err);
+}
+
+/// Test that cancelling a search midway through a download returns promptly and extracts nothing
+#[tokio::test(flavor = "current_thread")]
+async fn test_cancelled_during_download() {
+    use std::io::{BufRead, BufReader, Write};
+
+    // A server that sends the start of the archive, signals, then stalls before the rest
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+            line.clear();
+        }
+
+        let archive = crate_archive("eg-cancelled-download", "0.1.0", &[("src/lib.rs", "pub fn cancelled() {}")]);
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", archive.len());
+        (&stream).write_all(head.as_bytes()).unwrap();
+        (&stream).write_all(&archive[..archive.len() / 2]).unwrap();
+        started_tx.send(()).unwrap();
+        std::thread::sleep(std::time::Duration::from_secs(10));
+    });
+
+    let cache = tempfile::TempDir::new().unwrap();
+    let token = eg::rust::CancellationToken::new();
+    let search = tokio::spawn(eg::Eg::rust_crate("eg-cancelled-download")
+        .resolved_version("0.1.0")
+        .expect("Should parse version")
+        .download_base_url(&base_url)
+        .cache_dirs(cache.path(), &cache.path().join("extractions"))
+        .cancel_token(token.clone())
+        .search());
+
+    // Cancel only once the download is underway
+    started_rx.await.expect("The download should start");
+    let started = std::time::Instant::now();
+    token.cancel();
+
+    let err = search.await.unwrap().expect_err("A cancelled search should fail");
+    assert!(matches!(err, eg::EgError::Cancelled), "Unexpected error: {:?}", err);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5), "Should not wait for the download");
+    assert!(
+        !cache.path().join("extractions").join("eg-cancelled-download-0.1.0").exists(),
+        "A cancelled download leaves no extraction behind"
+    );
+}
+
+/// Test that cancelling a search while it reads a local archive stops before extracting it
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cancelled_during_extraction() {
+    use std::io::Write;
+
+    // A FIFO stands in for a slow archive: reading it blocks until the test writes
+    let dir = fixture_dir("cancelled-extraction", &[]);
+    let crate_file = dir.join("demo-cancelled-0.1.0.crate");
+    let _ = fs::remove_file(&crate_file);
+    let status = std::process::Command::new("mkfifo").arg(&crate_file).status().expect("Should run mkfifo");
+    assert!(status.success());
+
+    let cache = tempfile::TempDir::new().unwrap();
+    let token = eg::rust::CancellationToken::new();
+    let search = tokio::spawn(eg::Eg::rust_crate_file(&crate_file)
+        .pattern(r"cancelled")
+        .expect("Should compile regex")
+        .cache_dirs(cache.path(), &cache.path().join("extractions"))
+        .cancel_token(token.clone())
+        .search());
+
+    // Opening the FIFO for writing waits until the search has opened it for reading
+    let writer = {
+        let crate_file = crate_file.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut writer = fs::OpenOptions::new().write(true).open(&crate_file).unwrap();
+            let archive = crate_archive("demo-cancelled", "0.1.0", &[("src/lib.rs", "pub fn cancelled() {}")]);
+            writer.write_all(&archive[..archive.len() / 2]).unwrap();
+            writer
+        }).await.unwrap()
+    };
+    token.cancel();
+
+    let err = search.await.unwrap().expect_err("A cancelled search should fail");
+    assert!(matches!(err, eg::EgError::Cancelled), "Unexpected error: {:?}", err);
+    let content_dir = cache.path().join("extractions").join("content");
+    assert!(
+        fs::read_dir(&content_dir).map_or(true, |mut entries| entries.next().is_none()),
+        "Nothing should be extracted from a cancelled read"
+    );
+    drop(writer);
+}
+
+/// Test that a facade crate with no matches falls back to searching its dependencies
+#[tokio::test(flavor = "current_thread")]
+async fn test_follow_deps() {
+    // The facade re-exports its derive from a path dependency, which is where the proc macro lives
+    let dir = fixture_dir("follow-deps", &[
+        ("facade/Cargo.toml", "[package]\nname = \"facade\"\nversion = \"1.0.0\"\nedition = \"2021\"\n\n[dependencies]\nfacade-impl = { path = \"../facade-impl\", version = \"=1.0.0\" }\n"),
+        ("facade/src/lib.rs", "pub use facade_impl::Facade;\n"),
+        ("facade-impl/Cargo.toml", "[package]\nname = \"facade-impl\"\nversion = \"1.0.0\"\nedition = \"2021\"\n\n[lib]\nproc-macro = true\n"),
+        ("facade-impl/src/lib.rs", "#[proc_macro_derive(Facade)]\npub fn derive(input: TokenStream) -> TokenStream { input }\n"),
+    ]);
+
+    let result = eg::rust::RustCrateSearch::from_dir(&dir.join("facade"))
+        .pattern(r"proc_macro_derive")
+        .expect("Should compile regex")
+        .follow_deps(1)
+        .search()
+        .await
+        .expect("Should search the facade");
+
+    assert!(result.example_matches.is_empty() && result.other_matches.is_empty(), "The facade itself has no matches");
+
+    let dependency = result.dependency_matches.iter()
+        .find(|d| d.crate_name == "facade-impl")
+        .expect("Should find matches in facade-impl");
+    assert_eq!(dependency.version, "1.0.0");
+    assert!(dependency.checkout_path.ends_with("facade-impl"), "Path dependencies are searched in place");
+    assert_eq!(dependency.other_matches.len(), 1, "Should attribute the match to facade-impl");
+    assert_eq!(dependency.other_matches[0].line_content, "#[proc_macro_derive(Facade)]");
+}
+
+/// Test recording every file the `.crate` archive contained, not just the searched ones
+#[tokio::test(flavor = "current_thread")]
+async fn test_record_file_list() {
+    let dir = fixture_dir("record-file-list", &[]);
+    let crate_file = dir.join("demo-audit-0.2.0.crate");
+    fs::write(&crate_file, crate_archive("demo-audit", "0.2.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-audit\"\nversion = \"0.2.0\"\n"),
+        ("src/lib.rs", "pub fn audit() {}\n"),
+        ("assets/logo.svg", "<svg/>"),
+    ])).unwrap();
+    let search = || eg::Eg::rust_crate_file(&crate_file)
+        .pattern(r"audit")
+        .expect("Should compile regex");
+
+    let result = search().record_file_list(true).search().await.expect("Should search the archive");
+    let sizes: std::collections::HashMap<&Path, u64> = result.file_list.iter()
+        .map(|(path, size)| (path.as_path(), *size))
+        .collect();
+    assert!(sizes.get(Path::new("Cargo.toml")).is_some_and(|&size| size > 0), "{:?}", result.file_list);
+    assert!(sizes.get(Path::new("src/lib.rs")).is_some_and(|&size| size > 0), "{:?}", result.file_list);
+    assert!(sizes.contains_key(Path::new("assets/logo.svg")), "Files that aren't searched are listed too");
+
+    let plain = search().search().await.expect("Should search the archive");
+    assert!(plain.file_list.is_empty(), "Off by default");
+}
+
+/// Test getting just the src/ directory of an extracted crate
+#[tokio::test(flavor = "current_thread")]
+async fn test_docs_source() {
+    let dir = fixture_dir("docs-source", &[]);
+    let with_src = dir.join("demo-src-0.1.0.crate");
+    fs::write(&with_src, crate_archive("demo-src", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-src\"\nversion = \"0.1.0\"\n"),
+        ("src/lib.rs", "pub mod client;"),
+        ("src/client.rs", "pub struct Client;"),
+        ("examples/basic.rs", "fn main() {}"),
+    ])).unwrap();
+    let without_src = dir.join("demo-nosrc-0.1.0.crate");
+    fs::write(&without_src, crate_archive("demo-nosrc", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-nosrc\"\nversion = \"0.1.0\"\n\n[lib]\npath = \"lib.rs\"\n"),
+        ("lib.rs", "pub struct Client;"),
+    ])).unwrap();
+
+    let src = eg::Eg::rust_crate_file(&with_src)
+        .docs_source()
+        .await
+        .expect("Should extract the archive");
+    assert!(src.ends_with("src"));
+    assert!(src.join("lib.rs").exists() && src.join("client.rs").exists());
+
+    let err = eg::Eg::rust_crate_file(&without_src)
+        .docs_source()
+        .await
+        .expect_err("A crate without src/ has nothing to return");
+    assert!(matches!(err, eg::EgError::ExtractionError(_)), "Unexpected error: {:?}", err);
+}
+
+/// Test reading the repository URL from a local archive's manifest
+#[tokio::test(flavor = "current_thread")]
+async fn test_repository_url() {
+    let dir = fixture_dir("repository-url", &[]);
+    let with_repo = dir.join("demo-repo-0.1.0.crate");
+    fs::write(&with_repo, crate_archive("demo-repo", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-repo\"\nversion = \"0.1.0\"\nrepository = \"https://github.com/demo/demo-repo\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ])).unwrap();
+    let without_repo = dir.join("demo-norepo-0.1.0.crate");
+    fs::write(&without_repo, crate_archive("demo-norepo", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"demo-norepo\"\nversion = \"0.1.0\"\n"),
+        ("src/lib.rs", "pub fn demo() {}"),
+    ])).unwrap();
+
+    let url = eg::Eg::rust_crate_file(&with_repo).repository_url().await.expect("Should read the manifest");
+    assert_eq!(url.as_deref(), Some("https://github.com/demo/demo-repo"));
+
+    let url = eg::Eg::rust_crate_file(&without_repo).repository_url().await.expect("A missing URL isn't an error");
+    assert_eq!(url, None);
+}
+
+/// Test that sources cargo already unpacked are used in place, without any archive
+#[tokio::test(flavor = "current_thread")]
+async fn test_find_extracted_src() {
+    let cargo_home = fixture_dir("cargo-registry-src", &[
+        ("registry/src/github.com-1ecc6299db9ec823/demo-src-0.2.0/Cargo.toml", "[package]\nname = \"demo-src\"\nversion = \"0.2.0\"\n"),
+        ("registry/src/github.com-1ecc6299db9ec823/demo-src-0.2.0/src/lib.rs", "pub fn unpacked() {}"),
+        // Cargo was interrupted while unpacking this one
+        ("registry/src/index.crates.io-6f17d22bba15001f/demo-src-0.3.0/src/lib.rs", "pub fn partial() {}"),
+    ]);
+    let extraction_cache = fixture_dir("cargo-registry-src-cache", &[]);
+    let cache_manager = eg::rust::CacheManager::with_dirs(&cargo_home, &extraction_cache);
+
+    let found = cache_manager.find_extracted_src("demo-src", "0.2.0").expect("Should scan registry/src");
+    let expected = cargo_home.join("registry/src/github.com-1ecc6299db9ec823/demo-src-0.2.0");
+    assert_eq!(found.as_deref(), Some(expected.as_path()));
+    assert_eq!(cache_manager.find_extracted_src("demo-src", "0.3.0").unwrap(), None, "Unfinished unpacking is skipped");
+    assert_eq!(cache_manager.find_extracted_src("demo-src", "0.4.0").unwrap(), None);
+
+    // No download or extraction happens: the extractor points at an unreachable server
+    let extractor = CrateExtractor::new().base_url("http://127.0.0.1:9");
+    let checkout = cache_manager
+        .get_or_extract_crate("demo-src", "0.2.0", &extractor)
+        .await
+        .expect("Should use cargo's unpacked sources");
+    assert_eq!(checkout, expected);
+    assert!(fs::read_dir(&extraction_cache).unwrap().next().is_none(), "Nothing should be extracted");
+}
+
+/// Test that an identical search is answered from the result cache instead of rerunning
+#[tokio::test(flavor = "current_thread")]
+async fn test_result_cache() {
+    let dir = fixture_dir("result-cache", &[]);
+    let crate_file = dir.join("demo-cached-0.1.0.crate");
+    fs::write(&crate_file, crate_archive("demo-cached", "0.1.0", &[
+        ("src/lib.rs", "pub fn cached() {}"),
+    ])).unwrap();
+
+    let search = || eg::Eg::rust_crate_file(&crate_file)
+        .pattern(r"cached")
+        .expect("Should compile regex")
+        .result_cache(true);
+    let cache = eg::rust::ResultCache::global();
+    let misses = cache.misses();
+
+    let first = search().search().await.expect("Should search the archive");
+    assert_eq!(cache.misses(), misses + 1, "First search should run the pipeline");
+
+    let hits = cache.hits();
+    let second = search().search().await.expect("Should search the archive");
+    assert_eq!(cache.hits(), hits + 1, "Second search should come from the cache");
+    assert_eq!(cache.misses(), misses + 1, "Second search should not rerun extraction");
+    assert_eq!(second.other_matches.len(), first.other_matches.len());
+
+    // A different option is a different search
+    search().context_lines(0).search().await.expect("Should search the archive");
+    assert_eq!(cache.misses(), misses + 2);
+}
+
+/// Test that searches of the same version from different mirrors and caches don't share a result
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_result_cache_sources() {
+    let mirror = |body: &str| mock_http_server_bytes("200 OK", crate_archive("demo-mirrored", "0.1.0", &[
+        ("src/lib.rs", body),
+    ]));
+    let (first_url, _first_server) = mirror("pub fn mirrored() { from_first(); }\n");
+    let (second_url, _second_server) = mirror("pub fn mirrored() { from_second(); }\n");
+    let first_cache = tempfile::TempDir::new().unwrap();
+    let second_cache = tempfile::TempDir::new().unwrap();
+    let search = |base_url: &str, cache: &Path| eg::Eg::rust_crate("demo-mirrored")
+        .resolved_version("0.1.0")
+        .expect("Should accept the version")
+        .download_base_url(base_url)
+        .cache_dirs(cache, &cache.join("extractions"))
+        .pattern(r"from_\w+")
+        .expect("Should compile regex")
+        .result_cache(true);
+
+    let first = search(&first_url, first_cache.path()).search().await.expect("Should search the first mirror");
+    let second = search(&second_url, second_cache.path()).search().await.expect("Should search the second mirror");
+
+    assert_eq!(first.other_matches[0].line_content, "pub fn mirrored() { from_first(); }");
+    assert_eq!(second.other_matches[0].line_content, "pub fn mirrored() { from_second(); }", "Not the first mirror's cached result");
+}
+
+/// Test that an extraction failure keeps the underlying I/O error as its source
+#[tokio::test(flavor = "current_thread")]
+async fn test_extraction_error_source() {
+    let dir = fixture_dir("extraction-error-source", &[]);
+    let err = CrateExtractor::new()
+        .extract_bytes_to_content(b"not a gzip archive", &dir)
+        .await
+        .expect_err("Garbage bytes should fail to extract");
+    assert!(matches!(err, eg::EgError::ArchiveError { .. }), "Unexpected error: {:?}", err);
+
+    let mut source = std::error::Error::source(&err);
+    let mut found_io = false;
+    while let Some(cause) = source {
+        found_io |= cause.is::<std::io::Error>();
+        source = cause.source();
+    }
+    assert!(found_io, "Should chain to the underlying io::Error");
+}
+
+/// Test that downloads identify themselves with the configured user-agent
+#[tokio::test(flavor = "current_thread")]
+async fn test_user_agent() {
+    let (base_url, server) = mock_http_server("200 OK", "ok");
+
+    let bytes = CrateExtractor::new()
+        .user_agent("my-tool/1.0 (ops@example.com)")
+        .download_url(&format!("{}/demo-0.1.0.crate", base_url))
+        .await
+        .expect("Should download from the mock server");
+    assert_eq!(bytes, b"ok");
+
+    let headers = server.join().unwrap();
+    assert!(
+        headers.contains(&"user-agent: my-tool/1.0 (ops@example.com)".to_string()),
+        "Missing user-agent, got: {:?}",
+        headers
+    );
+}
+
+/// Test that a 404 for a crate archive is reported as a missing version, not a generic failure
+#[tokio::test(flavor = "current_thread")]
+async fn test_download_version_not_found() {
+    let (base_url, server) = mock_http_server("404 Not Found", "");
+
+    let err = CrateExtractor::new()
+        .download_crate_from(&base_url, "demo", "9.9.9")
+        .await
+        .expect_err("A 404 should fail the download");
+    server.join().unwrap();
+
+    match err {
+        eg::EgError::VersionNotFound { crate_name, version } => {
+            assert_eq!(crate_name, "demo");
+            assert_eq!(version, "9.9.9");
+        }
+        other => panic!("Expected VersionNotFound, got: {:?}", other),
+    }
+}
+
+/// Test that a pre-resolved version skips resolution: a crate crates.io has never heard of still resolves
+#[tokio::test(flavor = "current_thread")]
+async fn test_resolved_version() {
+    let version = eg::Eg::rust_crate("eg-test-unpublished-crate")
+        .resolved_version("1.2.3")
+        .expect("Should accept a semver version")
+        .resolve_only()
+        .await
+        .expect("Should not consult the registry");
+    assert_eq!(version, "1.2.3");
+
+    assert!(eg::Eg::rust_crate("regex").resolved_version("^1").is_err(), "A constraint isn't an exact version");
+}
+
+/// Test that the download size comes from a HEAD request's Content-Length
+#[tokio::test(flavor = "current_thread")]
+async fn test_download_size() {
+    let (base_url, server) = mock_http_server("200 OK", "0123456789");
+
+    let size = CrateExtractor::new()
+        .download_size_from(&base_url, "demo", "1.0.0")
+        .await
+        .expect("Should read the size");
+    let headers = server.join().unwrap();
+
+    assert_eq!(size, Some(10));
+    assert_eq!(headers[0], "head /demo/demo-1.0.0.crate http/1.1");
+}
+
+/// Test that a search downloads from a configured mirror instead of crates.io
+#[tokio::test(flavor = "current_thread")]
+async fn test_download_base_url() {
+    // Fresh caches, so the search has to download
+    let cache = tempfile::TempDir::new().unwrap();
+    let crate_name = "eg-mirror-test";
+    let archive = crate_archive(crate_name, "0.1.0", &[
+        ("examples/mirror.rs", "fn main() { mirror_marker(); }\n"),
+    ]);
+    let (base_url, server) = mock_http_server_bytes("200 OK", archive);
+
+    let result = eg::Eg::rust_crate(crate_name)
+        .resolved_version("0.1.0")
+        .expect("Should accept the version")
+        .download_base_url(&base_url)
+        .cache_dirs(cache.path(), &cache.path().join("extractions"))
+        .pattern(r"mirror_marker")
+        .expect("Should compile regex")
+        .search()
+        .await
+        .expect("Should download from the mirror");
+    let headers = server.join().unwrap();
+
+    assert_eq!(headers[0], format!("get /{0}/{0}-0.1.0.crate http/1.1", crate_name));
+    assert_eq!(result.example_matches.len(), 1);
+    assert!(result.checkout_path.join("examples/mirror.rs").exists());
+}
+
+/// Test that a download answered with 503 is retried until the archive comes through
+#[tokio::test(flavor = "current_thread")]
+async fn test_download_retry() {
+    let archive = crate_archive("retried", "0.1.0", &[("src/lib.rs", "")]);
+    let (base_url, server) = mock_http_server_sequence(vec![
+        ("503 Service Unavailable", b"try again".to_vec()),
+        ("200 OK", archive.clone()),
+    ]);
+
+    let bytes = CrateExtractor::new()
+        .download_crate_from(&base_url, "retried", "0.1.0")
+        .await
+        .expect("Should retry past the 503");
+    let requests = server.join().unwrap();
+
+    assert_eq!(bytes, archive);
+    assert_eq!(requests.len(), 2);
+}
+
+/// Test diffing the example files of two versions served as fixture archives
+#[tokio::test(flavor = "current_thread")]
+async fn test_diff_examples() {
+    let cache = tempfile::TempDir::new().unwrap();
+    let crate_name = "eg-diff-test";
+    let old = crate_archive(crate_name, "1.1.0", &[
+        ("examples/client.rs", "fn main() {\n    let c = Client::new();\n    c.send(1);\n}\n"),
+        ("examples/legacy.rs", "fn main() {}\n"),
+        ("examples/same.rs", "fn main() { Client::new(); }\n"),
+    ]);
+    let new = crate_archive(crate_name, "1.2.0", &[
+        ("examples/client.rs", "fn main() {\n    let c = Client::builder().build();\n    c.send(1);\n}\n"),
+        ("examples/server.rs", "fn main() {}\n"),
+        ("examples/same.rs", "fn main() { Client::new(); }\n"),
+    ]);
+    let (base_url, server) = mock_http_server_sequence(vec![("200 OK", old), ("200 OK", new)]);
+
+    let diff = eg::Eg::rust_crate(crate_name)
+        .download_base_url(&base_url)
+        .cache_dirs(cache.path(), &cache.path().join("extractions"))
+        .pattern(r"Client")
+        .expect("Should compile regex")
+        .diff_examples("1.1.0", "1.2.0")
+        .await
+        .expect("Should diff the two versions");
+    server.join().unwrap();
+
+    assert_eq!((diff.from_version.as_str(), diff.to_version.as_str()), ("1.1.0", "1.2.0"));
+    assert_eq!(diff.added, vec![PathBuf::from("examples/server.rs")]);
+    assert_eq!(diff.removed, vec![PathBuf::from("examples/legacy.rs")]);
+    assert_eq!(diff.modified.len(), 1, "Unchanged examples aren't reported");
+
+    let client = &diff.modified[0];
+    assert_eq!(client.file_path, PathBuf::from("examples/client.rs"));
+    assert_eq!(client.removed_lines, vec!["    let c = Client::new();"]);
+    assert_eq!(client.added_lines, vec!["    let c = Client::builder().build();"]);
+}
+
+/// Test that concurrent searches never have more downloads in flight than the process-wide limit
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_max_concurrent_downloads() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const SEARCHES: usize = 6;
+    const LIMIT: usize = 2;
+    eg::Eg::set_max_concurrent_downloads(LIMIT);
+
+    // Serves each archive slowly, tracking how many requests overlap
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Should bind mock server");
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let (in_flight, peak) = (in_flight.clone(), peak.clone());
+        std::thread::spawn(move || {
+            let connections: Vec<_> = (0..SEARCHES).map(|_| {
+                let (stream, _) = listener.accept().expect("Should accept a request");
+                let (in_flight, peak) = (in_flight.clone(), peak.clone());
+                std::thread::spawn(move || {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+
+                    let mut reader = BufReader::new(&stream);
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).expect("Should read request");
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).expect("Should read request");
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                    }
+
+                    // GET /{name}/{name}-0.1.0.crate HTTP/1.1
+                    let crate_name = request_line.split('/').nth(1).expect("Should request a crate path").to_string();
+                    let body = crate_archive(&crate_name, "0.1.0", &[("examples/pool.rs", "fn main() { pool_marker(); }\n")]);
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+
+                    // Leave before responding, so the client can't start its next download first
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                    (&stream).write_all(head.as_bytes()).expect("Should write response");
+                    (&stream).write_all(&body).expect("Should write response body");
+                })
+            }).collect();
+            connections.into_iter().for_each(|connection| connection.join().unwrap());
+        })
+    };
+
+    // Fresh caches, so every search has to download
+    let cache = tempfile::TempDir::new().unwrap();
+    let searches: Vec<_> = (0..SEARCHES).map(|i| {
+        let search = eg::Eg::rust_crate(&format!("eg-pool-test-{}", i))
+            .resolved_version("0.1.0")
+            .expect("Should accept the version")
+            .download_base_url(&base_url)
+            .cache_dirs(cache.path(), &cache.path().join("extractions"))
+            .pattern(r"pool_marker")
+            .expect("Should compile regex");
+        tokio::spawn(search.search())
+    }).collect();
+
+    for search in searches {
+        let result = search.await.unwrap().expect("Should download from the mock server");
+        assert_eq!(result.example_matches.len(), 1);
+    }
+    server.join().unwrap();
+
+    let peak = peak.load(Ordering::SeqCst);
+    assert!(peak <= LIMIT, "{} downloads overlapped, limit is {}", peak, LIMIT);
+}
+
+/// Test fetching a crate archive into a chosen directory without searching
+#[tokio::test(flavor = "current_thread")]
+async fn test_download_crate() {
+    let archive = crate_archive("demo-download", "0.0.1", &[
+        ("Cargo.toml", "[package]\nname = \"demo-download\"\nversion = \"0.0.1\"\n"),
+        ("src/lib.rs", "pub fn demo() {}\n"),
+    ]);
+    let (base_url, server) = mock_http_server_bytes("200 OK", archive.clone());
+    let cache = tempfile::TempDir::new().unwrap();
+    let dest = tempfile::TempDir::new().unwrap();
+
+    let downloaded = eg::Eg::rust_crate("demo-download")
+        .resolved_version("0.0.1")
+        .expect("Should accept the version")
+        .download_base_url(&base_url)
+        .cache_dirs(cache.path(), &cache.path().join("extractions"))
+        .download_crate(dest.path(), true)
+        .await
+        .expect("Should download the crate");
+    let headers = server.join().unwrap();
+
+    assert_eq!(headers[0], "get /demo-download/demo-download-0.0.1.crate http/1.1");
+    assert_eq!(downloaded.version, "0.0.1");
+    assert_eq!(downloaded.crate_path, dest.path().join("demo-download-0.0.1.crate"));
+    assert_eq!(fs::read(&downloaded.crate_path).unwrap(), archive, "Should write the .crate file");
+
+    let extracted_path = downloaded.extracted_path.expect("Should extract when asked");
+    assert!(extracted_path.join("Cargo.toml").exists(), "Should have Cargo.toml");
+}
+
+/// Test that a conceptual name resolves to the crate it stands for, and the substitution is reported
+#[tokio::test(flavor = "current_thread")]
+async fn test_resolve_aliases() {
+    let (base_url, server) = mock_http_server_sequence(vec![
+        ("200 OK", crate_archive("serde_json", "1.0.0", &[("src/de.rs", "pub fn from_str() {}\n")])),
+        ("200 OK", crate_archive("json", "1.0.0", &[("src/lib.rs", "pub fn parse() {}\n")])),
+    ]);
+    let cache = tempfile::TempDir::new().unwrap();
+    let search = |name: &str| eg::Eg::rust_crate(name)
+        .resolved_version("1.0.0")
+        .expect("Should accept the version")
+        .download_base_url(&base_url)
+        .cache_dirs(cache.path(), &cache.path().join("extractions"))
+        .pattern(r"fn from_str")
+        .expect("Should compile regex");
+
+    let result = search("json").resolve_aliases(true).search().await.expect("Should search serde_json");
+    assert_eq!(result.crate_name, "serde_json");
+    assert_eq!(result.alias.as_deref(), Some("json"));
+    assert_eq!(result.other_matches.len(), 1);
+
+    // User aliases work the same way, and searches without the option are left alone
+    eg::Eg::add_crate_alias("eg_test_json", "serde_json");
+    let aliased = search("EG-test-JSON").resolve_aliases(true).search().await.expect("Should search the user alias");
+    assert_eq!(aliased.crate_name, "serde_json");
+    let plain = search("json").search().await.expect("Should search the json crate");
+    assert_eq!(plain.crate_name, "json");
+    assert_eq!(plain.alias, None);
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests[0][0], "get /serde_json/serde_json-1.0.0.crate http/1.1");
+    assert_eq!(requests[1][0], "get /json/json-1.0.0.crate http/1.1", "The user alias reuses serde_json's extraction");
+}
+
+/// Test pre-extracting several crates at once, with one failure not spoiling the rest
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_warm_cache() {
+    let archive = |name: &str, version: &str| crate_archive(name, version, &[
+        ("Cargo.toml", &format!("[package]\nname = \"{}\"\nversion = \"{}\"\n", name, version)),
+    ]);
+    let (base_url, requested) = mock_http_server_routes(vec![
+        ("/api/v1/crates/demo-itoa".to_string(), crate_response("demo-itoa", &["1.0.9", "1.0.10", "1.0.11"])),
+        ("/api/v1/crates/demo-ryu".to_string(), crate_response("demo-ryu", &["0.9.0", "1.0.2", "1.0.3"])),
+        ("/dl/demo-itoa/demo-itoa-1.0.10.crate".to_string(), archive("demo-itoa", "1.0.10")),
+        ("/dl/demo-ryu/demo-ryu-1.0.3.crate".to_string(), archive("demo-ryu", "1.0.3")),
+    ]);
+    let cache = tempfile::TempDir::new().unwrap();
+    let crates = [
+        ("demo-itoa".to_string(), "=1.0.10".to_string()),
+        ("demo-ryu".to_string(), "1".to_string()),
+        ("eg-no-such-crate-for-warming".to_string(), "1".to_string()),
+    ];
+
+    let warmed = eg::Eg::rust_crate("")
+        .api_base_url(&format!("{}/api/v1", base_url))
+        .download_base_url(&format!("{}/dl", base_url))
+        .cache_dirs(cache.path(), &cache.path().join("extractions"))
+        .warm_cache(&crates)
+        .await;
+
+    assert_eq!(warmed.len(), 3);
+    let names: Vec<&str> = warmed.iter().map(|w| w.crate_name.as_str()).collect();
+    assert_eq!(names, vec!["demo-itoa", "demo-ryu", "eg-no-such-crate-for-warming"], "Results keep the input order");
+
+    let (version, path) = warmed[0].result.as_ref().expect("demo-itoa should be extracted");
+    assert_eq!(version, "1.0.10");
+    assert!(path.join("Cargo.toml").exists());
+    let (version, path) = warmed[1].result.as_ref().expect("demo-ryu should be extracted");
+    assert_eq!(version, "1.0.3", "The newest version matching the constraint");
+    assert!(path.join("Cargo.toml").exists());
+    assert!(
+        matches!(warmed[2].result, Err(eg::EgError::CrateNotFound(_))),
+        "A missing crate is reported, not fatal: {:?}", warmed[2].result
+    );
+    assert!(requested.lock().unwrap().iter().all(|path| path.starts_with("/api/v1/") || path.starts_with("/dl/")));
+}
+
+/// Test searching every published version that matches the constraint
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_all_matching_versions() {
+    let mut routes = vec![(
+        "/api/v1/crates/demo-buffer".to_string(),
+        crate_response("demo-buffer", &["1.0.7", "1.0.8", "1.0.9", "1.0.10", "1.0.11"]),
+    )];
+    for version in ["1.0.8", "1.0.9", "1.0.10"] {
+        routes.push((
+            format!("/dl/demo-buffer/demo-buffer-{}.crate", version),
+            crate_archive("demo-buffer", version, &[
+                ("Cargo.toml", &format!("[package]\nname = \"demo-buffer\"\nversion = \"{}\"\n", version)),
+                ("src/lib.rs", "pub fn demo() { let _ = demo_buffer::Buffer::new(); }"),
+            ]),
+        ));
+    }
+    let (base_url, _requested) = mock_http_server_routes(routes);
+    let cache = tempfile::TempDir::new().unwrap();
+    let search = || eg::Eg::rust_crate("demo-buffer")
+        .version(">=1.0.8, <=1.0.10")
+        .api_base_url(&format!("{}/api/v1", base_url))
+        .download_base_url(&format!("{}/dl", base_url))
+        .cache_dirs(cache.path(), &cache.path().join("extractions"))
+        .pattern(r"demo_buffer::Buffer")
+        .expect("Should compile regex");
+
+    let results = search()
+        .all_matching_versions(true)
+        .search_versions()
+        .await
+        .expect("Should search each version");
+
+    let versions: Vec<&str> = results.iter().map(|r| r.version.as_str()).collect();
+    assert_eq!(versions, vec!["1.0.8", "1.0.9", "1.0.10"], "One result per version, oldest first");
+    assert!(results.iter().all(|r| r.checkout_path.join("Cargo.toml").exists()));
+    assert!(results.iter().all(|r| r.other_matches.len() == 1), "Each version is searched");
+
+    // Without the flag it's a single search of the newest match
+    let single = search().search_versions().await.expect("Should search the newest version");
+    assert_eq!(single.len(), 1);
+    assert_eq!(single[0].version, "1.0.10");
+}
+
+/// Test looking up a registry crate's repository without downloading it
+#[tokio::test(flavor = "current_thread")]
+async fn test_registry_repository_url() {
+    let mut with_repo: serde_json::Value = serde_json::from_slice(&crate_response("demo-repo", &["0.1.0"])).unwrap();
+    with_repo["crate"]["repository"] = "https://github.com/demo/demo-repo".into();
+    let mut blank_repo: serde_json::Value = serde_json::from_slice(&crate_response("demo-blank", &["0.1.0"])).unwrap();
+    blank_repo["crate"]["repository"] = " ".into();
+    let (base_url, requested) = mock_http_server_routes(vec![
+        ("/api/v1/crates/demo-repo".to_string(), with_repo.to_string().into_bytes()),
+        ("/api/v1/crates/demo-norepo".to_string(), crate_response("demo-norepo", &["0.1.0"])),
+        ("/api/v1/crates/demo-blank".to_string(), blank_repo.to_string().into_bytes()),
+    ]);
+    let api_base_url = format!("{}/api/v1", base_url);
+
+    let url = eg::Eg::rust_crate("demo-repo").api_base_url(&api_base_url).repository_url().await.expect("Should query the API");
+    assert_eq!(url.as_deref(), Some("https://github.com/demo/demo-repo"));
+    let url = eg::Eg::rust_crate("demo-norepo").api_base_url(&api_base_url).repository_url().await.expect("A missing URL isn't an error");
+    assert_eq!(url, None);
+    let url = eg::Eg::rust_crate("demo-blank").api_base_url(&api_base_url).repository_url().await.expect("A blank URL isn't an error");
+    assert_eq!(url, None);
+
+    assert!(requested.lock().unwrap().iter().all(|path| path.starts_with("/api/v1/crates/")), "Nothing is downloaded");
+}
+
+/// Test searching a facade's companion crates alongside it
+#[tokio::test(flavor = "current_thread")]
+async fn test_include_companions() {
+    // The derive macro only appears in demo-facade-impl, an `-impl` companion of demo-facade
+    let dir = fixture_dir("include-companions", &[
+        ("demo-facade/Cargo.toml", "[package]\nname = \"demo-facade\"\nversion = \"1.0.0\"\nedition = \"2021\"\n\n[dependencies]\ndemo-facade-impl = { path = \"../demo-facade-impl\", version = \"=1.0.0\" }\ndemo-other = { path = \"../demo-other\", version = \"=1.0.0\" }\n"),
+        ("demo-facade/src/lib.rs", "pub use demo_facade_impl::Facade;\n"),
+        ("demo-facade-impl/Cargo.toml", "[package]\nname = \"demo-facade-impl\"\nversion = \"1.0.0\"\nedition = \"2021\"\n\n[lib]\nproc-macro = true\n"),
+        ("demo-facade-impl/src/lib.rs", "#[proc_macro_derive(Facade)]\npub fn derive(input: TokenStream) -> TokenStream { input }\n"),
+        ("demo-other/Cargo.toml", "[package]\nname = \"demo-other\"\nversion = \"1.0.0\"\nedition = \"2021\"\n"),
+        ("demo-other/src/lib.rs", "#[proc_macro_derive(Other)]\npub fn derive(input: TokenStream) -> TokenStream { input }\n"),
+    ]);
+
+    let result = eg::rust::RustCrateSearch::from_dir(&dir.join("demo-facade"))
+        .pattern(r"proc_macro_derive")
+        .expect("Should compile regex")
+        .include_companions(true)
+        .search()
+        .await
+        .expect("Should search the facade");
+
+    assert!(result.example_matches.is_empty() && result.other_matches.is_empty(), "The facade itself has no matches");
+    assert!(result.dependency_matches.is_empty(), "follow_deps is off");
+
+    assert_eq!(result.companion_matches.len(), 1, "demo-other isn't a companion: {:?}", result.companion_matches);
+    let companion = &result.companion_matches[0];
+    assert_eq!(companion.crate_name, "demo-facade-impl");
+    assert_eq!(companion.version, "1.0.0");
+    assert_eq!(companion.other_matches.len(), 1, "Should attribute the match to demo-facade-impl");
+    assert_eq!(companion.other_matches[0].line_content, "#[proc_macro_derive(Facade)]");
+}
@@ -1,6 +1,10 @@
 //! Integration tests for the eg library
 
+mod common;
+
+use common::fixture_dir;
 use eg::Eg;
+use eg::rust::DependencyKind;
 
 /// Test searching a crate that's in our current project dependencies
 /// Should use the version from cargo cache/src
@@ -157,3 +161,301 @@ async fn test_caching() {
     
     println!("✅ Caching works: both searches used {}", result1.checkout_path.display());
 }
+
+/// Test resolving a version without downloading or extracting the crate
+#[tokio::test(flavor = "current_thread")]
+async fn test_resolve_only() {
+    // Use 'home' since it's in our Cargo.toml and no other test searches it
+    let version = Eg::rust_crate("home")
+        .resolve_only()
+        .await
+        .expect("Should resolve home version");
+
+    assert!(semver::Version::parse(&version).is_ok(), "Should be a valid version, got: {}", version);
+
+    let cache_manager = eg::rust::CacheManager::new().expect("Should create cache manager");
+    let extraction_path = cache_manager.extraction_cache_dir().join(format!("home-{}", version));
+    assert!(!extraction_path.exists(), "Should not extract anything, found: {}", extraction_path.display());
+
+    println!("✅ home resolved to v{} without extraction", version);
+}
+
+/// Test listing the published versions of a crate
+#[tokio::test(flavor = "current_thread")]
+async fn test_available_versions() {
+    let versions = Eg::rust_crate("serde")
+        .available_versions()
+        .await
+        .expect("Should list serde versions");
+
+    assert!(!versions.is_empty(), "Should find some serde versions");
+    assert!(versions.windows(2).all(|w| w[0].version <= w[1].version), "Versions should be sorted");
+
+    // A constraint narrows the list
+    let constrained = Eg::rust_crate("serde")
+        .version("^1.0")
+        .available_versions()
+        .await
+        .expect("Should list constrained serde versions");
+
+    assert!(!constrained.is_empty() && constrained.len() < versions.len());
+    assert!(constrained.iter().all(|v| v.version.major == 1), "Should only list 1.x versions");
+
+    println!("✅ serde has {} versions ({} yanked)", versions.len(), versions.iter().filter(|v| v.yanked).count());
+}
+
+/// Test that "latest" and "*" resolve to the newest release instead of failing to parse
+#[tokio::test(flavor = "current_thread")]
+async fn test_latest_version_tokens() {
+    let versions = Eg::rust_crate("serde")
+        .available_versions()
+        .await
+        .expect("Should list serde versions");
+    let newest = versions.iter()
+        .filter(|v| !v.yanked)
+        .map(|v| v.version.to_string())
+        .next_back()
+        .expect("Should have an unyanked version");
+
+    for token in ["latest", "*"] {
+        let version = Eg::rust_crate("serde")
+            .version(token)
+            .resolve_only()
+            .await
+            .unwrap_or_else(|e| panic!("Should resolve '{}': {}", token, e));
+        assert_eq!(version, newest, "'{}' should pick the newest release", token);
+    }
+
+    println!("✅ latest and * both resolve serde to {}", newest);
+}
+
+/// Test that the latest version defaults to a stable release even when a prerelease is newer
+#[tokio::test(flavor = "current_thread")]
+async fn test_stable_only() {
+    // rand has published prereleases ahead of its stable releases
+    let versions = Eg::rust_crate("rand")
+        .available_versions()
+        .await
+        .expect("Should list rand versions");
+    let newest_stable = versions.iter()
+        .filter(|v| !v.yanked && v.version.pre.is_empty())
+        .map(|v| v.version.clone())
+        .next_back()
+        .expect("Should have a stable release");
+
+    let stable = Eg::rust_crate("rand")
+        .version("latest")
+        .resolve_only()
+        .await
+        .expect("Should resolve the latest stable rand");
+    assert_eq!(stable, newest_stable.to_string(), "Should skip any newer prerelease");
+
+    let any = Eg::rust_crate("rand")
+        .version("latest")
+        .stable_only(false)
+        .resolve_only()
+        .await
+        .expect("Should resolve the latest rand");
+    assert!(semver::Version::parse(&any).unwrap() >= newest_stable, "May pick a prerelease");
+
+    println!("✅ rand resolves to {} (stable) and {} (any)", stable, any);
+}
+
+/// Test getting the extracted source directory without running a search
+#[tokio::test(flavor = "current_thread")]
+async fn test_checkout_only() {
+    // Use 'regex' since it's in our Cargo.toml, so resolution stays local; the first call may extract
+    Eg::rust_crate("regex").checkout_only().await.expect("Should check out regex");
+
+    let started = std::time::Instant::now();
+    let (version, checkout_path) = Eg::rust_crate("regex")
+        .checkout_only()
+        .await
+        .expect("Should check out regex");
+    let elapsed = started.elapsed();
+
+    assert!(checkout_path.join("Cargo.toml").exists(), "Should point at the extracted crate");
+    assert!(elapsed < std::time::Duration::from_secs(5), "A cached checkout should be quick, took {:?}", elapsed);
+
+    println!("✅ regex v{} at {} in {:?}", version, checkout_path.display(), elapsed);
+}
+
+/// Test that a locked search refuses a version that drifts from Cargo.lock
+#[tokio::test(flavor = "current_thread")]
+async fn test_locked_version_drift() {
+    // 'regex' is pinned by our Cargo.lock, so the default resolution agrees with the lockfile
+    let pinned = Eg::rust_crate("regex")
+        .locked(true)
+        .resolve_only()
+        .await
+        .expect("The lockfile pin should satisfy a locked resolution");
+
+    let err = Eg::rust_crate("regex")
+        .version("=1.0.0")
+        .locked(true)
+        .resolve_only()
+        .await
+        .expect_err("A constraint that conflicts with the pin should fail");
+    assert!(err.to_string().contains("version drift"), "Unexpected error: {}", err);
+
+    // Without locked, the same constraint resolves normally
+    let unlocked = Eg::rust_crate("regex")
+        .version("=1.0.0")
+        .resolve_only()
+        .await
+        .expect("Should resolve the explicit version");
+    assert_eq!(unlocked, "1.0.0");
+
+    println!("✅ regex pinned at {}, =1.0.0 rejected as drift when locked", pinned);
+}
+
+/// Test that a dependency replaced through `[patch.crates-io]` is searched in its local sources
+#[tokio::test(flavor = "current_thread")]
+async fn test_patched_dependency() {
+    let project = fixture_dir("patched-project", &[
+        ("Cargo.toml", r#"[package]
+name = "patched-project"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+itoa = "1"
+
+[patch.crates-io]
+itoa = { path = "itoa" }
+"#),
+        ("src/lib.rs", ""),
+        ("itoa/Cargo.toml", r#"[package]
+name = "itoa"
+version = "1.99.0"
+edition = "2021"
+"#),
+        ("itoa/src/lib.rs", "pub fn only_in_the_patched_itoa() {}\n"),
+    ]);
+
+    let result = Eg::rust_crate("itoa")
+        .manifest_path(project.join("Cargo.toml"))
+        .pattern(r"only_in_the_patched_itoa")
+        .expect("Should compile regex")
+        .search()
+        .await
+        .expect("Should search the patched dependency");
+
+    assert_eq!(result.version, "1.99.0");
+    assert_eq!(result.checkout_path, project.join("itoa"));
+    assert_eq!(result.other_matches.len(), 1, "Should find the function only the local source has");
+
+    println!("✅ Patched itoa searched at {}", result.checkout_path.display());
+}
+
+/// Test taking the version from a crate that is only a dev-dependency
+#[tokio::test(flavor = "current_thread")]
+async fn test_dev_dependency_kind() {
+    let project = fixture_dir("dev-dep-project", &[
+        ("Cargo.toml", r#"[package]
+name = "dev-dep-project"
+version = "0.1.0"
+edition = "2021"
+
+[dev-dependencies]
+itoa = "=1.0.10"
+"#),
+        ("src/lib.rs", ""),
+    ]);
+
+    let version = Eg::rust_crate("itoa")
+        .manifest_path(project.join("Cargo.toml"))
+        .dep_kind(DependencyKind::Dev)
+        .resolve_only()
+        .await
+        .expect("Should resolve the dev-dependency");
+    assert_eq!(version, "1.0.10");
+
+    let latest = Eg::rust_crate("itoa")
+        .version("*")
+        .resolve_only()
+        .await
+        .expect("Should resolve the latest itoa");
+    let normal = Eg::rust_crate("itoa")
+        .manifest_path(project.join("Cargo.toml"))
+        .dep_kind(DependencyKind::Normal)
+        .resolve_only()
+        .await
+        .expect("Should fall back to the registry");
+    assert_eq!(normal, latest, "A dev-dependency shouldn't count as a normal one");
+
+    println!("✅ Dev-dependency itoa resolved to {version}");
+}
+
+/// Test searching every crate whose name matches a glob
+#[tokio::test(flavor = "current_thread")]
+async fn test_rust_crates_matching() {
+    let results = Eg::rust_crates_matching("tokio-*")
+        .pattern(r"tokio::")
+        .expect("Should compile regex")
+        .max_crates(5)
+        .search()
+        .await
+        .expect("Should search matching crates");
+
+    assert!(results.iter().all(|r| r.crate_name.starts_with("tokio-") || r.crate_name.starts_with("tokio_")));
+    let with_matches: Vec<_> = results.iter()
+        .filter(|r| !r.result.example_matches.is_empty() || !r.result.other_matches.is_empty())
+        .map(|r| r.crate_name.as_str())
+        .collect();
+    assert!(with_matches.len() >= 2, "Should find matches in several tokio- crates, got: {:?}", with_matches);
+
+    println!("✅ matches in {:?}", with_matches);
+}
+
+/// Test that a project dependency resolves by either its package name or its library name
+#[tokio::test(flavor = "current_thread")]
+async fn test_resolve_by_lib_name() {
+    let project_dir = std::env::temp_dir().join(format!("eg-lib-name-{}", std::process::id()));
+    std::fs::create_dir_all(project_dir.join("src")).unwrap();
+    std::fs::write(
+        project_dir.join("Cargo.toml"),
+        "[package]\nname = \"eg-fixture-package\"\nversion = \"0.3.7\"\nedition = \"2021\"\n\n[lib]\nname = \"eg_fixture_lib\"\npath = \"src/lib.rs\"\n",
+    ).unwrap();
+    std::fs::write(project_dir.join("src/lib.rs"), "").unwrap();
+
+    let resolver = eg::rust::VersionResolver::new().manifest_path(project_dir.join("Cargo.toml"));
+    for name in ["eg-fixture-package", "eg_fixture_lib"] {
+        let resolved = resolver.resolve_crate(name, None).await.expect("Should resolve from the fixture project");
+        assert_eq!(resolved.name, "eg-fixture-package", "Should report the package name for '{}'", name);
+        assert_eq!(resolved.version, "0.3.7");
+    }
+
+    println!("✅ package and lib names both resolve to eg-fixture-package v0.3.7");
+}
+
+/// Test discovering crates by crates.io keyword
+#[tokio::test(flavor = "current_thread")]
+async fn test_find_by_keyword() {
+    let crates = Eg::find_by_keyword("http")
+        .await
+        .expect("Should query crates by keyword");
+
+    assert!(!crates.is_empty(), "Should find crates tagged 'http'");
+    assert!(crates.iter().any(|c| c.name == "hyper"), "Should include hyper");
+    assert!(crates.windows(2).all(|w| w[0].downloads >= w[1].downloads), "Should be sorted by downloads");
+
+    println!("✅ Found {} crates tagged 'http', top: {}", crates.len(), crates[0].name);
+}
+
+/// Test that mis-cased and hyphenated names resolve to the registry's canonical crate name
+#[tokio::test(flavor = "current_thread")]
+async fn test_normalized_crate_name() {
+    let resolver = eg::rust::VersionResolver::new();
+
+    for name in ["Serde_JSON", "serde-json"] {
+        let resolved = resolver
+            .resolve_crate(name, Some("^1.0"))
+            .await
+            .expect("Should resolve despite the spelling");
+        assert_eq!(resolved.name, "serde_json", "'{}' should resolve to serde_json", name);
+        assert!(resolved.version.starts_with("1."));
+    }
+
+    println!("✅ Serde_JSON and serde-json both resolve to serde_json");
+}
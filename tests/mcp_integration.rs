@@ -6,7 +6,7 @@ mod mcp_tests {
     #[tokio::test]
     async fn test_mcp_server_initialization() {
         let mut child = Command::new("cargo")
-            .args(&["run", "--bin", "eg-mcp"])
+            .args(["run", "--bin", "eg-mcp"])
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::null())
@@ -51,5 +51,125 @@ mod mcp_tests {
 
         // Cleanup
         child.kill().expect("Failed to kill child process");
+        child.wait().expect("Failed to reap child process");
+    }
+
+    #[tokio::test]
+    async fn test_get_crate_source_listing() {
+        let mut child = Command::new("cargo")
+            .args(["run", "--bin", "eg-mcp"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start eg-mcp server");
+
+        let stdin = child.stdin.as_mut().expect("Failed to get stdin");
+        let stdout = child.stdout.as_mut().expect("Failed to get stdout");
+        let mut reader = BufReader::new(stdout);
+
+        let init_request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {"tools": {}},
+                "clientInfo": {"name": "test", "version": "1.0"}
+            }
+        });
+        writeln!(stdin, "{}", init_request).expect("Failed to write to stdin");
+        let mut response_line = String::new();
+        reader.read_line(&mut response_line).expect("Failed to read response");
+
+        writeln!(stdin, "{}", json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+            .expect("Failed to write to stdin");
+
+        // 'regex' is one of our own dependencies, so it resolves from the project
+        let call_request = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": {
+                "name": "get_crate_source",
+                "arguments": {"crate_name": "regex", "include_listing": true}
+            }
+        });
+        writeln!(stdin, "{}", call_request).expect("Failed to write to stdin");
+
+        let mut response_line = String::new();
+        reader.read_line(&mut response_line).expect("Failed to read response");
+        let response: Value = serde_json::from_str(&response_line)
+            .expect("Failed to parse JSON response");
+        assert_eq!(response["id"], 2);
+
+        let text = response["result"]["content"][0]["text"].as_str().expect("Should return text content");
+        let source: Value = serde_json::from_str(text).expect("Should return JSON text");
+        assert!(source["checkout_path"].is_string());
+        assert!(source["examples"].is_array(), "Listing should be included: {}", source);
+        assert!(source["stats"]["files_seen"].as_u64() > Some(0), "Stats should be included: {}", source);
+
+        child.kill().expect("Failed to kill child process");
+        child.wait().expect("Failed to reap child process");
+    }
+
+    #[tokio::test]
+    async fn test_search_max_snippet_lines() {
+        let mut child = Command::new("cargo")
+            .args(["run", "--bin", "eg-mcp"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start eg-mcp server");
+
+        let stdin = child.stdin.as_mut().expect("Failed to get stdin");
+        let stdout = child.stdout.as_mut().expect("Failed to get stdout");
+        let mut reader = BufReader::new(stdout);
+
+        let init_request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {"tools": {}},
+                "clientInfo": {"name": "test", "version": "1.0"}
+            }
+        });
+        writeln!(stdin, "{}", init_request).expect("Failed to write to stdin");
+        let mut response_line = String::new();
+        reader.read_line(&mut response_line).expect("Failed to read response");
+
+        writeln!(stdin, "{}", json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+            .expect("Failed to write to stdin");
+
+        // Default context is two lines either side, so one line forces a cut around every match
+        let call_request = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": {
+                "name": "search_crate_examples",
+                "arguments": {"crate_name": "regex", "pattern": "Regex::new", "format": "hunk", "max_snippet_lines": 1}
+            }
+        });
+        writeln!(stdin, "{}", call_request).expect("Failed to write to stdin");
+
+        let mut response_line = String::new();
+        reader.read_line(&mut response_line).expect("Failed to read response");
+        let response: Value = serde_json::from_str(&response_line)
+            .expect("Failed to parse JSON response");
+        assert_eq!(response["id"], 2);
+
+        let text = response["result"]["content"][0]["text"].as_str().expect("Should return text content");
+        assert!(text.contains(" // ... (truncated) ..."), "Snippets should be cut: {}", text);
+        assert!(
+            text.lines().filter(|line| line.starts_with('>')).all(|line| line.contains("Regex::new")),
+            "The matched line is what's kept"
+        );
+
+        child.kill().expect("Failed to kill child process");
+        child.wait().expect("Failed to reap child process");
     }
 }
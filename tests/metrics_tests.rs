@@ -0,0 +1,53 @@
+//! Tests for the process-wide metrics counters
+//!
+//! Kept in their own test binary so other tests can't move the counters concurrently.
+
+mod common;
+
+use common::{crate_archive, fixture_dir};
+use eg::Eg;
+
+/// Test that an uncached, a cached, and a result-cached search each move the right counter
+#[tokio::test(flavor = "current_thread")]
+async fn test_metrics_counters() {
+    let dir = fixture_dir("metrics", &[]);
+    let crate_file = dir.join("demo-metrics-0.1.0.crate");
+
+    // Unique contents, so the content-addressed cache can't already hold this archive
+    let unique = format!("pub fn metered() {{}} // {:?}", std::time::SystemTime::now());
+    std::fs::write(&crate_file, crate_archive("demo-metrics", "0.1.0", &[
+        ("src/lib.rs", unique.as_str()),
+    ])).unwrap();
+
+    let search = || Eg::rust_crate_file(&crate_file).pattern(r"metered").expect("Should compile regex");
+    let start = Eg::metrics();
+
+    search().search().await.expect("Should search the archive");
+    let after_uncached = Eg::metrics();
+    assert_eq!(after_uncached.extractions, start.extractions + 1);
+    assert_eq!(after_uncached.cache_hits, start.cache_hits);
+
+    search().search().await.expect("Should search the archive");
+    let after_cached = Eg::metrics();
+    assert_eq!(after_cached.extractions, after_uncached.extractions, "Should reuse the extraction");
+    assert_eq!(after_cached.cache_hits, after_uncached.cache_hits + 1);
+
+    // A new pattern over the same archive reruns only the regex over the extracted files
+    Eg::rust_crate_file(&crate_file)
+        .pattern(r"fn \w+")
+        .expect("Should compile regex")
+        .search()
+        .await
+        .expect("Should search the archive");
+    let after_new_pattern = Eg::metrics();
+    assert_eq!(after_new_pattern.extractions, after_cached.extractions, "Should not re-decompress");
+    assert_eq!(after_new_pattern.downloads, after_cached.downloads, "Should not re-download");
+    assert_eq!(after_new_pattern.cache_hits, after_cached.cache_hits + 1);
+    let after_cached = after_new_pattern;
+
+    search().result_cache(true).search().await.expect("Should search the archive");
+    search().result_cache(true).search().await.expect("Should search the archive");
+    let after_result_cache = Eg::metrics();
+    assert_eq!(after_result_cache.result_cache_hits, after_cached.result_cache_hits + 1);
+    assert_eq!(after_result_cache.downloads, start.downloads, "Local archives are never downloaded");
+}
@@ -0,0 +1,373 @@
+//! Tests for combining and post-processing search results
+
+mod common;
+
+use eg::{CheckoutSource, ContextLine, Match, OutputFormat, SearchResult};
+use std::path::PathBuf;
+
+/// Build a match at the given location
+#[allow(deprecated)] // struct update touches the deprecated context vectors
+fn found(file_path: &str, line_number: u32, line_content: &str) -> Match {
+    Match {
+        file_path: PathBuf::from(file_path),
+        line_number,
+        line_content: line_content.to_string(),
+        ..Match::default()
+    }
+}
+
+/// Test that merging two results yields their union without duplicate matches
+#[test]
+fn test_merge_dedups() {
+    let packaged = SearchResult {
+        version: "1.0.0".to_string(),
+        example_matches: vec![
+            found("examples/client.rs", 3, "client.connect();"),
+            found("examples/server.rs", 8, "server.listen();"),
+        ],
+        ..SearchResult::default()
+    };
+    let repository = SearchResult {
+        version: "1.0.0".to_string(),
+        example_matches: vec![
+            found("examples/client.rs", 3, "client.connect();"),
+            found("examples/proxy.rs", 12, "proxy.connect();"),
+        ],
+        ..SearchResult::default()
+    };
+
+    let merged = packaged.merge(repository);
+
+    let locations: Vec<_> = merged.example_matches.iter()
+        .map(|m| (m.file_path.to_string_lossy().to_string(), m.line_number))
+        .collect();
+    assert_eq!(locations, vec![
+        ("examples/client.rs".to_string(), 3),
+        ("examples/server.rs".to_string(), 8),
+        ("examples/proxy.rs".to_string(), 12),
+    ]);
+    assert_eq!(merged.version, "1.0.0");
+}
+
+/// Test that the same snippet found under another path is merged as a duplicate
+#[test]
+fn test_merge_dedups_by_snippet() {
+    let with_context = |file_path: &str, line_number: u32| Match {
+        context: vec![
+            ContextLine { line_number: line_number - 1, content: "let client = Client::new();".to_string(), is_match: false },
+            ContextLine { line_number, content: "client.connect();".to_string(), is_match: true },
+        ],
+        ..found(file_path, line_number, "client.connect();")
+    };
+    let packaged = SearchResult {
+        example_matches: vec![with_context("examples/client.rs", 3)],
+        ..SearchResult::default()
+    };
+    let repository = SearchResult {
+        example_matches: vec![
+            with_context("crates/demo/examples/client.rs", 5),
+            found("examples/client.rs", 3, "client.connect();"),
+        ],
+        ..SearchResult::default()
+    };
+
+    let merged = packaged.merge(repository);
+
+    let paths: Vec<_> = merged.example_matches.iter().map(|m| m.file_path.clone()).collect();
+    assert_eq!(
+        paths,
+        vec![PathBuf::from("examples/client.rs"), PathBuf::from("examples/client.rs")],
+        "The moved copy is dropped; a different snippet at the same location is kept"
+    );
+    assert!(merged.example_matches[1].context.is_empty());
+}
+
+/// Test that run commands pass the features a gated example requires
+#[test]
+fn test_run_commands() {
+    let crate_dir = common::fixture_dir("run-commands", &[
+        ("Cargo.toml", "[package]\nname = \"demo\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[features]\nrustls = []\nserde = []\n\n[[example]]\nname = \"tls\"\nrequired-features = [\"rustls\", \"serde\"]\n"),
+        ("src/lib.rs", ""),
+        ("examples/tls.rs", "fn main() {}"),
+        ("examples/hello.rs", "fn main() {}"),
+    ]);
+
+    let result = SearchResult {
+        checkout_path: crate_dir,
+        ..SearchResult::default()
+    };
+
+    assert_eq!(result.run_commands().expect("Should read the manifest"), vec![
+        (PathBuf::from("examples/hello.rs"), "cargo run --example hello".to_string()),
+        (PathBuf::from("examples/tls.rs"), "cargo run --example tls --features rustls,serde".to_string()),
+    ]);
+}
+
+/// Test that a broken manifest is reported rather than read as a crate without examples
+#[test]
+fn test_run_commands_broken_manifest() {
+    let crate_dir = common::fixture_dir("run-commands-broken", &[
+        ("Cargo.toml", "[package\nname = \"demo\"\n"),
+        ("examples/hello.rs", "fn main() {}"),
+    ]);
+
+    let result = SearchResult {
+        checkout_path: crate_dir,
+        ..SearchResult::default()
+    };
+
+    assert!(result.run_commands().is_err());
+}
+
+/// Test that a hunk header spans exactly the context window around the match
+#[test]
+#[allow(deprecated)] // struct update touches the deprecated context vectors
+fn test_hunk_format() {
+    let context = [(11, "let a = 1;", false), (12, "client.connect();", true), (13, "let b = 2;", false)];
+    let m = Match {
+        context: context.iter()
+            .map(|&(line_number, content, is_match)| ContextLine { line_number, content: content.to_string(), is_match })
+            .collect(),
+        ..found("examples/client.rs", 12, "client.connect();")
+    };
+
+    assert_eq!(m.to_hunk(), "@@ -11,3 @@ examples/client.rs\n let a = 1;\n>client.connect();\n let b = 2;\n");
+
+    let result = SearchResult {
+        example_matches: vec![m.clone()],
+        ..SearchResult::default()
+    };
+    assert_eq!(result.render(OutputFormat::Hunk), m.to_hunk());
+}
+
+/// Test grouping matches by file, keeping the order each file first appears in
+#[test]
+fn test_by_file() {
+    let result = SearchResult {
+        example_matches: vec![
+            found("examples/client.rs", 3, "client.connect();"),
+            found("examples/server.rs", 8, "server.listen();"),
+            found("examples/client.rs", 9, "client.close();"),
+        ],
+        ..SearchResult::default()
+    };
+
+    let groups = result.by_file();
+    let summary: Vec<(PathBuf, Vec<u32>)> = groups.iter()
+        .map(|file| (file.path.to_path_buf(), file.matches.iter().map(|m| m.line_number).collect()))
+        .collect();
+    assert_eq!(summary, vec![
+        (PathBuf::from("examples/client.rs"), vec![3, 9]),
+        (PathBuf::from("examples/server.rs"), vec![8]),
+    ]);
+}
+
+/// Test that long snippets are cut around the match with truncation markers
+#[test]
+fn test_limit_snippets() {
+    let context = |lines: std::ops::RangeInclusive<u32>, matched: u32| lines
+        .map(|line_number| ContextLine { line_number, content: format!("line {line_number}"), is_match: line_number == matched })
+        .collect::<Vec<_>>();
+    let mut result = SearchResult {
+        example_matches: vec![
+            Match { context: context(1..=20, 10), ..found("examples/long.rs", 10, "line 10") },
+            Match { context: context(1..=20, 2), ..found("examples/early.rs", 2, "line 2") },
+            Match { context: context(1..=3, 2), ..found("examples/short.rs", 2, "line 2") },
+        ],
+        ..SearchResult::default()
+    };
+
+    result.limit_snippets(5);
+
+    fn lines(m: &Match) -> Vec<&str> {
+        m.context.iter().map(|line| line.content.as_str()).collect()
+    }
+    let marker = "// ... (truncated) ...";
+    assert_eq!(lines(&result.example_matches[0]), vec![marker, "line 8", "line 9", "line 10", "line 11", "line 12", marker]);
+    assert_eq!(lines(&result.example_matches[1]), vec!["line 1", "line 2", "line 3", "line 4", "line 5", marker], "Lines unused before the match go after it");
+    assert_eq!(lines(&result.example_matches[2]), vec!["line 1", "line 2", "line 3"], "Short snippets are untouched");
+    assert!(result.example_matches[0].context[0].is_truncation_marker());
+    assert!(result.example_matches[0].to_hunk().starts_with("@@ -8,7 @@"), "The hunk starts at the first real line");
+}
+
+/// Test that a long multi-line match is cut in its line content as well as its context
+#[test]
+fn test_limit_snippets_multiline_match() {
+    let matched: Vec<String> = (1..=6).map(|line_number| format!("line {line_number}")).collect();
+    let mut result = SearchResult {
+        other_matches: vec![Match {
+            context: matched.iter()
+                .enumerate()
+                .map(|(idx, content)| ContextLine { line_number: idx as u32 + 1, content: content.clone(), is_match: true })
+                .collect(),
+            ..found("src/lib.rs", 1, &matched.join("\n"))
+        }],
+        ..SearchResult::default()
+    };
+
+    result.limit_snippets(3);
+
+    let m = &result.other_matches[0];
+    assert_eq!(m.line_content, "line 1\nline 2\nline 3\n// ... (truncated) ...");
+    let lines: Vec<&str> = m.context.iter().map(|line| line.content.as_str()).collect();
+    assert_eq!(lines, vec!["line 1", "line 2", "line 3", "// ... (truncated) ..."]);
+}
+
+/// Test the summary line's fields for a search over a known source tree
+#[tokio::test(flavor = "current_thread")]
+async fn test_summary_line() {
+    let crate_dir = common::fixture_dir("summary-line", &[
+        ("src/lib.rs", "pub fn connect() {}\npub fn reconnect() { connect() }\n"),
+        ("examples/client.rs", "fn main() { demo::connect(); }\n"),
+    ]);
+
+    let result = eg::Eg::rust_dir(&crate_dir)
+        .pattern(r"connect\(")
+        .expect("Should compile regex")
+        .search()
+        .await
+        .expect("Should search the directory");
+
+    assert_eq!(result.summary_line(), "matches=3 files=2 source=local version=- truncated=false");
+}
+
+/// Test that JSON Lines output is one standalone match object per line
+#[tokio::test(flavor = "current_thread")]
+async fn test_search_ndjson() {
+    let crate_dir = common::fixture_dir("search-ndjson", &[
+        ("src/lib.rs", "pub fn connect() {}\npub fn reconnect() { connect() }\n"),
+        ("examples/client.rs", "fn main() { demo::connect(); }\n"),
+    ]);
+
+    let mut output = Vec::new();
+    let result = eg::Eg::rust_dir(&crate_dir)
+        .pattern(r"connect\(")
+        .expect("Should compile regex")
+        .search_ndjson(&mut output)
+        .await
+        .expect("Should search the directory");
+
+    let output = String::from_utf8(output).expect("Output should be UTF-8");
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3);
+    for line in &lines {
+        let value: serde_json::Value = serde_json::from_str(line).expect("Each line should parse on its own");
+        assert!(value["file_path"].is_string() && value["line_number"].is_u64(), "Not a match: {}", line);
+    }
+    assert!(lines[0].contains("examples/client.rs"), "Examples come first");
+    assert_eq!(result.render(OutputFormat::JsonLines), output);
+}
+
+/// Test that absolute match paths point at files in the on-disk extraction
+#[tokio::test(flavor = "current_thread")]
+async fn test_absolute_path() {
+    let dir = common::fixture_dir("absolute-path", &[]);
+    let crate_file = dir.join("pathy-0.1.0.crate");
+    std::fs::write(&crate_file, common::crate_archive("pathy", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"pathy\"\nversion = \"0.1.0\"\n"),
+        ("examples/open.rs", "fn main() { pathy::open_me(); }\n"),
+    ])).unwrap();
+
+    let search = || eg::Eg::rust_crate_file(&crate_file).pattern(r"open_me").expect("Should compile regex");
+
+    let relative = search().search().await.expect("Should search the archive");
+    let m = &relative.example_matches[0];
+    assert_eq!(m.file_path, PathBuf::from("examples/open.rs"));
+    let joined = relative.absolute_path(m);
+    assert!(joined.is_absolute() && joined.exists(), "{} should exist", joined.display());
+
+    let absolute = search().absolute_paths(true).search().await.expect("Should search the archive");
+    assert_eq!(absolute.example_matches[0].file_path, joined);
+    assert_eq!(absolute.absolute_path(&absolute.example_matches[0]), joined);
+}
+
+/// Test that the markdown export fences each match and links packaged sources to docs.rs
+#[test]
+fn test_to_markdown() {
+    let mut example = found("examples/client.rs", 12, "client.connect();");
+    example.context = vec![
+        ContextLine { line_number: 11, content: "let client = Client::new();".to_string(), is_match: false },
+        ContextLine { line_number: 12, content: "client.connect();".to_string(), is_match: true },
+    ];
+    let result = SearchResult {
+        crate_name: "demo".to_string(),
+        version: "1.2.3".to_string(),
+        example_matches: vec![example],
+        other_matches: vec![found("src/lib.rs", 40, "pub fn connect() {}")],
+        ..SearchResult::default()
+    };
+
+    let markdown = result.to_markdown();
+    assert!(markdown.starts_with("# demo 1.2.3\n"));
+    assert!(markdown.contains("- [examples/client.rs:12](#match-1)\n- [src/lib.rs:40](#match-2)\n"));
+    assert!(markdown.contains("[Source on docs.rs](https://docs.rs/crate/demo/1.2.3/source/examples/client.rs#12)"));
+    assert!(markdown.contains("Lines 11-12, match at line 12:\n\n```rust\nlet client = Client::new();\nclient.connect();\n```\n"));
+    assert!(markdown.contains("## Other matches"));
+    assert_eq!(result.render(OutputFormat::Markdown), markdown);
+
+    let local = SearchResult { source: CheckoutSource::Local, ..result };
+    assert!(!local.to_markdown().contains("docs.rs"), "Local sources have no docs.rs page");
+}
+
+/// Test that the phases a search runs are timed and fit within the whole search
+#[tokio::test(flavor = "current_thread")]
+async fn test_collect_timings() {
+    let dir = common::fixture_dir("collect-timings", &[]);
+    let crate_file = dir.join("timed-0.1.0.crate");
+    std::fs::write(&crate_file, common::crate_archive("timed", "0.1.0", &[
+        ("Cargo.toml", "[package]\nname = \"timed\"\nversion = \"0.1.0\"\n"),
+        ("examples/tick.rs", "fn main() { timed::tick(); }\n"),
+    ])).unwrap();
+
+    let untimed = eg::Eg::rust_crate_file(&crate_file).search().await.expect("Should search the archive");
+    assert_eq!(untimed.timings, None, "Timings are opt-in");
+
+    let started = std::time::Instant::now();
+    let result = eg::Eg::rust_crate_file(&crate_file)
+        .pattern(r"tick")
+        .expect("Should compile regex")
+        .collect_timings(true)
+        .search()
+        .await
+        .expect("Should search the archive");
+    let elapsed = started.elapsed();
+
+    let timings = result.timings.expect("Should collect timings");
+    assert_eq!(timings.download, std::time::Duration::ZERO, "Local archives are never downloaded");
+    assert_eq!(timings.locate, std::time::Duration::ZERO, "Local archives are never looked up in a cache");
+    assert!(timings.extract > std::time::Duration::ZERO, "Unpacking should be timed: {:?}", timings);
+    assert!(timings.search > std::time::Duration::ZERO, "Matching should be timed: {:?}", timings);
+    assert!(timings.total() <= elapsed, "{:?} should fit within {:?}", timings, elapsed);
+}
+
+/// Test that a configured search can be shared across tasks and run more than once
+#[tokio::test(flavor = "current_thread")]
+async fn test_reusable_builder() {
+    fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+    fn assert_send<T: Send>(_: &T) {}
+    assert_shareable::<eg::rust::RustCrateSearch>();
+    assert_shareable::<eg::rust::RustCratesMatching>();
+
+    let dir = common::fixture_dir("reusable-builder", &[]);
+    let crate_file = dir.join("reuse-0.1.0.crate");
+    std::fs::write(&crate_file, common::crate_archive("reuse", "0.1.0", &[
+        ("examples/again.rs", "fn main() { reuse::again(); }\n"),
+    ])).unwrap();
+
+    let configured = eg::Eg::rust_crate_file(&crate_file)
+        .pattern(r"again\(")
+        .expect("Should compile regex")
+        .context_lines(0);
+
+    let first = configured.clone().search();
+    assert_send(&first);
+    let first = first.await.expect("First run should succeed");
+    let second = tokio::spawn(configured.clone().search())
+        .await
+        .expect("Task should not panic")
+        .expect("Second run should succeed");
+
+    assert_eq!(first.example_matches.len(), 1);
+    assert_eq!(second.example_matches.len(), first.example_matches.len());
+    assert_eq!(second.checkout_path, first.checkout_path);
+}
@@ -0,0 +1,1013 @@
+//! Tests for searching local crate trees, without touching the network
+
+mod common;
+
+use common::fixture_dir;
+use eg::rust::{CrateSearcher, MatchMode, SearchScope};
+use eg::DocBlockKind;
+use regex::Regex;
+use std::fs;
+use std::path::PathBuf;
+
+/// Test matching example file names without scanning their contents
+#[test]
+fn test_match_filenames() {
+    let crate_dir = fixture_dir("match-filenames", &[
+        ("src/tls.rs", "pub fn tls() {}"),
+    ]);
+
+    // Invalid UTF-8 contents would fail to read, so any content scanning would drop these files
+    for example in ["examples/tls_client.rs", "examples/websocket.rs", "examples/tls/main.rs"] {
+        let path = crate_dir.join(example);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"\xff\xfe not utf-8").unwrap();
+    }
+
+    let pattern = Regex::new("tls").unwrap();
+    let mut matches = CrateSearcher::new()
+        .search_filenames(&crate_dir, &pattern)
+        .expect("Filename search should succeed");
+    matches.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+
+    let paths: Vec<_> = matches.iter().map(|m| m.file_path.clone()).collect();
+    assert_eq!(paths, vec![
+        PathBuf::from("examples/tls/main.rs"),
+        PathBuf::from("examples/tls_client.rs"),
+    ]);
+    assert!(matches.iter().all(|m| m.line_number == 0), "Should be whole-file matches");
+}
+
+/// Test a pattern spanning two lines produces a single match at its starting line
+#[test]
+fn test_multiline_match() {
+    let crate_dir = fixture_dir("multiline-match", &[
+        ("examples/server.rs", "use std::net::TcpListener;\n\nfn serve(\n    listener: TcpListener,\n) {\n}\n"),
+    ]);
+
+    let pattern = regex::RegexBuilder::new(r"fn serve\(.*?listener")
+        .dot_matches_new_line(true)
+        .build()
+        .unwrap();
+    let matches = CrateSearcher::new()
+        .multiline_matches(true)
+        .search_crate(&crate_dir, &pattern, 1)
+        .expect("Search should succeed");
+
+    assert!(matches.other_matches.is_empty());
+    assert_eq!(matches.example_matches.len(), 1, "Should report one multi-line match");
+
+    let found = &matches.example_matches[0];
+    assert_eq!(found.line_number, 3, "Should report the starting line");
+    assert_eq!(found.line_content, "fn serve(\n    listener: TcpListener,");
+    let context: Vec<_> = found.context.iter().map(|c| (c.line_number, c.is_match)).collect();
+    assert_eq!(context, vec![(2, false), (3, true), (4, true), (5, false)]);
+}
+
+/// Test that a precompiled pattern is shared, not recompiled, across a batch of searches
+#[tokio::test(flavor = "current_thread")]
+async fn test_shared_pattern_arc() {
+    let pattern = std::sync::Arc::new(Regex::new(r"spawn").unwrap());
+
+    let searches: Vec<_> = ["demo-tokio", "demo-async-std", "demo-smol"]
+        .into_iter()
+        .map(|name| {
+            let source = format!("//! {}\n\npub fn run() {{ spawn(); }}\n", name);
+            let dir = fixture_dir(&format!("shared-pattern-{}", name), &[("src/lib.rs", source.as_str())]);
+            eg::Eg::rust_dir(&dir).pattern_arc(pattern.clone()).context_lines(3)
+        })
+        .collect();
+
+    for search in &searches {
+        let compiled = search.compiled_pattern().expect("Should have a pattern");
+        assert!(std::sync::Arc::ptr_eq(compiled, &pattern), "Should reuse the same compiled regex");
+    }
+
+    let tasks: Vec<_> = searches.into_iter().map(|search| tokio::spawn(search.search())).collect();
+    for task in tasks {
+        let result = task.await.unwrap().expect("Should search the directory");
+        assert_eq!(result.other_matches.len(), 1, "{:?}", result.other_matches);
+        let found = &result.other_matches[0];
+        assert_eq!(found.file_path, PathBuf::from("src/lib.rs"));
+        assert_eq!(found.line_number, 3);
+        assert_eq!(found.line_content, "pub fn run() { spawn(); }");
+    }
+}
+
+/// Test that a per-file limit keeps one noisy file from crowding out the others
+#[test]
+fn test_max_matches_per_file() {
+    let generated = "let spawned = spawn();\n".repeat(50);
+    let crate_dir = fixture_dir("max-matches-per-file", &[
+        ("examples/generated.rs", generated.as_str()),
+        ("examples/a.rs", "fn main() { spawn(); }"),
+        ("examples/b.rs", "fn main() { spawn(); }"),
+        ("src/lib.rs", "pub fn spawn() {}\npub fn spawn_blocking() {}\n"),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .max_matches_per_file(Some(2))
+        .search_crate(&crate_dir, &Regex::new("spawn").unwrap(), 0)
+        .expect("Search should succeed");
+
+    let count = |name: &str| matches.example_matches.iter().filter(|m| m.file_path.ends_with(name)).count();
+    assert_eq!(count("generated.rs"), 2, "Noisy file should be capped");
+    assert_eq!(count("a.rs"), 1);
+    assert_eq!(count("b.rs"), 1);
+    assert_eq!(matches.other_matches.len(), 2, "Files at the limit are kept whole");
+    assert_eq!(matches.truncated_files, vec![PathBuf::from("examples/generated.rs")]);
+}
+
+/// Test that crate-relative paths render with forward slashes on every platform
+#[test]
+fn test_forward_slash_paths() {
+    let path = std::path::Path::new("examples").join("net").join("tcp.rs");
+    let normalized = eg::rust::normalize_path_separators(&path);
+    assert_eq!(normalized.to_string_lossy(), "examples/net/tcp.rs");
+
+    let crate_dir = fixture_dir("forward-slash-paths", &[
+        ("examples/net/tcp.rs", "fn main() { connect(); }"),
+    ]);
+    let matches = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new("connect").unwrap(), 0)
+        .expect("Search should succeed");
+    assert_eq!(matches.example_matches[0].file_path.to_string_lossy(), "examples/net/tcp.rs");
+}
+
+/// Test that context lines carry contiguous line numbers and flag the matched line
+#[test]
+fn test_context_line_numbers() {
+    let crate_dir = fixture_dir("context-line-numbers", &[
+        ("src/lib.rs", "// one\n// two\nfn target() {}\n// four\n// five\n"),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new("target").unwrap(), 2)
+        .expect("Search should succeed");
+    let found = &matches.other_matches[0];
+
+    let line_numbers: Vec<_> = found.context.iter().map(|c| c.line_number).collect();
+    assert_eq!(line_numbers, vec![1, 2, 3, 4, 5], "Context should be contiguous");
+
+    let flagged: Vec<_> = found.context.iter().filter(|c| c.is_match).collect();
+    assert_eq!(flagged.len(), 1);
+    assert_eq!(flagged[0].line_number, found.line_number);
+    assert_eq!(flagged[0].content, "fn target() {}");
+}
+
+/// Test that zero context lines yields just the match, without allocating context vectors
+#[test]
+#[allow(deprecated)] // checks the deprecated context vectors stay unallocated
+fn test_zero_context() {
+    let crate_dir = fixture_dir("zero-context", &[
+        ("src/lib.rs", "// one\n// two\nfn target() {}\n// four\n// five\n"),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new("target").unwrap(), 0)
+        .expect("Search should succeed");
+    let found = &matches.other_matches[0];
+
+    assert_eq!(found.line_number, 3);
+    assert!(found.context_before.is_empty() && found.context_before.capacity() == 0);
+    assert!(found.context_after.is_empty() && found.context_after.capacity() == 0);
+    assert_eq!(found.context, vec![eg::ContextLine { line_number: 3, content: "fn target() {}".to_string(), is_match: true }]);
+}
+
+/// Test that a match's byte range slices exactly the matched text out of the file
+#[test]
+fn test_byte_offsets() {
+    let example = "use demo::Client;\r\n\r\nfn main() {\r\n    let c = Client::connect(\"addr\");\r\n}\r\n";
+    let crate_dir = fixture_dir("byte-offsets", &[
+        ("examples/connect.rs", example),
+        ("src/lib.rs", "pub struct Client;\nimpl Client {\n    pub fn connect(\n        addr: &str,\n    ) {}\n}\n"),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new(r"Client::connect").unwrap(), 1)
+        .expect("Search should succeed");
+    let found = &matches.example_matches[0];
+    assert_eq!(&example[found.byte_start..found.byte_end], "Client::connect");
+
+    let multiline = CrateSearcher::new()
+        .multiline_matches(true)
+        .search_crate(&crate_dir, &Regex::new(r"fn connect\(\s*addr").unwrap(), 0)
+        .expect("Search should succeed");
+    let found = &multiline.other_matches[0];
+    let lib = fs::read_to_string(crate_dir.join("src/lib.rs")).unwrap();
+    assert_eq!(&lib[found.byte_start..found.byte_end], "fn connect(\n        addr");
+}
+
+/// Test that a byte order mark and CRLF endings don't shift lines or break anchors
+#[tokio::test(flavor = "current_thread")]
+async fn test_bom_and_crlf() {
+    let example = "\u{feff}use demo::Client;\r\n\r\nfn main() {\r\n    Client::connect();\r\n}\r\n";
+    let crate_dir = fixture_dir("bom-crlf", &[("examples/connect.rs", example)]);
+    let search = |pattern: &str| eg::Eg::rust_dir(&crate_dir)
+        .pattern(pattern)
+        .expect("Should compile regex");
+
+    let result = search(r"^use demo").search().await.expect("Should search the directory");
+    assert_eq!(result.example_matches.len(), 1, "^ should anchor after the BOM");
+    let found = &result.example_matches[0];
+    assert_eq!(found.line_number, 1);
+    assert_eq!(found.line_content, "use demo::Client;");
+    assert_eq!(&example[found.byte_start..found.byte_end], "use demo", "Offsets count the BOM");
+
+    let result = search(r"connect\(\);$").search().await.expect("Should search the directory");
+    assert_eq!(result.example_matches.iter().map(|m| m.line_number).collect::<Vec<_>>(), vec![4]);
+
+    let result = search(r"^fn main\(\) \{$")
+        .dot_matches_newline(true)
+        .expect("Should recompile the pattern")
+        .search()
+        .await
+        .expect("Should search the directory");
+    let found = &result.example_matches[0];
+    assert_eq!(found.line_number, 3);
+    assert_eq!(&example[found.byte_start..found.byte_end], "fn main() {");
+
+    let result = eg::Eg::rust_dir(&crate_dir)
+        .find_ident("Client")
+        .expect("Should build the identifier search")
+        .search()
+        .await
+        .expect("Should search the directory");
+    let found = &result.example_matches[0];
+    assert_eq!(found.line_number, 1);
+    assert_eq!(&example[found.byte_start..found.byte_end], "Client");
+}
+
+/// Test that identifier search skips longer identifiers, comments, and strings
+#[tokio::test(flavor = "current_thread")]
+async fn test_find_ident() {
+    let crate_dir = fixture_dir("find-ident", &[
+        ("examples/tasks.rs", "fn main() {\n    // spawn a task\n    let name = \"spawn\";\n    tokio::task::spawn_blocking(work);\n    tokio::spawn(async {});\n}\n"),
+        ("src/broken.rs", "fn spawn( {\n"),
+    ]);
+
+    let result = eg::Eg::rust_dir(&crate_dir)
+        .find_ident("spawn")
+        .expect("Should build the identifier search")
+        .search()
+        .await
+        .expect("Should search the directory");
+
+    let lines: Vec<u32> = result.example_matches.iter().map(|m| m.line_number).collect();
+    assert_eq!(lines, vec![5], "Only the real call to spawn should match");
+    let found = &result.example_matches[0];
+    let source = fs::read_to_string(crate_dir.join("examples/tasks.rs")).unwrap();
+    assert_eq!(&source[found.byte_start..found.byte_end], "spawn");
+
+    // An unbalanced file doesn't tokenize, so it falls back to the regex
+    assert_eq!(result.other_matches.len(), 1);
+    assert_eq!(result.other_matches[0].file_path, PathBuf::from("src/broken.rs"));
+}
+
+/// Test that macro search spans whole invocations and skips a function of the same name
+#[tokio::test(flavor = "current_thread")]
+async fn test_find_macro() {
+    let example = "fn json() {}\n\nfn main() {\n    let v = json!({\n        \"a\": 1,\n    });\n    json();\n    let w = serde_json::json!([1]);\n}\n";
+    let crate_dir = fixture_dir("find-macro", &[
+        ("examples/build.rs", example),
+        ("src/lib.rs", "#[derive(Debug, Serialize)]\npub struct Config;\n"),
+    ]);
+
+    let result = eg::Eg::rust_dir(&crate_dir)
+        .find_macro("json!")
+        .expect("Should build the macro search")
+        .search()
+        .await
+        .expect("Should search the directory");
+
+    let lines: Vec<u32> = result.example_matches.iter().map(|m| m.line_number).collect();
+    assert_eq!(lines, vec![4, 8], "The json function should not match");
+    let found = &result.example_matches[0];
+    assert_eq!(&example[found.byte_start..found.byte_end], "json!({\n        \"a\": 1,\n    })");
+    assert_eq!(found.line_content.lines().count(), 3, "The match covers the whole invocation");
+
+    let derives = eg::Eg::rust_dir(&crate_dir)
+        .find_macro("Serialize")
+        .expect("Should build the macro search")
+        .search()
+        .await
+        .expect("Should search the directory");
+    assert_eq!(derives.other_matches.len(), 1);
+    assert_eq!(derives.other_matches[0].line_number, 1);
+}
+
+/// Test classifying README code blocks by their fence attributes, and excluding compile_fail ones
+#[tokio::test(flavor = "current_thread")]
+async fn test_doc_block_kinds() {
+    let readme = "```rust\nrun();\n```\n```no_run\nconnect();\n```\n```rust,ignore\npseudo code\n```\n```compile_fail,E0382\nuse_after_move();\n```\n```should_panic\npanics();\n```\n```text\nnot rust\n```\n";
+    let crate_dir = fixture_dir("doc-block-kinds", &[("README.md", readme)]);
+
+    let examples = CrateSearcher::new()
+        .fenced_examples(&crate_dir, std::path::Path::new("README.md"))
+        .expect("Should read the README");
+    let kinds: Vec<_> = examples.iter().map(|example| example.doc_block).collect();
+    assert_eq!(kinds, vec![
+        Some(DocBlockKind::Run),
+        Some(DocBlockKind::NoRun),
+        Some(DocBlockKind::Ignore),
+        Some(DocBlockKind::CompileFail),
+        Some(DocBlockKind::ShouldPanic),
+    ]);
+
+    let usable = eg::Eg::rust_dir(&crate_dir)
+        .exclude_doc_blocks(&[DocBlockKind::CompileFail, DocBlockKind::Ignore])
+        .readme_examples()
+        .await
+        .expect("Should read the README");
+    let contents: Vec<&str> = usable.iter().map(|example| example.content.as_str()).collect();
+    assert_eq!(contents, vec!["run();", "connect();", "panics();"]);
+}
+
+/// A matcher for the literal word "needle" that records the files it was asked about
+struct NeedleMatcher {
+    seen: std::sync::Arc<std::sync::Mutex<Vec<PathBuf>>>,
+}
+
+impl eg::rust::Matcher for NeedleMatcher {
+    fn find(&self, path: &std::path::Path, content: &str) -> Vec<eg::rust::SearchRange> {
+        self.seen.lock().unwrap().push(path.to_path_buf());
+        content.match_indices("needle")
+            .map(|(start, found)| eg::rust::SearchRange { byte_start: start, byte_end: start + found.len() })
+            .collect()
+    }
+}
+
+/// Test that a custom matcher replaces the regex and drives the rest of the pipeline
+#[tokio::test(flavor = "current_thread")]
+async fn test_custom_matcher() {
+    let example = "fn main() {\n    find(\"needle\");\n}\n";
+    let crate_dir = fixture_dir("custom-matcher", &[
+        ("examples/haystack.rs", example),
+        ("src/lib.rs", "// no match here\npub fn find(_: &str) {}\n"),
+    ]);
+
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let result = eg::Eg::rust_dir(&crate_dir)
+        .matcher(Box::new(NeedleMatcher { seen: seen.clone() }))
+        .context_lines(1)
+        .search()
+        .await
+        .expect("Should search the directory");
+
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort();
+    assert_eq!(seen, vec![PathBuf::from("examples/haystack.rs"), PathBuf::from("src/lib.rs")]);
+
+    assert!(result.other_matches.is_empty());
+    assert_eq!(result.example_matches.len(), 1);
+    let found = &result.example_matches[0];
+    assert_eq!(found.line_number, 2);
+    assert_eq!(&example[found.byte_start..found.byte_end], "needle");
+    assert_eq!(found.context.len(), 3, "Context lines apply to custom matches too");
+}
+
+/// Test telling plain, async, and main-less examples apart
+#[tokio::test(flavor = "current_thread")]
+async fn test_entry_signature() {
+    let crate_dir = fixture_dir("entry-signature", &[
+        ("examples/plain.rs", "fn main() {\n    demo::run();\n}\n"),
+        ("examples/server.rs", "#[tokio::main]\nasync fn main() -> Result<(), Box<dyn std::error::Error>> {\n    demo::run();\n    Ok(())\n}\n"),
+        ("examples/snippet.rs", "fn helper() {\n    demo::run();\n}\n"),
+    ]);
+
+    let result = eg::Eg::rust_dir(&crate_dir)
+        .pattern(r"demo::run")
+        .expect("Should compile regex")
+        .search()
+        .await
+        .expect("Should search the directory");
+    let signature = |file: &str| {
+        let found = result.example_matches.iter()
+            .find(|m| m.file_path == std::path::Path::new(file))
+            .expect("Every example should match");
+        found.entry_signature(&result.checkout_path).expect("Should read the example")
+    };
+
+    assert_eq!(signature("examples/plain.rs"), eg::EntrySignature {
+        has_main: true,
+        ..Default::default()
+    });
+    assert_eq!(signature("examples/server.rs"), eg::EntrySignature {
+        has_main: true,
+        is_async: true,
+        returns_result: true,
+        runtime: Some("tokio::main".to_string()),
+    });
+    assert_eq!(signature("examples/snippet.rs"), eg::EntrySignature::default());
+}
+
+/// Test searching a crate's README, keeping fenced code blocks whole in the context
+#[test]
+fn test_include_markdown() {
+    let readme = "# Demo\n\nQuick start:\n\n```rust\nlet client = Client::new();\nclient.connect();\nclient.close();\n```\n\nMore text.\n";
+    let crate_dir = fixture_dir("include-markdown", &[
+        ("README.md", readme),
+        ("docs/guide.md", "client.connect() in a nested doc"),
+        ("src/lib.rs", "pub struct Client;"),
+    ]);
+
+    let pattern = Regex::new(r"connect").unwrap();
+    let without = CrateSearcher::new()
+        .search_crate(&crate_dir, &pattern, 0)
+        .expect("Search should succeed");
+    assert!(without.other_matches.is_empty(), "Markdown is skipped by default");
+
+    let with = CrateSearcher::new()
+        .include_markdown(true)
+        .search_crate(&crate_dir, &pattern, 0)
+        .expect("Search should succeed");
+    assert_eq!(with.other_matches.len(), 1, "Only root-level markdown is searched");
+
+    let found = &with.other_matches[0];
+    assert_eq!(found.file_path, PathBuf::from("README.md"));
+    assert_eq!(found.source, eg::MatchSource::Docs);
+    assert_eq!(found.line_number, 7);
+
+    let context: Vec<_> = found.context.iter().map(|c| c.content.as_str()).collect();
+    assert_eq!(context.first(), Some(&"```rust"), "Context should start at the opening fence");
+    assert_eq!(context.last(), Some(&"```"), "Context should end at the closing fence");
+}
+
+/// Test that the build script is searched by default, tagged as such, and can be left out
+#[tokio::test(flavor = "current_thread")]
+async fn test_include_build_scripts() {
+    let crate_dir = fixture_dir("include-build-scripts", &[
+        ("build.rs", "fn main() {\n    println!(\"cargo:rerun-if-changed=schema.json\");\n    codegen::generate(\"schema.json\");\n}\n"),
+        ("src/lib.rs", "include!(concat!(env!(\"OUT_DIR\"), \"/generated.rs\"));\n// codegen::generate runs in build.rs\n"),
+        ("src/build.rs", "pub fn build() { codegen::generate(\"inline\"); }\n"),
+    ]);
+    let search = || eg::Eg::rust_dir(&crate_dir)
+        .pattern(r"codegen::generate")
+        .expect("Should compile regex");
+
+    let without = search().include_build_scripts(false).search().await.expect("Should search the directory");
+    let files: Vec<PathBuf> = without.other_matches.iter().map(|m| m.file_path.clone()).collect();
+    assert_eq!(files.len(), 2, "Only the root build.rs is skipped: {:?}", files);
+    assert!(!files.contains(&PathBuf::from("build.rs")));
+
+    let with = search().search().await.expect("Should search the directory");
+    let build: Vec<_> = with.other_matches.iter().filter(|m| m.file_path == std::path::Path::new("build.rs")).collect();
+    assert_eq!(build.len(), 1, "The build script is searched by default");
+    assert_eq!(build[0].line_number, 3);
+    assert_eq!(build[0].source, eg::MatchSource::Build);
+    assert!(
+        with.other_matches.iter().filter(|m| m.file_path != std::path::Path::new("build.rs")).all(|m| m.source == eg::MatchSource::Code),
+        "A build.rs module under src/ is ordinary code"
+    );
+}
+
+/// Test narrowing and widening the parts of a crate a search covers
+#[tokio::test(flavor = "current_thread")]
+async fn test_search_scope() {
+    let crate_dir = fixture_dir("search-scope", &[
+        ("examples/a.rs", "fn main() { needle(); }\n"),
+        ("src/lib.rs", "pub fn needle() {}\n"),
+        ("tests/it.rs", "#[test]\nfn it() { needle(); }\n"),
+        ("README.md", "Call `needle()`.\n"),
+        ("build.rs", "fn main() { /* needle */ }\n"),
+    ]);
+    let files = |scope: Option<SearchScope>| {
+        let crate_dir = crate_dir.clone();
+        async move {
+            let mut search = eg::Eg::rust_dir(&crate_dir).pattern(r"needle").expect("Should compile regex");
+            if let Some(scope) = scope {
+                search = search.scope(scope);
+            }
+            let result = search.search().await.expect("Should search the directory");
+            let mut files: Vec<PathBuf> = result.example_matches.iter()
+                .chain(&result.other_matches)
+                .map(|m| m.file_path.clone())
+                .collect();
+            files.sort();
+            files
+        }
+    };
+
+    let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+    assert_eq!(files(None).await, paths(&["build.rs", "examples/a.rs", "src/lib.rs", "tests/it.rs"]));
+    assert_eq!(files(Some(SearchScope::examples())).await, paths(&["examples/a.rs"]));
+    assert_eq!(files(Some(SearchScope::examples().with_tests())).await, paths(&["examples/a.rs", "tests/it.rs"]));
+    assert_eq!(
+        files(Some(SearchScope::all())).await,
+        paths(&["README.md", "build.rs", "examples/a.rs", "src/lib.rs", "tests/it.rs"])
+    );
+}
+
+/// Test widening the search to other extensions and to hidden files under examples/
+#[tokio::test(flavor = "current_thread")]
+async fn test_include_extensions() {
+    let crate_dir = fixture_dir("include-extensions", &[
+        ("examples/server.rs", "fn main() { demo::serve(\"server.toml\"); }\n"),
+        ("examples/server.toml", "[demo]\nport = 8080\n"),
+        ("examples/.config", "demo.port = 9090\n"),
+        ("examples/notes.txt", "demo.port is configurable\n"),
+    ]);
+    let search = || eg::Eg::rust_dir(&crate_dir)
+        .pattern(r"port|serve")
+        .expect("Should compile regex");
+    let files = |result: &eg::SearchResult| -> Vec<PathBuf> {
+        let mut files: Vec<_> = result.example_matches.iter().map(|m| m.file_path.clone()).collect();
+        files.sort();
+        files.dedup();
+        files
+    };
+
+    let default = search().search().await.expect("Should search the directory");
+    assert_eq!(files(&default), vec![PathBuf::from("examples/server.rs")]);
+
+    let with_toml = search()
+        .include_extensions(&["rs", "toml"])
+        .search()
+        .await
+        .expect("Should search the directory");
+    assert_eq!(files(&with_toml), vec![PathBuf::from("examples/server.rs"), PathBuf::from("examples/server.toml")]);
+    let toml_match = with_toml.example_matches.iter().find(|m| m.file_path == std::path::Path::new("examples/server.toml")).unwrap();
+    assert_eq!(toml_match.line_number, 2);
+    assert_eq!(toml_match.source, eg::MatchSource::Example);
+
+    let with_hidden = search()
+        .include_extensions(&["rs", "toml"])
+        .include_hidden(true)
+        .search()
+        .await
+        .expect("Should search the directory");
+    assert_eq!(files(&with_hidden), vec![
+        PathBuf::from("examples/.config"),
+        PathBuf::from("examples/server.rs"),
+        PathBuf::from("examples/server.toml"),
+    ], "Unlisted extensions stay skipped");
+}
+
+/// Test attaching the doc comment of the documented item a match falls in
+#[tokio::test(flavor = "current_thread")]
+async fn test_attach_doc_comments() {
+    let source = "/// Connects to the server.\n///\n/// Retries once on timeout.\npub fn connect() {\n    retry(dial);\n}\n\npub struct Client;\n\nimpl Client {\n    /// Sends a request.\n    pub fn send(&self) {\n        retry(write);\n    }\n\n    pub fn close(&self) {\n        retry(shutdown);\n    }\n}\n";
+    let crate_dir = fixture_dir("attach-doc-comments", &[("src/lib.rs", source)]);
+    let search = || eg::Eg::rust_dir(&crate_dir)
+        .pattern(r"retry\(")
+        .expect("Should compile regex");
+
+    let result = search().attach_doc_comments(true).search().await.expect("Should search the directory");
+    let docs: Vec<(u32, Option<&str>)> = result.other_matches.iter()
+        .map(|m| (m.line_number, m.doc_comment.as_deref()))
+        .collect();
+    assert_eq!(docs, vec![
+        (5, Some("Connects to the server.\n\nRetries once on timeout.")),
+        (13, Some("Sends a request.")),
+        (17, None),
+    ], "An undocumented method inside an undocumented impl has no doc comment");
+
+    let plain = search().search().await.expect("Should search the directory");
+    assert!(plain.other_matches.iter().all(|m| m.doc_comment.is_none()), "Off by default");
+}
+
+/// Test attaching the enclosing test function, with its assertions, to matches in tests
+#[tokio::test(flavor = "current_thread")]
+async fn test_include_test_context() {
+    let source = "use demo::parse;\n\nfn helper() -> u32 {\n    parse(\"7\")\n}\n\n#[test]\nfn parses_numbers() {\n    let value = parse(\"42\");\n    assert_eq!(value, 42);\n    assert!(\n        value > 0,\n    );\n}\n";
+    let crate_dir = fixture_dir("include-test-context", &[("tests/parse.rs", source)]);
+    let search = || eg::Eg::rust_dir(&crate_dir)
+        .pattern(r"parse\(")
+        .expect("Should compile regex");
+
+    let result = search().include_test_context(true).search().await.expect("Should search the directory");
+    assert_eq!(result.other_matches.len(), 2);
+    assert!(result.other_matches[0].test_context.is_none(), "A helper outside any test has no test context");
+
+    let test = result.other_matches[1].test_context.as_ref().expect("Should attach the enclosing test");
+    assert_eq!(test.name, "parses_numbers");
+    let line_numbers: Vec<u32> = test.lines.iter().map(|line| line.line_number).collect();
+    assert_eq!(line_numbers, (7..=14).collect::<Vec<_>>(), "The whole function, #[test] included");
+    assert_eq!(test.assertion_lines, vec![10, 11]);
+    let matched: Vec<u32> = test.lines.iter().filter(|line| line.is_match).map(|line| line.line_number).collect();
+    assert_eq!(matched, vec![9]);
+
+    let plain = search().search().await.expect("Should search the directory");
+    assert!(plain.other_matches.iter().all(|m| m.test_context.is_none()), "Off by default");
+}
+
+/// Test that the best example is a beginner-friendly file that actually uses the crate
+#[test]
+fn test_best_example() {
+    let basic = format!("use demo::Client;\n\nfn main() {{\n{}}}\n", "    Client::new().send();\n".repeat(25));
+    let stress = format!("fn main() {{\n{}}}\n", "    demo::Client::new().send();\n".repeat(600));
+    let crate_dir = fixture_dir("best-example", &[
+        ("examples/basic.rs", basic.as_str()),
+        ("examples/stress_test.rs", stress.as_str()),
+        ("examples/helpers/mod.rs", "pub fn helper() {}"),
+        ("src/lib.rs", "pub struct Client;"),
+    ]);
+
+    let best = CrateSearcher::new()
+        .best_example(&crate_dir, "demo")
+        .expect("Should score examples")
+        .expect("Should pick an example");
+
+    assert_eq!(best.file_path, PathBuf::from("examples/basic.rs"));
+    assert_eq!(best.content, basic, "Should return the whole file");
+}
+
+/// Test that a directory's best example is scored against its package name, not the directory's
+#[tokio::test(flavor = "current_thread")]
+async fn test_best_example_uses_package_name() {
+    let crate_dir = fixture_dir("best-example-package-name", &[
+        ("Cargo.toml", "[package]\nname = \"demo-client\"\nversion = \"0.1.0\"\nedition = \"2021\"\n"),
+        ("src/lib.rs", "pub fn run() {}\n"),
+        ("examples/a.rs", "fn main() { other::run(); }\n"),
+        ("examples/b.rs", "fn main() { demo_client::run(); }\n"),
+    ]);
+
+    let best = eg::Eg::rust_dir(&crate_dir)
+        .best_example()
+        .await
+        .expect("Should score examples")
+        .expect("Should pick an example");
+
+    assert_eq!(best.file_path, PathBuf::from("examples/b.rs"), "The example using the crate should win");
+}
+
+/// Test that a near-duplicate of a pasted snippet wins over unrelated examples
+#[tokio::test(flavor = "current_thread")]
+async fn test_most_similar() {
+    let crate_dir = fixture_dir("most-similar", &[
+        ("examples/server.rs", "use demo::Server;\n\nfn main() {\n    let server = Server::bind(\"0.0.0.0:8080\");\n    server.serve(handler);\n}\n"),
+        ("examples/client.rs", "use demo::Client;\n\nfn main() {\n    let client = Client::new();\n    let body = client.get(\"http://localhost:8080/\").send();\n    println!(\"{body}\");\n}\n"),
+        ("examples/config.rs", "use demo::Config;\n\nfn main() {\n    let config = Config::from_env();\n    dbg!(config);\n}\n"),
+    ]);
+
+    // The client example, reformatted and with a different URL and a comment
+    let snippet = "let client = Client::new(); // connect\nlet body = client.get(url).send();\nprintln!(\"{body}\");";
+    let example = eg::Eg::rust_dir(&crate_dir)
+        .most_similar(snippet)
+        .await
+        .expect("Should score the examples")
+        .expect("Should find a similar example");
+    assert_eq!(example.file_path, PathBuf::from("examples/client.rs"));
+    assert!(example.content.contains("Client::new()"), "Should return the whole file");
+
+    let unrelated = eg::Eg::rust_dir(&crate_dir)
+        .most_similar("%%%")
+        .await
+        .expect("Should score the examples");
+    assert!(unrelated.is_none(), "A snippet sharing no words matches nothing");
+}
+
+/// Test combining patterns: AllInFile drops files that only contain one of them
+#[test]
+fn test_multiple_patterns() {
+    let crate_dir = fixture_dir("multiple-patterns", &[
+        ("examples/both.rs", "let listener = TcpListener::bind(addr);\nlet stream = listener.accept();\n"),
+        ("examples/bind_only.rs", "let listener = TcpListener::bind(addr);\n"),
+        ("examples/same_line.rs", "TcpListener::bind(addr).accept();\n"),
+    ]);
+
+    let bind = Regex::new(r"bind").unwrap();
+    let accept = Regex::new(r"accept").unwrap();
+    let files = |mode| {
+        let matches = CrateSearcher::new()
+            .search_crate_patterns(&crate_dir, &[&bind, &accept], mode, 0)
+            .expect("Search should succeed");
+        let mut files: Vec<_> = matches.example_matches.iter().map(|m| m.file_path.clone()).collect();
+        files.sort();
+        files.dedup();
+        files
+    };
+
+    assert_eq!(files(MatchMode::AnyInFile), vec![
+        PathBuf::from("examples/bind_only.rs"),
+        PathBuf::from("examples/both.rs"),
+        PathBuf::from("examples/same_line.rs"),
+    ]);
+    assert_eq!(files(MatchMode::AllInFile), vec![
+        PathBuf::from("examples/both.rs"),
+        PathBuf::from("examples/same_line.rs"),
+    ]);
+    assert_eq!(files(MatchMode::AllOnLine), vec![PathBuf::from("examples/same_line.rs")]);
+}
+
+/// Test that only the top-level examples/ directory holds examples, not a module named examples
+#[test]
+fn test_nested_examples_module() {
+    let crate_dir = fixture_dir("nested-examples-module", &[
+        ("examples/demo.rs", "fn main() { connect(); }"),
+        ("src/examples/mod.rs", "pub fn connect() {}"),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new("connect").unwrap(), 0)
+        .expect("Search should succeed");
+    assert_eq!(matches.example_matches.len(), 1);
+    assert_eq!(matches.example_matches[0].file_path, PathBuf::from("examples/demo.rs"));
+    assert_eq!(matches.other_matches.len(), 1);
+    assert_eq!(matches.other_matches[0].file_path, PathBuf::from("src/examples/mod.rs"));
+
+    let files = CrateSearcher::new().example_files(&crate_dir).expect("Should list examples");
+    assert_eq!(files, vec![PathBuf::from("examples/demo.rs")]);
+}
+
+/// Test that stats show a crate was scanned even when it ships no examples
+#[test]
+fn test_extraction_stats_without_examples() {
+    let files = [
+        ("Cargo.toml", "[package]\nname = \"demo\"\n"),
+        ("src/lib.rs", "pub mod client;\n"),
+        ("src/client.rs", "pub struct Client;\n"),
+    ];
+    let crate_dir = fixture_dir("extraction-stats", &files);
+
+    let pattern = Regex::new("no_such_function").unwrap();
+    let matches = CrateSearcher::new()
+        .search_crate(&crate_dir, &pattern, 2)
+        .expect("Should scan the crate");
+    let stats = matches.stats;
+
+    assert!(matches.example_matches.is_empty() && matches.other_matches.is_empty());
+    assert_eq!(stats.files_seen, 3, "Files that weren't searched still count as seen");
+    assert_eq!(stats.example_files, 0, "Crate has no examples/ directory");
+    let rust_bytes = files.iter()
+        .filter(|(path, _)| path.ends_with(".rs"))
+        .map(|(_, content)| content.len() as u64)
+        .sum::<u64>();
+    assert_eq!(stats.bytes_read, rust_bytes, "Only the searched files are read");
+}
+
+/// Test that code_only drops matches in comments and literals but keeps real calls
+#[test]
+fn test_code_only() {
+    let source = "// spawn(old_task);\n/* spawn(a);\n   spawn(b); */\nfn main() {\n    let label = \"spawn\";\n    let c = '\\'';\n    spawn(task);\n}\n";
+    let crate_dir = fixture_dir("code-only", &[
+        ("src/lib.rs", source),
+    ]);
+
+    let pattern = Regex::new(r"spawn").unwrap();
+    let all = CrateSearcher::new()
+        .search_crate(&crate_dir, &pattern, 0)
+        .expect("Search should succeed");
+    assert_eq!(all.other_matches.len(), 5);
+
+    let code = CrateSearcher::new()
+        .code_only(true)
+        .search_crate(&crate_dir, &pattern, 0)
+        .expect("Search should succeed");
+    let lines: Vec<_> = code.other_matches.iter().map(|m| m.line_number).collect();
+    assert_eq!(lines, vec![7], "Only the real call should remain");
+}
+
+/// Test searching an arbitrary directory tree with no registry or version involved
+#[tokio::test(flavor = "current_thread")]
+async fn test_rust_dir() {
+    let root = fixture_dir("rust-dir", &[
+        ("examples/echo.rs", "fn main() { serve_echo(); }"),
+        ("src/lib.rs", "pub fn serve_echo() {}"),
+        ("target/debug/build.rs", "fn serve_echo() {}"),
+    ]);
+
+    let result = eg::Eg::rust_dir(&root)
+        .pattern(r"serve_echo")
+        .expect("Should compile regex")
+        .search()
+        .await
+        .expect("Should search the directory");
+
+    assert_eq!(result.version, "");
+    assert_eq!(result.checkout_path, root);
+    assert_eq!(result.example_matches.len(), 1);
+    assert_eq!(result.example_matches[0].file_path, PathBuf::from("examples/echo.rs"));
+    assert_eq!(result.other_matches.len(), 1, "target/ should be skipped like in a crate");
+}
+
+/// Test that code gated out on the host is dropped from the snippet, keeping original line numbers
+#[test]
+fn test_simplify_snippets() {
+    let crate_dir = fixture_dir("simplify-snippets", &[
+        ("examples/sep.rs", concat!(
+            "fn main() {\n",
+            "    #[cfg(target_os = \"no-such-os\")]\n",
+            "    let sep = {\n",
+            "        ';'\n",
+            "    };\n",
+            "    #[cfg(not(target_os = \"no-such-os\"))]\n",
+            "    let sep = ':';\n",
+            "    print_separator(sep);\n",
+            "}\n",
+            "\n",
+            "#[cfg(all(feature = \"extra\", target_os = \"no-such-os\"))]\n",
+            "fn print_separator(sep: char) { println!(\"{}\", sep) }\n",
+        )),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .simplify_snippets(true)
+        .search_crate(&crate_dir, &Regex::new(r"print_separator\(sep\)").unwrap(), 10)
+        .expect("Search should succeed");
+    let found = &matches.example_matches[0];
+
+    let line_numbers: Vec<_> = found.context.iter().map(|c| c.line_number).collect();
+    assert_eq!(line_numbers, vec![1, 6, 7, 8, 9, 10], "Gated lines 2-5 and 11-12 should be dropped");
+    assert!(found.context.iter().all(|c| !c.content.contains("';'")));
+
+    let plain = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new(r"print_separator\(sep\)").unwrap(), 10)
+        .expect("Search should succeed");
+    assert_eq!(plain.example_matches[0].context.len(), 12, "Without simplification every line is kept");
+}
+
+/// Test that simplification also drops gated lines from an attached test function
+#[test]
+fn test_simplify_test_context() {
+    let crate_dir = fixture_dir("simplify-test-context", &[
+        ("tests/sep.rs", concat!(
+            "#[test]\n",
+            "fn separator() {\n",
+            "    #[cfg(target_os = \"no-such-os\")]\n",
+            "    assert_eq!(separator(), ';');\n",
+            "    let sep = separator();\n",
+            "    assert_eq!(sep, ':');\n",
+            "}\n",
+        )),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .simplify_snippets(true)
+        .test_context(true)
+        .search_crate(&crate_dir, &Regex::new(r"let sep").unwrap(), 10)
+        .expect("Search should succeed");
+    let test = matches.other_matches[0].test_context.as_ref().expect("Should attach the enclosing test");
+
+    let line_numbers: Vec<_> = test.lines.iter().map(|line| line.line_number).collect();
+    assert_eq!(line_numbers, vec![1, 2, 5, 6, 7], "Gated lines 3-4 should be dropped");
+    assert_eq!(test.assertion_lines, vec![6], "A dropped assertion isn't listed");
+}
+
+/// Test that reading stops once the total byte budget is spent, keeping the partial result
+#[test]
+fn test_max_total_bytes() {
+    let content = "fn main() { budget_marker(); }\n";
+    let names: Vec<String> = (0..5).map(|i| format!("examples/ex{}.rs", i)).collect();
+    let files: Vec<(&str, &str)> = names.iter().map(|name| (name.as_str(), content)).collect();
+    let crate_dir = fixture_dir("max-total-bytes", &files);
+
+    // A third file would go over budget, so only two are read
+    let file_len = content.len() as u64;
+    let matches = CrateSearcher::new()
+        .max_total_bytes(Some(2 * file_len + file_len / 2))
+        .search_crate(&crate_dir, &Regex::new("budget_marker").unwrap(), 0)
+        .expect("Search should succeed");
+
+    assert!(matches.truncated);
+    assert_eq!(matches.example_matches.len(), 2);
+    assert_eq!(matches.stats.bytes_read, 2 * file_len, "Should never read past the budget");
+
+    let unlimited = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new("budget_marker").unwrap(), 0)
+        .expect("Search should succeed");
+    assert!(!unlimited.truncated);
+    assert_eq!(unlimited.example_matches.len(), 5);
+}
+
+/// Test that only the first occurrence in each example file is reported
+#[test]
+fn test_first_match_only() {
+    let crate_dir = fixture_dir("first-match-only", &[
+        ("examples/spawn.rs", "fn main() {\n    spawn(a);\n    spawn(b);\n    spawn(c);\n}\n"),
+        ("src/lib.rs", "pub fn spawn() {}\npub fn respawn() { spawn() }\n"),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .first_match_only(true)
+        .search_crate(&crate_dir, &Regex::new(r"spawn\(").unwrap(), 0)
+        .expect("Search should succeed");
+
+    assert_eq!(matches.example_matches.len(), 1);
+    assert_eq!(matches.example_matches[0].line_number, 2);
+    assert!(matches.truncated_files.is_empty());
+    assert_eq!(matches.other_matches.len(), 2, "Non-example files are unaffected");
+}
+
+/// Test listing example files with their sizes
+#[test]
+fn test_example_listing() {
+    let crate_dir = fixture_dir("example-listing", &[
+        ("examples/zeta.rs", "fn main() {}\n"),
+        ("examples/alpha/main.rs", "fn main() { alpha(); }\n"),
+        ("src/lib.rs", "pub fn alpha() {}\n"),
+    ]);
+
+    let listing = CrateSearcher::new()
+        .example_listing(&crate_dir)
+        .expect("Listing should succeed");
+
+    assert_eq!(listing, vec![
+        (PathBuf::from("examples/alpha/main.rs"), "fn main() { alpha(); }\n".len() as u64),
+        (PathBuf::from("examples/zeta.rs"), "fn main() {}\n".len() as u64),
+    ]);
+}
+
+/// Test that a multi-file example is stitched into one self-contained snippet
+#[test]
+fn test_resolved_snippet() {
+    let crate_dir = fixture_dir("resolved-snippet", &[
+        ("examples/chat/main.rs", "mod protocol;\n\nfn main() {\n    protocol::greet();\n}\n"),
+        ("examples/chat/protocol.rs", "pub fn greet() {\n    println!(\"hi\");\n}\n"),
+    ]);
+
+    let matches = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new(r"protocol::greet").unwrap(), 0)
+        .expect("Search should succeed");
+    let snippet = matches.example_matches[0]
+        .resolved_snippet(&crate_dir)
+        .expect("Should resolve the snippet");
+
+    assert_eq!(
+        snippet,
+        "mod protocol {\npub fn greet() {\n    println!(\"hi\");\n}\n}\n\nfn main() {\n    protocol::greet();\n}\n"
+    );
+}
+
+/// Test that `include!`s reaching outside the crate root are left as written
+#[test]
+fn test_resolved_snippet_stays_in_crate() {
+    let outside = fixture_dir("resolved-snippet-outside", &[("secret.txt", "let token = \"hunter2\";\n")]);
+    let crate_dir = fixture_dir("resolved-snippet-escape", &[]);
+    let escape = format!("../../{}/secret.txt", outside.file_name().unwrap().to_string_lossy());
+    let source = format!("fn main() {{\n    include!(\"{}\");\n}}\n", escape);
+    fs::create_dir_all(crate_dir.join("examples")).expect("Should create examples dir");
+    fs::write(crate_dir.join("examples/leak.rs"), &source).expect("Should write example");
+    assert!(crate_dir.join("examples").join(&escape).exists(), "The include should point at a real file");
+
+    let matches = CrateSearcher::new()
+        .search_crate(&crate_dir, &Regex::new(r"include!").unwrap(), 0)
+        .expect("Search should succeed");
+    let snippet = matches.example_matches[0]
+        .resolved_snippet(&crate_dir)
+        .expect("Should resolve the snippet");
+
+    assert_eq!(snippet, source);
+}
+
+/// Test that the marker cargo writes into its own extractions isn't listed as a packaged file
+#[test]
+fn test_file_list_skips_cargo_marker() {
+    let crate_dir = fixture_dir("file-list-cargo-ok", &[
+        (".cargo-ok", "{\"v\":1}"),
+        (".cargo_vcs_info.json", "{}"),
+        ("src/lib.rs", "pub fn demo() {}\n"),
+    ]);
+
+    let files: Vec<PathBuf> = CrateSearcher::new()
+        .file_list(&crate_dir)
+        .expect("Listing should succeed")
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+
+    assert_eq!(files, vec![PathBuf::from(".cargo_vcs_info.json"), PathBuf::from("src/lib.rs")]);
+}
+
+/// Test that a match just below an item, e.g. in a trailing comment, gets that item's doc comment
+#[tokio::test(flavor = "current_thread")]
+async fn test_doc_comment_below_item() {
+    let source = "/// Connects to the server.\npub fn connect() {}\n// retry(connect) after a timeout\n\npub struct Plain;\n// retry(plain) is never needed\n\n/// A client.\npub struct Client;\n\n/// Client methods.\nimpl Client {\n    /// Sends a request.\n    pub fn send(&self) {}\n    // retry(send) is safe\n\n    pub fn close(&self) {}\n    // retry(close) is not\n}\n";
+    let crate_dir = fixture_dir("doc-comment-below-item", &[("src/lib.rs", source)]);
+
+    let result = eg::Eg::rust_dir(&crate_dir)
+        .pattern(r"retry\(")
+        .expect("Should compile regex")
+        .attach_doc_comments(true)
+        .search()
+        .await
+        .expect("Should search the directory");
+    let docs: Vec<(u32, Option<&str>)> = result.other_matches.iter()
+        .map(|m| (m.line_number, m.doc_comment.as_deref()))
+        .collect();
+    assert_eq!(docs, vec![
+        (3, Some("Connects to the server.")),
+        (6, None),
+        (15, Some("Sends a request.")),
+        (18, Some("Client methods.")),
+    ], "The item just above wins; an undocumented one falls back to the enclosing item's doc comment");
+}
+
+/// Test that a manifest cargo can't read doesn't stop the search itself
+#[tokio::test(flavor = "current_thread")]
+async fn test_search_unreadable_manifest() {
+    // The package sits under a workspace root that doesn't list it, so `cargo metadata` refuses it
+    let dir = fixture_dir("unreadable-manifest", &[
+        ("Cargo.toml", "[workspace]\nmembers = []\n"),
+        ("demo-broken/Cargo.toml", "[package]\nname = \"demo-broken\"\nversion = \"0.1.0\"\nedition = \"2021\"\n"),
+        ("demo-broken/examples/hello.rs", "fn main() { demo_broken::hello(); }\n"),
+    ]);
+
+    let result = eg::Eg::rust_dir(&dir.join("demo-broken"))
+        .pattern(r"hello\(")
+        .expect("Should compile regex")
+        .search()
+        .await
+        .expect("Should search despite the unreadable manifest");
+    assert_eq!(result.example_matches.len(), 1);
+    assert_eq!(result.example_matches[0].file_path, PathBuf::from("examples/hello.rs"));
+}
@@ -0,0 +1,72 @@
+//! Tests for the tracing spans emitted by the search pipeline
+
+mod common;
+
+use common::{crate_archive, fixture_dir};
+use eg::rust::{CrateExtractor, CrateSearcher, VersionResolver};
+use regex::Regex;
+use std::sync::{Arc, Mutex};
+use tracing::span;
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+/// Layer that records the name of every span created
+struct SpanNames(Arc<Mutex<Vec<String>>>);
+
+impl<S: Subscriber> Layer<S> for SpanNames {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, _id: &span::Id, _ctx: Context<'_, S>) {
+        self.0.lock().unwrap().push(attrs.metadata().name().to_string());
+    }
+}
+
+/// Test that each pipeline phase opens its own span
+#[tokio::test(flavor = "current_thread")]
+async fn test_pipeline_spans() {
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let subscriber = tracing_subscriber::registry().with(SpanNames(names.clone()));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    // Use 'regex' since it's in our Cargo.toml, so resolution stays local
+    VersionResolver::new()
+        .resolve_version("regex", None)
+        .await
+        .expect("Should resolve regex version");
+
+    let dir = fixture_dir("pipeline-spans", &[]);
+    let crate_file = dir.join("demo-0.1.0.crate");
+    std::fs::write(&crate_file, crate_archive("demo", "0.1.0", &[
+        ("examples/hello.rs", "fn main() { println!(\"hello\"); }"),
+    ])).unwrap();
+
+    let extraction_path = dir.join("demo-0.1.0");
+    CrateExtractor::new()
+        .extract_crate_to_cache(&crate_file, &extraction_path)
+        .await
+        .expect("Should extract crate");
+
+    CrateSearcher::new()
+        .search_crate(&extraction_path, &Regex::new("hello").unwrap(), 2)
+        .expect("Should search crate");
+
+    let names = names.lock().unwrap();
+    for expected in ["resolve_version", "extract_crate_to_cache", "extract_from_reader", "search_crate"] {
+        assert!(names.iter().any(|n| n == expected), "Missing span '{}', got: {:?}", expected, names);
+    }
+}
+
+/// Test that resolving the package name opens its own span, also inside `resolve_version`
+#[tokio::test(flavor = "current_thread")]
+async fn test_resolve_crate_span() {
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let subscriber = tracing_subscriber::registry().with(SpanNames(names.clone()));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    // Use 'regex' since it's in our Cargo.toml, so resolution stays local
+    let resolver = VersionResolver::new();
+    resolver.resolve_crate("regex", None).await.expect("Should resolve regex");
+    assert_eq!(*names.lock().unwrap(), vec!["resolve_crate"]);
+
+    names.lock().unwrap().clear();
+    resolver.resolve_version("regex", None).await.expect("Should resolve regex version");
+    assert_eq!(*names.lock().unwrap(), vec!["resolve_version", "resolve_crate"]);
+}
@@ -0,0 +1,92 @@
+//! Tests that depend on the process's working directory
+//!
+//! Changing directory affects every thread, so these live in their own test binary and take
+//! `CWD_LOCK` while they run, through `CwdGuard`.
+
+mod common;
+
+use common::fixture_dir;
+use eg::Eg;
+use std::path::{Path, PathBuf};
+use tokio::sync::{Mutex, MutexGuard};
+
+static CWD_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Holds `CWD_LOCK` and puts the previous working directory back when dropped, even if the test panics
+struct CwdGuard {
+    previous: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl CwdGuard {
+    async fn enter(dir: &Path) -> Self {
+        let lock = CWD_LOCK.lock().await;
+        let previous = std::env::current_dir().expect("Should read the working directory");
+        std::env::set_current_dir(dir).expect("Should enter the directory");
+        CwdGuard { previous, _lock: lock }
+    }
+}
+
+impl Drop for CwdGuard {
+    fn drop(&mut self) {
+        // Runs before `_lock` is released, so no other test sees the fixture directory
+        let _ = std::env::set_current_dir(&self.previous);
+    }
+}
+
+/// Run `f` with `dir` as the working directory, restoring the previous one afterwards
+async fn in_dir<T>(dir: &Path, f: impl std::future::Future<Output = T>) -> T {
+    let _guard = CwdGuard::enter(dir).await;
+    f.await
+}
+
+/// Test that resolution without a project falls through to the latest version instead of failing
+#[tokio::test(flavor = "current_thread")]
+async fn test_resolve_without_project() {
+    let dir = fixture_dir("no-project", &[]);
+    assert!(
+        !dir.ancestors().any(|d| d.join("Cargo.toml").is_file()),
+        "The temp dir must not be inside a Cargo project"
+    );
+
+    let (resolved, latest) = in_dir(&dir, async {
+        let resolved = Eg::rust_crate("regex")
+            .resolve_only()
+            .await
+            .expect("Should fall back to the latest version");
+        let latest = Eg::rust_crate("regex")
+            .version("latest")
+            .resolve_only()
+            .await
+            .expect("Should resolve the latest version");
+        (resolved, latest)
+    }).await;
+
+    assert_eq!(resolved, latest);
+    println!("✅ Outside a project, regex resolved to latest {}", resolved);
+}
+
+/// Test that the crate being developed is searched in place, examples included
+#[tokio::test(flavor = "current_thread")]
+async fn test_current_crate() {
+    let dir = fixture_dir("current-crate", &[
+        ("Cargo.toml", "[package]\nname = \"self-inspect\"\nversion = \"0.3.0\"\nedition = \"2021\"\n"),
+        ("src/lib.rs", "pub fn inspect_me() {}\n"),
+        ("examples/inspect.rs", "fn main() { self_inspect::inspect_me(); }\n"),
+    ]);
+
+    let result = in_dir(&dir, async {
+        Eg::current_crate()
+            .pattern(r"inspect_me")
+            .expect("Should compile regex")
+            .search()
+            .await
+            .expect("Should search the current crate")
+    }).await;
+
+    assert_eq!(result.crate_name, "self-inspect");
+    assert_eq!(result.version, "0.3.0");
+    assert_eq!(result.checkout_path, dir);
+    assert_eq!(result.example_matches.len(), 1);
+    assert_eq!(result.example_matches[0].file_path, Path::new("examples/inspect.rs"));
+}